@@ -17,12 +17,14 @@
 
 use clap::{Arg, ArgAction, ArgMatches, Command};
 use console::style;
-use crowbook::Book;
+use crowbook::{Book, BookOptions};
 use rust_i18n::t;
+use walkdir::WalkDir;
 
 use std::env;
 use std::fs;
 use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::process::exit;
 
 static BIRD: &str = "🐦 ";
@@ -159,6 +161,144 @@ pub fn create_book(matches: &ArgMatches) -> ! {
     }
 }
 
+/// Options that are listed (commented out) in the book file generated by --init
+const INIT_OPTIONS: &[&str] = &[
+    "subtitle",
+    "license",
+    "version",
+    "date",
+    "cover",
+    "output.epub",
+    "output.html",
+    "output.html.dir",
+    "output.pdf",
+    "rendering.num_depth",
+    "rendering.inline_toc",
+    "rendering.initials",
+    "html.icon",
+    "epub.version",
+    "tex.class",
+    "resources.files",
+];
+
+/// Writes a file created by --init
+fn write_init_file(path: &Path, content: &str) -> Result<(), String> {
+    if fs::metadata(path).is_ok() {
+        return Err(t!("error.create", file = path.display()).into_owned());
+    }
+    fs::write(path, content)
+        .map_err(|err| t!("error.init", file = path.display(), error = err).into_owned())
+}
+
+/// Creates a directory for --init
+fn create_init_dir(path: &Path) -> Result<(), String> {
+    fs::create_dir_all(path)
+        .map_err(|err| t!("error.init", file = path.display(), error = err).into_owned())
+}
+
+/// Returns true for Markdown files that are about a project rather than
+/// chapters of a book, e.g. `README.md` or `LICENSE.md`
+fn is_project_file(path: &Path) -> bool {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_uppercase())
+        .unwrap_or_default();
+    [
+        "README",
+        "CHANGELOG",
+        "CHANGES",
+        "HISTORY",
+        "LICENSE",
+        "LICENCE",
+        "COPYING",
+        "CONTRIBUTING",
+        "CODE_OF_CONDUCT",
+        "AUTHORS",
+    ]
+    .iter()
+    .any(|name| stem == *name || stem.starts_with(&format!("{name}-")))
+}
+
+/// Lists the Markdown files of a directory as chapters, for --init --from-dir
+fn list_chapters(dir: &Path) -> Vec<String> {
+    let mut chapters = vec![];
+    for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if entry.file_type().is_file()
+            && path.extension().map(|e| e == "md").unwrap_or(false)
+            && !is_project_file(path)
+        {
+            let path = path.strip_prefix(dir).unwrap();
+            chapters.push(format!("+ {}", path.display()).replace('\\', "/"));
+        }
+    }
+    chapters.sort_by(|a, b| crowbook::natural_cmp(a, b));
+    chapters
+}
+
+/// Creates a project skeleton in `dir`, and returns the path of the book file
+fn create_project(dir: &Path, from_dir: bool, with_templates: bool) -> Result<PathBuf, String> {
+    let book_file = dir.join("book.book");
+    if fs::metadata(&book_file).is_ok() {
+        return Err(t!("error.create", file = book_file.display()).into_owned());
+    }
+
+    let mut chapters = vec![];
+    if from_dir {
+        chapters = list_chapters(dir);
+    } else {
+        // Only scaffold directories when there is no existing content to list
+        for subdir in &["chapters", "images"] {
+            create_init_dir(&dir.join(subdir))?;
+        }
+        write_init_file(&dir.join("chapters/preface.md"), &t!("msg.init_preface"))?;
+        write_init_file(&dir.join("chapters/chapter_01.md"), &t!("msg.init_chapter"))?;
+        chapters.push(String::from("- chapters/preface.md"));
+        chapters.push(String::from("+ chapters/chapter_01.md"));
+    }
+
+    let mut content = String::from(t!("msg.init_book"));
+    content.push('\n');
+    content.push_str(&BookOptions::sample(INIT_OPTIONS));
+
+    if with_templates {
+        let book = Book::new();
+        create_init_dir(&dir.join("templates"))?;
+        content.push_str(&t!("msg.init_templates"));
+        for template in BookOptions::templates() {
+            // ok to unwrap since all template options have a default content
+            let tpl = book.get_template(template).unwrap();
+            write_init_file(&dir.join("templates").join(template), &tpl)?;
+            content.push_str(&format!("{template}: templates/{template}\n"));
+        }
+    }
+
+    content.push_str(&t!("msg.chapter_list"));
+    for chapter in chapters {
+        content.push_str(&chapter);
+        content.push('\n');
+    }
+    write_init_file(&book_file, &content)?;
+    Ok(book_file)
+}
+
+/// Creates a project skeleton with --init and exits
+pub fn init_project(matches: &ArgMatches) -> ! {
+    // ok to unwrap since clap sets a default value
+    let dir = Path::new(matches.get_one::<String>("init").unwrap());
+    match create_project(
+        dir,
+        matches.get_flag("from-dir"),
+        matches.get_flag("with-templates"),
+    ) {
+        Ok(book_file) => {
+            println!("{}", t!("msg.created", file = book_file.display()));
+            exit(0);
+        }
+        Err(msg) => print_error_and_exit(&msg, false),
+    }
+}
+
 pub fn create_matches() -> ArgMatches {
     app().get_matches()
 }
@@ -183,6 +323,16 @@ fn app() -> clap::Command {
         static ref PRINT_TEMPLATE: String = t!("cmd.template").into_owned();
         static ref BOOK: String = t!("cmd.book").into_owned();
         static ref STATS: String = t!("cmd.stats").into_owned();
+        static ref PROFILE: String = t!("cmd.profile").into_owned();
+        static ref ALL_PROFILES: String = t!("cmd.all_profiles").into_owned();
+        static ref CHECK: String = t!("cmd.check").into_owned();
+        static ref CHECK_FORMAT: String = t!("cmd.check_format").into_owned();
+        static ref MIGRATE: String = t!("cmd.migrate").into_owned();
+        static ref INIT: String = t!("cmd.init").into_owned();
+        static ref FROM_DIR: String = t!("cmd.from_dir").into_owned();
+        static ref WITH_TEMPLATES: String = t!("cmd.with_templates").into_owned();
+        static ref STDOUT: String = t!("cmd.stdout").into_owned();
+        static ref EXPORT_THEME: String = t!("cmd.export_theme").into_owned();
         static ref TEMPLATE: String = t!("clap.template").into_owned();
     }
 
@@ -263,6 +413,7 @@ fn app() -> clap::Command {
                     "tex",
                     "odt",
                     "html.dir",
+                    "json",
                 ])
                 .help(TO.as_str()),
         )
@@ -309,6 +460,85 @@ fn app() -> clap::Command {
                 .action(ArgAction::SetTrue)
                 .help(STATS.as_str()),
         )
+        .arg(
+            Arg::new("profile")
+                .short('p')
+                .long("profile")
+                .action(ArgAction::Set)
+                .num_args(1)
+                .help(PROFILE.as_str()),
+        )
+        .arg(
+            Arg::new("all-profiles")
+                .long("all-profiles")
+                .action(ArgAction::SetTrue)
+                .help(ALL_PROFILES.as_str())
+                .conflicts_with_all(["profile", "to"]),
+        )
+        .arg(
+            Arg::new("check")
+                .long("check")
+                .action(ArgAction::SetTrue)
+                .help(CHECK.as_str())
+                .conflicts_with_all(["stats", "to", "all-profiles"]),
+        )
+        .arg(
+            Arg::new("check-format")
+                .long("check-format")
+                .action(ArgAction::Set)
+                .num_args(1)
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .requires("check")
+                .help(CHECK_FORMAT.as_str()),
+        )
+        .arg(
+            Arg::new("migrate")
+                .long("migrate")
+                .action(ArgAction::SetTrue)
+                .help(MIGRATE.as_str())
+                .conflicts_with_all(["check", "stats", "to", "all-profiles", "single"]),
+        )
+        .arg(
+            Arg::new("init")
+                .long("init")
+                .action(ArgAction::Set)
+                .num_args(0..=1)
+                .value_name("DIR")
+                .default_missing_value(".")
+                .help(INIT.as_str())
+                .conflicts_with_all(["BOOK", "files", "check", "migrate"]),
+        )
+        .arg(
+            Arg::new("from-dir")
+                .long("from-dir")
+                .action(ArgAction::SetTrue)
+                .requires("init")
+                .help(FROM_DIR.as_str()),
+        )
+        .arg(
+            Arg::new("with-templates")
+                .long("with-templates")
+                .action(ArgAction::SetTrue)
+                .requires("init")
+                .help(WITH_TEMPLATES.as_str()),
+        )
+        .arg(
+            Arg::new("stdout")
+                .long("stdout")
+                .action(ArgAction::SetTrue)
+                .requires("migrate")
+                .help(STDOUT.as_str()),
+        )
+        .arg(
+            Arg::new("export-theme")
+                .long("export-theme")
+                .action(ArgAction::Set)
+                .num_args(1)
+                .value_name("DIR")
+                .help(EXPORT_THEME.as_str())
+                .conflicts_with_all(["files", "check", "migrate", "init", "to", "stats"]),
+        )
         .arg(
             Arg::new("BOOK")
                 .index(1)
@@ -322,10 +552,47 @@ fn app() -> clap::Command {
 
 #[cfg(test)]
 mod tests {
-    use super::app;
+    use super::{app, create_project};
+
+    use std::fs;
 
     #[test]
     fn verify_app() {
         app().debug_assert();
     }
+
+    #[test]
+    fn init_scaffold() {
+        let dir = tempfile::tempdir().unwrap();
+        let book_file = create_project(dir.path(), false, false).unwrap();
+        let content = fs::read_to_string(book_file).unwrap();
+        assert!(content.contains("- chapters/preface.md\n+ chapters/chapter_01.md\n"));
+        assert!(dir.path().join("chapters/chapter_01.md").is_file());
+        assert!(dir.path().join("images").is_dir());
+        // Existing files are not overwritten
+        assert!(create_project(dir.path(), false, false).is_err());
+    }
+
+    #[test]
+    fn init_from_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("part_1")).unwrap();
+        for file in [
+            "chapter_10.md",
+            "chapter_2.md",
+            "part_1/intro.md",
+            "README.md",
+            "CHANGELOG.md",
+            "LICENSE-MIT.md",
+            "notes.txt",
+        ] {
+            fs::write(dir.path().join(file), "# Title\n").unwrap();
+        }
+        let book_file = create_project(dir.path(), true, false).unwrap();
+        let content = fs::read_to_string(book_file).unwrap();
+        assert!(content.ends_with("+ chapter_2.md\n+ chapter_10.md\n+ part_1/intro.md\n"));
+        assert!(!content.contains("README"));
+        assert!(!dir.path().join("chapters").exists());
+        assert!(!dir.path().join("images").exists());
+    }
 }
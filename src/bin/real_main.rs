@@ -18,7 +18,7 @@
 use crate::helpers::*;
 
 use crowbook::Stats;
-use crowbook::{Book, BookOptions, Result};
+use crowbook::{Book, BookOptions, Check, Migration, Result, Severity, Theme};
 
 use clap::ArgMatches;
 use simplelog::{ConfigBuilder, LevelFilter, SimpleLogger, TermLogger, WriteLogger};
@@ -31,6 +31,77 @@ use yaml_rust::Yaml;
 use rust_i18n::t;
 
 
+/// Print the result of --check and exit with the appropriate code
+fn print_check(check: &Check, matches: &ArgMatches) -> ! {
+    if matches.get_one::<String>("check-format").map(|s| s.as_str()) == Some("json") {
+        println!("{}", check.to_json());
+    } else {
+        println!("{check}");
+    }
+    if check.has_errors() {
+        exit(1);
+    } else if check.count(Severity::Warning) > 0 {
+        exit(2);
+    } else {
+        exit(0);
+    }
+}
+
+/// Migrate deprecated options of a book configuration file and exit
+fn migrate_book(file: &str, stdout: bool, emoji: bool) -> ! {
+    let migrations = match Migration::from_file(file) {
+        Ok(migrations) => migrations,
+        Err(err) => {
+            print_error(&format!("{err}"), emoji);
+            exit(1);
+        }
+    };
+    for migration in &migrations {
+        for (line, change) in &migration.changes {
+            eprintln!("{}:{line}: {change}", migration.file.display());
+        }
+        if stdout {
+            if migrations.len() > 1 {
+                println!("# {}", migration.file.display());
+            }
+            print!("{}", migration.content);
+        } else if migration.is_changed() {
+            if let Err(err) = migration.write() {
+                print_error(&format!("{err}"), emoji);
+                exit(1);
+            }
+            eprintln!("{}", t!("msg.migrated", file = migration.file.display()));
+        } else {
+            eprintln!("{}", t!("msg.no_migration", file = migration.file.display()));
+        }
+    }
+    exit(0);
+}
+
+/// Write the templates of the book (or the default ones) to a theme directory and exit
+fn export_theme(dir: &str, matches: &ArgMatches, emoji: bool) -> ! {
+    let mut book = Book::new();
+    set_book_options(&mut book, matches);
+    if let Some(file) = matches.get_one::<String>("BOOK") {
+        if let Err(err) = book.load_file(file) {
+            print_error(&format!("{err}"), emoji);
+            exit(1);
+        }
+    }
+    match Theme::export(&book, dir) {
+        Ok(files) => {
+            for file in files {
+                eprintln!("{}", t!("msg.exported", file = file.display()));
+            }
+            exit(0);
+        }
+        Err(err) => {
+            print_error(&format!("{err}"), emoji);
+            exit(1);
+        }
+    }
+}
+
 /// Render a book to specific format
 fn render_format(book: &mut Book, emoji: bool, matches: &ArgMatches, format: &str) {
     let mut key = String::from("output.");
@@ -91,7 +162,7 @@ pub fn try_main() -> Result<()> {
         exit(0);
     }
 
-    if matches.get_flag("no-fancy") || matches.get_flag("stats") {
+    if matches.get_flag("no-fancy") || matches.get_flag("stats") || matches.get_flag("check") {
         fancy_ui = false;
         emoji = false;
     }
@@ -117,6 +188,14 @@ pub fn try_main() -> Result<()> {
         }
     }
 
+    if matches.contains_id("init") {
+        init_project(&matches);
+    }
+
+    if let Some(dir) = matches.get_one::<String>("export-theme") {
+        export_theme(dir, &matches, emoji);
+    }
+
     if matches.get_many::<String>("files").is_some() {
         create_book(&matches);
     }
@@ -131,12 +210,19 @@ pub fn try_main() -> Result<()> {
     // ok to unwrap since clap checks it's there
     let &s = book.as_ref().unwrap();
 
+    if matches.get_flag("migrate") {
+        migrate_book(s, matches.get_flag("stdout"), emoji);
+    }
+
     // Initialize logger
     let mut builder = ConfigBuilder::new();
     builder.set_target_level(LevelFilter::Off);
     builder.set_location_level(LevelFilter::Off);
     builder.set_time_level(LevelFilter::Off);
-    let verbosity = if matches.get_flag("verbose") && !matches.get_flag("stats") {
+    let verbosity = if matches.get_flag("check") && !matches.get_flag("verbose") {
+        // Problems are reported by the check itself
+        LevelFilter::Off
+    } else if matches.get_flag("verbose") && !matches.get_flag("stats") {
         builder.set_time_level(LevelFilter::Error);
         builder.set_target_level(LevelFilter::Error);
         fancy_ui = false;
@@ -190,6 +276,9 @@ pub fn try_main() -> Result<()> {
             book.add_progress_bar(emoji);
         }
         book.set_options(&get_book_options(&matches));
+        if let Some(profile) = matches.get_one::<String>("profile") {
+            book.with_profile(profile);
+        }
 
         {
             let res = if matches.get_flag("single") {
@@ -207,6 +296,7 @@ pub fn try_main() -> Result<()> {
 
             match res {
                 Ok(..) => {}
+                Err(err) if matches.get_flag("check") => print_check(&Check::from_error(&err), &matches),
                 Err(err) => {
                     book.set_error(&format!("{err}"));
                     return Err(err);
@@ -216,14 +306,38 @@ pub fn try_main() -> Result<()> {
 
         set_book_options(&mut book, &matches);
 
+        if matches.get_flag("check") {
+            print_check(&Check::new(&book), &matches);
+        }
+
         if matches.get_flag("stats") {
-            let stats = Stats::new(&book, matches.get_flag("verbose"));
+            let format = matches.get_one::<String>("to").map_or("html", |s| s.as_str());
+            let stats = Stats::for_format(&book, format, matches.get_flag("verbose"));
             println!("{stats}");
             exit(0);
         }
 
         if let Some(format) = matches.get_one::<String>("to") {
             render_format(&mut book, emoji, &matches, format);
+        } else if matches.get_flag("all-profiles") {
+            let profiles: Vec<String> = book
+                .options
+                .get_profiles()
+                .iter()
+                .map(|s| s.to_string())
+                .collect();
+            if profiles.is_empty() {
+                book.render_all();
+            }
+            // Load the book again for each profile, and apply `--set` options on top of it
+            for name in &profiles {
+                if let Err(err) = book.reload_with_profile(name) {
+                    book.set_error(&format!("{err}"));
+                    return Err(err);
+                }
+                set_book_options(&mut book, &matches);
+                book.render_all();
+            }
         } else {
             book.render_all();
         }
@@ -15,37 +15,45 @@
 // You should have received a copy of the GNU Lesser General Public License
 // along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
 
-use crate::book_bars::Bars;
 use crate::book_renderer::BookRenderer;
 use crate::bookoptions::BookOptions;
+use crate::cache::Cache;
 use crate::chapter::Chapter;
 use crate::cleaner::{Cleaner, CleanerParams, Default, French, Off};
 use crate::epub::Epub;
 use crate::error::{Error, Result, Source};
+use crate::filter;
 use crate::html_dir::HtmlDir;
 use crate::html_single::HtmlSingle;
+use crate::json::{self, Json};
 use crate::lang;
 use crate::latex::{Latex, Pdf};
 use crate::misc;
 use crate::number::Number;
 use crate::parser::Features;
 use crate::parser::Parser;
+use crate::progress::{CancellationToken, Emitter, Event, ProgressListener};
 use crate::resource_handler::ResourceHandler;
 use crate::templates::{epub, epub3, highlight, html, html_dir, html_single, latex};
 use crate::text_view::view_as_text;
-use crate::token::Token;
+use crate::token::{Condition, Token};
+use crate::sink::OutputSink;
+use crate::vfs::BookSource;
+use crate::visitor::{Fold, Visitor, VisitorMut};
 
 use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::collections::{HashMap, BTreeMap};
 use std::fmt;
-use std::fs::File;
 use std::io::{Read, Write};
 use std::iter::IntoIterator;
+use std::mem;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use roman_numerals_rs::RomanNumeral;
 use rayon::prelude::*;
+use yaml_rust::yaml::Hash;
 use yaml_rust::{Yaml, YamlLoader};
 use rust_i18n::t;
 
@@ -58,6 +66,20 @@ pub enum Header {
     Part,
 }
 
+/// Where notes are displayed
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Notes {
+    /// At the bottom of the page (LaTeX), or as pop-up notes (EPUB). For HTML this is
+    /// the same as `ChapterEnd`.
+    Footnotes,
+    /// At the end of each chapter
+    ChapterEnd,
+    /// All together at the end of the book
+    BookEnd,
+    /// In the margin
+    Side,
+}
+
 /// Header data (for chapter or part)
 #[derive(Debug, Clone)]
 pub struct HeaderData {
@@ -72,6 +94,7 @@ pub struct HeaderData {
 }
 
 /// The types of bars
+#[deprecated(note = "progress bars are now displayed by a `ProgressListener`")]
 #[derive(Copy, Clone)]
 pub enum Crowbar {
     Main,
@@ -80,6 +103,7 @@ pub enum Crowbar {
 }
 
 /// The state of bars
+#[deprecated(note = "progress bars are now displayed by a `ProgressListener`")]
 #[derive(Copy, Clone)]
 pub enum CrowbarState {
     Running,
@@ -87,6 +111,48 @@ pub enum CrowbarState {
     Error,
 }
 
+/// Progress bars of a book.
+///
+/// Kept for compatibility only: progress bars are now displayed by a
+/// `ProgressListener`, so this doesn't contain anything.
+#[deprecated(note = "progress bars are now displayed by a `ProgressListener`")]
+#[derive(Default)]
+pub struct Bars {}
+
+#[allow(deprecated)]
+impl Bars {
+    pub fn new() -> Bars {
+        Bars {}
+    }
+}
+
+/// A chapter listed in the configuration file, before it is loaded
+struct ChapterEntry {
+    /// Line of the configuration file
+    line: u32,
+    condition: Option<Condition>,
+    kind: EntryKind,
+}
+
+enum EntryKind {
+    /// A part without a file, only a title
+    Part(Vec<Token>),
+    /// A chapter (or a subchapter, if `level` is set) read from a file
+    File {
+        number: Number,
+        file: String,
+        level: Option<i32>,
+        add_title: bool,
+    },
+}
+
+/// The result of parsing a chapter, that can be done in parallel
+struct ParsedFile {
+    parser: Parser,
+    tokens: Vec<Token>,
+    yaml_block: String,
+}
+
 impl fmt::Display for HeaderData {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.text)
@@ -142,15 +208,30 @@ pub struct Book<'a> {
     cleaner: Box<dyn Cleaner>,
     formats: HashMap<&'static str, (String, Box<dyn BookRenderer>)>,
 
-    #[doc(hidden)]
-    pub bars: Bars,
+    /// Receive the events of the book
+    emitter: Emitter,
+    cancellation: CancellationToken,
+
+    /// Profile that has been applied to options, if any
+    profile: Option<String>,
+    /// Profile to apply when the configuration file is read
+    requested_profile: Option<String>,
+    /// File the book was loaded from, and options before loading it, so it
+    /// can be loaded again with another profile
+    loaded_from: Option<(PathBuf, BookOptions)>,
 
     /// Store the templates registry
     pub registry: upon::Engine<'a>,
+
+    /// Kept for compatibility, progress bars are now a progress listener
+    #[deprecated(note = "use `add_progress_listener` instead")]
+    #[allow(deprecated)]
+    pub bars: Bars,
 }
 
 impl<'a> Book<'a> {
     /// Creates a new, empty `Book`
+    #[allow(deprecated)]
     pub fn new() -> Book<'a> {
         let mut book = Book {
             source: Source::empty(),
@@ -160,8 +241,13 @@ impl<'a> Book<'a> {
             options: BookOptions::new(),
             formats: HashMap::new(),
             features: Features::new(),
-            bars: Bars::new(),
+            emitter: Emitter::default(),
+            cancellation: CancellationToken::new(),
+            profile: None,
+            requested_profile: None,
+            loaded_from: None,
             registry: upon::Engine::new(),
+            bars: Bars::new(),
         };
 
         // Add some filters to registry that are useful for some templates
@@ -180,13 +266,14 @@ impl<'a> Book<'a> {
         )
         .add_format("tex", t!("format.tex"), Box::new(Latex {}))
         .add_format("pdf", t!("format.pdf"), Box::new(Pdf {}))
-        .add_format("epub", t!("format.epub"), Box::new(Epub {}));
+        .add_format("epub", t!("format.epub"), Box::new(Epub {}))
+        .add_format("json", t!("format.json"), Box::new(Json {}));
         book
     }
 
-    /// Sets an error message to the progress bar, if it is set
+    /// Reports an error to progress listeners (and the progress bar, if it is set)
     pub fn set_error(&self, msg: &str) {
-        self.bar_finish(Crowbar::Main, CrowbarState::Error, msg)
+        self.emit(Event::Error(msg.to_owned()));
     }
 
     /// Adds a progress bar where where info should be written.
@@ -196,6 +283,41 @@ impl<'a> Book<'a> {
         self.private_add_progress_bar(emoji);
     }
 
+    /// Registers a listener that receives the events of the book: chapters loaded,
+    /// formats rendered, warnings and errors.
+    ///
+    /// See `ProgressListener` for an example.
+    pub fn add_progress_listener<L: ProgressListener + 'static>(&mut self, listener: L) -> &mut Self {
+        self.emitter.add(Arc::new(listener));
+        self
+    }
+
+    /// Sets the token that can be used to cancel rendering from another thread
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) -> &mut Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Returns the token that can be used to cancel rendering
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Sends an event to progress listeners
+    pub(crate) fn emit(&self, event: Event) {
+        self.emitter.emit(event);
+    }
+
+    /// Logs a warning about the content of the book, and sends it to progress listeners
+    pub(crate) fn warn(&self, msg: String) {
+        self.emitter.warn(msg);
+    }
+
+    /// Returns an emitter sending events to the progress listeners of the book
+    pub(crate) fn emitter(&self) -> Emitter {
+        self.emitter.clone()
+    }
+
     /// Register a format that can be rendered.
     ///
     /// The renderer for this format must implement the `BookRenderer` trait.
@@ -280,20 +402,26 @@ impl<'a> Book<'a> {
     /// let result = book.load_file("some.book");
     /// ```
     pub fn load_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.loaded_from = Some((path.as_ref().to_owned(), self.options.clone()));
         let filename = format!("{}", path.as_ref().display());
         self.source = Source::new(filename.as_str());
         self.options.source = Source::new(filename.as_str());
 
-        let f = File::open(path.as_ref()).map_err(|_| {
+        let content = self.options.book_source.read(path.as_ref()).map_err(|_| {
             Error::file_not_found(Source::empty(), t!("format.book"), filename.clone())
         })?;
+        let f = content.as_slice();
         // Set book path to book's directory
         if let Some(parent) = path.as_ref().parent() {
             self.root = parent.to_owned();
             self.options.root = self.root.clone();
         }
 
-        match self.read_config(&f) {
+        if path.as_ref().extension().is_some_and(|ext| ext == "json") {
+            return self.from_json(f);
+        }
+
+        match self.read_config(f) {
             Ok(_) => Ok(()),
             Err(err) => {
                 if err.is_config_parser() && path.as_ref().ends_with(".md") {
@@ -311,6 +439,50 @@ impl<'a> Book<'a> {
         }
     }
 
+    /// Loads a book from its JSON representation, as written by the `json` output format.
+    ///
+    /// Chapters are not parsed again, so this can be used to render a book whose content
+    /// was generated or modified by other tools. `load_file` calls this method for files
+    /// with a `.json` extension.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crowbook::Book;
+    /// let mut book = Book::new();
+    /// book.read_config("title: Foo\n\n+ foo.md".as_bytes()); // not unwrapping since foo.md doesn't exist
+    /// let mut json = vec![];
+    /// book.render_format_to("json", &mut json);
+    /// let mut copy = Book::new();
+    /// copy.from_json(json.as_slice());
+    /// ```
+    pub fn from_json<R: Read>(&mut self, source: R) -> Result<()> {
+        self.emit(Event::Status(t!("ui.options").into_owned()));
+        let (options, chapters) = json::read(&self.source, source)?;
+        self.options.source = self.source.clone();
+        for (key, value) in options {
+            if let Err(err) = self.options.set_yaml(key, value) {
+                error!("{}", err);
+            }
+        }
+        self.update_cleaner();
+        self.set_chapter_template()?;
+        for chapter in chapters {
+            self.features = self.features | Features::from_tokens(&chapter.content);
+            self.chapters.push(chapter);
+        }
+        Ok(())
+    }
+
+    /// Sets where the files of the book (configuration file, chapters, templates, images,
+    /// ...) are read from, instead of the file system.
+    ///
+    /// This must be called before the book is loaded. See `MemorySource` for an example.
+    pub fn set_book_source<S: BookSource + 'static>(&mut self, source: S) -> &mut Self {
+        self.options.book_source = Arc::new(source);
+        self
+    }
+
     /// Loads a single markdown file
     ///
     /// This is *not* used to add a chapter to an existing book, but to to load the
@@ -458,7 +630,7 @@ impl<'a> Book<'a> {
             Ok(words[0])
         }
 
-        self.bar_set_message(Crowbar::Main, &t!("ui.options"));
+        self.emit(Event::Status(t!("ui.options").into_owned()));
 
         let mut s = String::new();
         source.read_to_string(&mut s).map_err(|err| {
@@ -499,6 +671,10 @@ impl<'a> Book<'a> {
             }
 
             if let Some(next_line) = lines.peek() {
+                if next_line.starts_with(char::is_whitespace) {
+                    // Indented line, part of a nested block
+                    continue;
+                }
                 let doc = YamlLoader::load_from_str(next_line);
                 if let Ok(doc) = doc {
                     if !doc.is_empty() && doc[0].as_hash().is_some() {
@@ -538,19 +714,47 @@ impl<'a> Book<'a> {
         // Update cleaner according to options (autoclean/lang)
         self.update_cleaner();
 
-        self.bar_set_message(Crowbar::Main, &t!("ui.chapters"));
+        // Apply the profile before chapters are parsed, as it can change how they are
+        if let Some(name) = self.requested_profile.clone() {
+            self.set_profile(&name)?;
+        }
+
+        self.emit(Event::Status(t!("ui.chapters").into_owned()));
 
-        // Parse chapters
-        let lines: Vec<_> = lines.collect();
-        self.add_second_bar(&t!("ui.processing"), lines.len() as u64);
+        // List chapters
+        let mut entries: Vec<ChapterEntry> = vec![];
         for line in lines {
-            self.inc_second_bar();
             line_number += 1;
             self.source.set_line(line_number);
-            let line = line.trim();
+            let mut line = line.trim();
             if line.is_empty() || line.starts_with('#') {
                 continue;
             }
+            // A chapter line may end with a condition, e.g. `+ chapter.md {only=pdf}`
+            let mut condition = None;
+            if line.ends_with('}') {
+                if let Some(pos) = line.rfind('{') {
+                    condition = Condition::parse(&line[pos..]);
+                    if condition.is_some() {
+                        line = line[..pos].trim_end();
+                    }
+                }
+            }
+            let n_entries = entries.len();
+            let mut add_files = |files: Vec<(Number, String)>, level, add_title| {
+                for (number, file) in files {
+                    entries.push(ChapterEntry {
+                        line: line_number,
+                        condition: None,
+                        kind: EntryKind::File {
+                            number,
+                            file,
+                            level,
+                            add_title,
+                        },
+                    });
+                }
+            };
             if line.starts_with("--") {
                 // Subchapter
                 let mut level = 0;
@@ -564,19 +768,25 @@ impl<'a> Book<'a> {
                 assert!(level > 1);
                 level -= 1;
                 let file = get_filename(&self.source, &line[level..])?;
-                self.add_subchapter(level as i32, file)?;
+                let files = self.expand_chapter_pattern(file)?;
+                // The number of a subchapter is the one of the chapter it belongs to
+                add_files(
+                    files.into_iter().map(|f| (Number::Hidden, f)).collect(),
+                    Some(level as i32),
+                    false,
+                );
             } else if line.starts_with('-') {
                 // unnumbered chapter
                 let file = get_filename(&self.source, line)?;
-                self.add_chapter(Number::Unnumbered, file, false)?;
+                add_files(self.chapter_pattern(Number::Unnumbered, file)?, None, false);
             } else if line.starts_with('+') {
                 // numbered chapter
                 let file = get_filename(&self.source, line)?;
-                self.add_chapter(Number::Default, file, true)?;
+                add_files(self.chapter_pattern(Number::Default, file)?, None, true);
             } else if line.starts_with('!') {
                 // hidden chapter
                 let file = get_filename(&self.source, line)?;
-                self.add_chapter(Number::Hidden, file, false)?;
+                add_files(self.chapter_pattern(Number::Hidden, file)?, None, false);
             } else if line.starts_with(|c: char| c.is_ascii_digit()) {
                 // chapter with specific number
                 let parts: Vec<_> = line
@@ -595,23 +805,25 @@ impl<'a> Book<'a> {
                         t!("error.chapter_number", error = err),
                     )
                 })?;
-                self.add_chapter(Number::Specified(number), file, true)?;
+                add_files(self.chapter_pattern(Number::Specified(number), file)?, None, true);
             } else if let Some(subline) = line.strip_prefix('@') {
                 /* Part */
                 if subline.starts_with(|c: char| c.is_whitespace()) {
                     let subline = subline.trim();
                     let ast = Parser::from(self).parse_inline(subline)?;
-                    let ast = vec![Token::Header(1, ast)];
-                    self.chapters
-                        .push(Chapter::new(Number::DefaultPart, String::new(), ast));
+                    entries.push(ChapterEntry {
+                        line: line_number,
+                        condition: None,
+                        kind: EntryKind::Part(vec![Token::Header(1, ast)]),
+                    });
                 } else if subline.starts_with('-') {
                     /* Unnumbered part */
                     let file = get_filename(&self.source, subline)?;
-                    self.add_chapter(Number::UnnumberedPart, file, true)?;
+                    add_files(self.chapter_pattern(Number::UnnumberedPart, file)?, None, true);
                 } else if subline.starts_with('+') {
                     /* Numbered part */
                     let file = get_filename(&self.source, subline)?;
-                    self.add_chapter(Number::DefaultPart, file, true)?;
+                    add_files(self.chapter_pattern(Number::DefaultPart, file)?, None, true);
                 } else if subline.starts_with(|c: char| c.is_ascii_digit()) {
                     /* Specified  part*/
                     let parts: Vec<_> = subline
@@ -630,7 +842,7 @@ impl<'a> Book<'a> {
                             t!("error.part_number", error = err),
                         )
                     })?;
-                    self.add_chapter(Number::SpecifiedPart(number), file, true)?;
+                    add_files(self.chapter_pattern(Number::SpecifiedPart(number), file)?, None, true);
                 } else {
                     return Err(Error::config_parser(
                         &self.source,
@@ -643,9 +855,80 @@ impl<'a> Book<'a> {
                     t!("error.chapter_definition"),
                 ));
             }
+            for entry in &mut entries[n_entries..] {
+                entry.condition = condition.clone();
+            }
         }
 
-        self.bar_finish(Crowbar::Second, CrowbarState::Success, "");
+        // Read and parse chapters in parallel, then add them in order
+        let parsed: Vec<Option<Result<ParsedFile>>> = entries
+            .par_iter()
+            .map(|entry| match entry.kind {
+                EntryKind::File { ref file, .. } => {
+                    let mut source = self.source.clone();
+                    source.set_line(entry.line);
+                    Some(
+                        self.cancellation
+                            .check()
+                            .and_then(|_| self.read_chapter(&source, file))
+                            .and_then(|content| self.parse_chapter(file, &content)),
+                    )
+                }
+                EntryKind::Part(_) => None,
+            })
+            .collect();
+
+        let total = entries
+            .iter()
+            .filter(|e| matches!(e.kind, EntryKind::File { .. }))
+            .count();
+        let mut current = 0;
+        for (entry, parsed) in entries.into_iter().zip(parsed) {
+            self.cancellation.check()?;
+            self.source.set_line(entry.line);
+            let n_chapters = self.chapters.len();
+            match entry.kind {
+                EntryKind::Part(ast) => {
+                    self.chapters
+                        .push(Chapter::new(Number::DefaultPart, String::new(), ast));
+                }
+                EntryKind::File {
+                    number,
+                    file,
+                    level,
+                    add_title,
+                } => {
+                    let mut parsed = parsed.unwrap()?;
+                    // The YAML block of a previous chapter may have changed how
+                    // this one must be parsed
+                    if !parsed.parser.has_same_settings(&Parser::from(self)) {
+                        let content = self.read_chapter(&self.source, &file)?;
+                        parsed = self.parse_chapter(&file, &content)?;
+                    }
+                    match level {
+                        None => {
+                            self.add_parsed_chapter(number, &file, parsed, add_title)?;
+                        }
+                        Some(level) => {
+                            let number = self.chapters.last().map_or(Number::Hidden, |c| c.number);
+                            self.add_parsed_chapter(number, &file, parsed, false)?;
+                            self.adjust_subchapter_levels(level, &file)?;
+                        }
+                    }
+                    current += 1;
+                    self.emit(Event::ChapterLoaded {
+                        file: misc::normalize(&file),
+                        current,
+                        total,
+                    });
+                }
+            }
+            if let Some(ref condition) = entry.condition {
+                for chapter in &mut self.chapters[n_chapters..] {
+                    chapter.condition = Some(condition.clone());
+                }
+            }
+        }
 
         self.source.unset_line();
         self.set_chapter_template()?;
@@ -694,15 +977,15 @@ impl<'a> Book<'a> {
             }
         });
 
-        for key in &keys {
-            self.add_spinner_to_multibar(key);
-        }
-
-        keys.par_iter().enumerate().for_each(|(i, fmt)| {
-            self.render_format_with_bar(fmt, i);
+        keys.par_iter().for_each(|fmt| {
+            self.render_format(fmt);
         });
 
-        self.bar_finish(Crowbar::Main, CrowbarState::Success, &t!("ui.finished"));
+        if self.cancellation.is_cancelled() {
+            self.emit(Event::Error(Error::cancelled().to_string()));
+        } else {
+            self.emit(Event::Finished);
+        }
 
         // if handles.is_empty() {
         //     Logger::display_warning(lformat!("Crowbook generated no file because no output file was \
@@ -710,19 +993,166 @@ impl<'a> Book<'a> {
         // }
     }
 
-    /// Renders the book to the given format and reports to progress bar if set
-    pub fn render_format_with_bar(&self, format: &str, bar: usize) {
+    /// Selects a profile, i.e. a named set of options defined under `profiles` in
+    /// the book configuration file, to apply when this file is read.
+    ///
+    /// The profile is applied right after the options of the configuration file,
+    /// before the chapters are parsed, so it can change how they are (e.g. with
+    /// `input.*` options, or with conditional chapters using `rendering.edition`).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crowbook::Book;
+    /// let content = "\
+    /// title: Foo
+    /// profiles:
+    ///   print:
+    ///     tex.paper.size: a5paper
+    /// ";
+    /// let mut book = Book::new();
+    /// book.with_profile("print").read_config(content.as_bytes()).unwrap();
+    /// assert_eq!(book.options.get_str("tex.paper.size").unwrap(), "a5paper");
+    /// ```
+    pub fn with_profile(&mut self, name: &str) -> &mut Self {
+        self.requested_profile = Some(name.to_owned());
+        self
+    }
+
+    /// Applies a profile on top of current options.
+    ///
+    /// Output files that are not redefined by the profile get the profile name as a suffix
+    /// (e.g. `book.pdf` becomes `book-print.pdf`), and `rendering.edition` is set to the
+    /// profile name unless the profile sets it.
+    ///
+    /// Chapters that are already loaded are not parsed again, so options that are used
+    /// when parsing them have no effect; use `with_profile` before loading the book
+    /// or `reload_with_profile` after.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crowbook::Book;
+    /// let content = "\
+    /// title: Foo
+    /// output.pdf: foo.pdf
+    /// profiles:
+    ///   print:
+    ///     tex.paper.size: a5paper
+    /// ";
+    /// let mut book = Book::new();
+    /// book.read_config(content.as_bytes()).unwrap();
+    /// book.set_profile("print").unwrap();
+    /// assert_eq!(book.options.get_str("tex.paper.size").unwrap(), "a5paper");
+    /// assert_eq!(book.options.get_relative_path("output.pdf").unwrap(), "foo-print.pdf");
+    /// assert!(book.set_profile("web").is_err());
+    /// ```
+    pub fn set_profile(&mut self, name: &str) -> Result<&mut Self> {
+        let profile = self.options.get_profile(name).cloned().ok_or_else(|| {
+            Error::default(
+                &self.source,
+                t!(
+                    "error.unknown_profile",
+                    profile = name,
+                    profiles = self.options.get_profiles().join(", ")
+                ),
+            )
+        })?;
+
+        // Remember output paths so this profile won't overwrite the files of other ones
+        let outputs: Vec<(String, String)> = self
+            .formats
+            .keys()
+            .filter_map(|fmt| {
+                let key = format!("output.{fmt}");
+                self.options
+                    .get_relative_path(&key)
+                    .ok()
+                    .map(|path| (key, path.to_owned()))
+            })
+            .collect();
+
+        let edition = Yaml::String(String::from("rendering.edition"));
+        if !profile.contains_key(&edition) {
+            self.options.set_yaml(edition, Yaml::String(name.to_owned()))?;
+        }
+        for (key, value) in profile {
+            self.options.set_yaml(key, value)?;
+        }
+
+        for (key, path) in outputs {
+            if path != "auto" && self.options.get_relative_path(&key).ok() == Some(path.as_str()) {
+                self.options
+                    .set_yaml(Yaml::String(key), Yaml::String(misc::add_suffix(&path, name)))?;
+            }
+        }
+
+        self.profile = Some(name.to_owned());
+        self.update_cleaner();
+        Ok(self)
+    }
+
+    /// Loads the book again from the file it was loaded from with `load_file`, applying
+    /// the given profile before chapters are parsed.
+    ///
+    /// Options set before loading the book are kept, but the ones set after are lost.
+    /// If the book wasn't loaded from a file, this is the same as `set_profile`.
+    pub fn reload_with_profile(&mut self, name: &str) -> Result<&mut Self> {
+        let (path, options) = match self.loaded_from.clone() {
+            Some(loaded) => loaded,
+            None => return self.set_profile(name),
+        };
+        let requested = self.requested_profile.replace(name.to_owned());
+        self.options = options;
+        self.chapters.clear();
+        self.features = Features::new();
+        let res = self.load_file(path);
+        self.requested_profile = requested;
+        res?;
+        Ok(self)
+    }
+
+    /// Generates output files for each profile defined in the book configuration file,
+    /// or simply calls `render_all` if there is none.
+    ///
+    /// The book is loaded again for each profile (see `reload_with_profile`), and
+    /// restored to its previous state after rendering.
+    pub fn render_all_profiles(&mut self) {
+        let profiles: Vec<String> = self
+            .options
+            .get_profiles()
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        if profiles.is_empty() {
+            self.render_all();
+            return;
+        }
+        let options = self.options.clone();
+        let chapters = mem::take(&mut self.chapters);
+        let features = self.features;
+        let profile = self.profile.take();
+        for name in &profiles {
+            match self.reload_with_profile(name) {
+                Ok(_) => self.render_all(),
+                Err(err) => error!("{}", err),
+            }
+            self.options = options.clone();
+        }
+        self.chapters = chapters;
+        self.features = features;
+        self.profile = profile;
+        self.update_cleaner();
+    }
+
+    /// Renders the book to the given format, if `output.{format}` is set.
+    ///
+    /// Errors are logged and reported to progress listeners.
+    pub fn render_format(&self, format: &str) {
         let mut key = String::from("output.");
         key.push_str(format);
         if let Ok(path) = self.options.get_path(&key) {
-            self.bar_set_message(Crowbar::Spinner(bar), &t!("ui.rendering_format"));
-            let result = self.render_format_to_file_with_bar(format, path, bar);
-            if let Err(err) = result {
-                self.bar_finish(
-                    Crowbar::Spinner(bar),
-                    CrowbarState::Error,
-                    &format!("{err}"),
-                );
+            if let Err(err) = self.render_format_to_path(format, path) {
                 error!(
                     "{}",
                     t!("error.rendering",
@@ -734,18 +1164,91 @@ impl<'a> Book<'a> {
         }
     }
 
+    /// Renders the book to the given format, if `output.{format}` is set.
+    #[deprecated(note = "use `render_format` and a progress listener")]
+    pub fn render_format_with_bar(&self, format: &str, _bar: usize) {
+        self.render_format(format)
+    }
+
+    /// Renders the book to the given format, writing it to `path`.
+    #[deprecated(note = "use `render_format_to_file` and a progress listener")]
     pub fn render_format_to_file_with_bar<P: Into<PathBuf>>(
         &self,
         format: &str,
         path: P,
-        bar: usize,
+        _bar: usize,
     ) -> Result<()> {
+        self.render_format_to_path(format, path)
+    }
+
+    /// Sets a finished message to a progress bar. Does nothing.
+    #[deprecated(note = "progress bars are now displayed by a `ProgressListener`")]
+    #[allow(deprecated)]
+    pub fn bar_finish(&self, _bar: Crowbar, _state: CrowbarState, _msg: &str) {}
+
+    /// Adds a secondary progress bar to display progress of book parsing. Does nothing.
+    #[deprecated(note = "progress bars are now displayed by a `ProgressListener`")]
+    pub fn add_second_bar(&mut self, _msg: &str, _len: u64) {}
+
+    /// Increments the secondary bar. Does nothing.
+    #[deprecated(note = "progress bars are now displayed by a `ProgressListener`")]
+    pub fn inc_second_bar(&self) {}
+
+    /// Adds a spinner labeled `key` to the progress bars. Does nothing, and returns 0.
+    #[deprecated(note = "progress bars are now displayed by a `ProgressListener`")]
+    pub fn add_spinner_to_multibar(&mut self, _key: &str) -> usize {
+        0
+    }
+
+    /// Sets the message of a progress bar. Does nothing.
+    #[deprecated(note = "progress bars are now displayed by a `ProgressListener`")]
+    #[allow(deprecated)]
+    pub fn bar_set_message(&self, _bar: Crowbar, _msg: &str) {}
+
+    /// Renders a format and reports it to progress listeners
+    ///
+    /// `render` returns the path of the generated file, if there is one. It is not
+    /// called if rendering has been cancelled.
+    fn render_with_events<F>(&self, format: &str, render: F) -> Result<()>
+    where
+        F: FnOnce() -> Result<Option<String>>,
+    {
+        self.emit(Event::FormatStarted {
+            format: format.to_owned(),
+        });
+        match self.cancellation.check().and_then(|_| render()) {
+            Ok(path) => {
+                self.emit(Event::FormatFinished {
+                    format: format.to_owned(),
+                    path,
+                });
+                Ok(())
+            }
+            Err(err) => {
+                self.emit(Event::FormatFailed {
+                    format: format.to_owned(),
+                    error: err.to_string(),
+                });
+                Err(err)
+            }
+        }
+    }
+
+    /// Reports the end of rendering a single format to progress listeners
+    fn finish_rendering(&self, result: &Result<()>) {
+        match *result {
+            Ok(_) => self.emit(Event::Finished),
+            Err(_) => self.emit(Event::Error(t!("ui.error").into_owned())),
+        }
+    }
+
+    fn render_format_to_path<P: Into<PathBuf>>(&self, format: &str, path: P) -> Result<()> {
         debug!(
             "{}",
             t!("msg.attempting", format = format)
         );
         let path = path.into();
-        match self.formats.get(format) {
+        self.render_with_events(format, || match self.formats.get(format) {
             Some((description, renderer)) => {
                 let path = if path.ends_with("auto") {
                     let file = if let Some(s) = self
@@ -754,7 +1257,10 @@ impl<'a> Book<'a> {
                         .as_ref()
                         .and_then(|f| Path::new(f).file_stem())
                     {
-                        s.to_string_lossy().into_owned()
+                        match self.profile {
+                            Some(ref profile) => format!("{}-{profile}", s.to_string_lossy()),
+                            None => s.to_string_lossy().into_owned(),
+                        }
                     } else {
                         return Err(Error::default(&self.source, t!("error.infer",
                                                                      format = description)));
@@ -779,26 +1285,21 @@ impl<'a> Book<'a> {
                     path = &path
                 );
                 info!("{}", &msg);
-                self.bar_finish(
-                    Crowbar::Spinner(bar),
-                    CrowbarState::Success,
-                    &t!("ui.generated", path = path),
-                );
-                Ok(())
+                Ok(Some(path))
             }
             None => Err(Error::default(
                 Source::empty(),
                 t!("error.unknown", format = format),
             )),
-        }
+        })
     }
 
     /// Render book to specified format according to book options, and write the results
     /// in the `Write` object.
     ///
     /// This method will fail if the format is not handled by the book, or if there is a
-    /// problem during rendering, or if the renderer can't render to a byte stream (e.g.
-    /// multiple files HTML renderer can't, as it must create a directory.)
+    /// problem during rendering, or if the renderer can't render to a byte stream. (The
+    /// multiple files HTML renderer writes a zip archive, if the `archive` feature is enabled.)
     ///
     /// # See also
     /// * `render_format_to_file`, which creates a new file (that *can* be a directory).
@@ -809,44 +1310,61 @@ impl<'a> Book<'a> {
             "{}",
             t!("msg.attempting", format = format)
         );
-        let bar = self.add_spinner_to_multibar(format);
-        match self.formats.get(format) {
-            Some((description, renderer)) => match renderer.render(self, f) {
-                Ok(_) => {
-                    self.bar_finish(
-                        Crowbar::Spinner(bar),
-                        CrowbarState::Success,
-                        &t!("ui.generated", path = format),
-                    );
-                    self.bar_finish(Crowbar::Main, CrowbarState::Success, &t!("ui.finished"));
-                    info!(
-                        "{}",
-                        t!("msg.generated_short", format = description)
-                    );
-                    Ok(())
-                }
-                Err(e) => {
-                    self.bar_finish(
-                        Crowbar::Spinner(bar),
-                        CrowbarState::Error,
-                        &format!("{error}", error = e),
-                    );
-                    self.bar_finish(Crowbar::Main, CrowbarState::Error, &t!("ui.error"));
-                    Err(e)
-                }
-            },
-            None => {
-                self.bar_finish(
-                    Crowbar::Spinner(bar),
-                    CrowbarState::Error,
-                    &t!("error.unknown_short"),
+        let result = self.render_with_events(format, || match self.formats.get(format) {
+            Some((description, renderer)) => {
+                renderer.render(self, f)?;
+                info!(
+                    "{}",
+                    t!("msg.generated_short", format = description)
                 );
-                Err(Error::default(
+                Ok(None)
+            }
+            None => Err(Error::default(
+                Source::empty(),
+                t!("error.unknown", format = format),
+            )),
+        });
+        self.finish_rendering(&result);
+        result
+    }
+
+    /// Render book to specified format, writing the files it produces to an `OutputSink`.
+    ///
+    /// Formats producing a single file write it with the name they would use for the
+    /// `auto` output (e.g. `book.epub` if the book was loaded from `book.book`), while
+    /// `html.dir` writes all its files at the root of the sink, and `tex` writes the images
+    /// it uses next to the `.tex` file. EPUB and PDF files are built before being written to
+    /// the sink, so their content can't be split into several files.
+    ///
+    /// # See also
+    /// * `MemorySink`, to keep all generated files in memory.
+    pub fn render_format_to_sink(&self, format: &str, sink: &mut dyn OutputSink) -> Result<()> {
+        debug!(
+            "{}",
+            t!("msg.attempting", format = format)
+        );
+        let result = self.render_with_events(format, || {
+            let (description, renderer) = self.formats.get(format).ok_or_else(|| {
+                Error::default(
                     Source::empty(),
                     t!("error.unknown", format = format),
-                ))
-            }
-        }
+                )
+            })?;
+            let name = self
+                .source
+                .file
+                .as_ref()
+                .and_then(|f| Path::new(f).file_stem())
+                .map_or_else(|| String::from("book"), |s| s.to_string_lossy().into_owned());
+            renderer.render_to_sink(self, &name, sink)?;
+            info!(
+                "{}",
+                t!("msg.generated_short", format = description)
+            );
+            Ok(None)
+        });
+        self.finish_rendering(&result);
+        result
     }
 
     /// Render book to specified format according to book options. Creates a new file
@@ -858,8 +1376,7 @@ impl<'a> Book<'a> {
     /// # Arguments
     ///
     /// * `format`: the format to render;
-    /// * `path`: a path to the file that will be created;
-    /// * `bar`: a Progressbar, or `None`
+    /// * `path`: a path to the file that will be created.
     ///
     /// # See also
     /// * `render_format_to`, which writes in any `Write`able object.
@@ -867,10 +1384,9 @@ impl<'a> Book<'a> {
     ///   in the book configuration file.
 
     pub fn render_format_to_file<P: Into<PathBuf>>(&mut self, format: &str, path: P) -> Result<()> {
-        let bar = self.add_spinner_to_multibar(format);
-        self.render_format_to_file_with_bar(format, path, bar)?;
-        self.bar_finish(Crowbar::Main, CrowbarState::Success, &t!("ui.finished"));
-        Ok(())
+        let result = self.render_format_to_path(format, path);
+        self.finish_rendering(&result);
+        result
     }
 
     /// Adds a chapter to the book.
@@ -881,12 +1397,9 @@ impl<'a> Book<'a> {
         number: Number,
         file: &str,
         mut source: R,
-        mut add_title_if_empty: bool,
+        add_title_if_empty: bool,
     ) -> Result<&mut Self> {
-        self.bar_set_message(
-            Crowbar::Main,
-            &t!("ui.processing_file", file = file),
-        );
+        self.emit(Event::Status(t!("ui.processing_file", file = file).into_owned()));
         let mut content = String::new();
         source.read_to_string(&mut content).map_err(|_| {
             Error::parser(
@@ -899,17 +1412,43 @@ impl<'a> Book<'a> {
         })?;
 
         // parse the file
-        self.bar_set_message(Crowbar::Second, &t!("ui.parsing..."));
+        let parsed = self.parse_chapter(file, &content)?;
+        self.add_parsed_chapter(number, file, parsed, add_title_if_empty)
+    }
 
-        let mut parser = Parser::from(self);
-        parser.set_source_file(file);
-        let mut yaml_block = String::from("");
-        let mut tokens = parser.parse(&content, Option::Some(&mut yaml_block))?;
+    /// Adds a chapter that has been parsed by `parse_chapter`
+    fn add_parsed_chapter(
+        &mut self,
+        number: Number,
+        file: &str,
+        parsed: ParsedFile,
+        mut add_title_if_empty: bool,
+    ) -> Result<&mut Self> {
+        let ParsedFile {
+            parser,
+            mut tokens,
+            yaml_block,
+        } = parsed;
 
         // Parse YAML block
-        self.parse_yaml(&yaml_block);
+        let chapter_options = self.parse_yaml(&yaml_block);
         self.features = self.features | parser.features();
 
+        // Warn about variables that don't match any metadata, or that are not set
+        if !parser.variable_refs().is_empty() {
+            let known = self.get_metadata(|s| Ok(s.to_owned()))?;
+            for (name, line) in parser.variable_refs() {
+                let key = name.replace('.', "_");
+                let mut source = Source::new(file);
+                source.set_line(*line as u32);
+                if !known.contains_key(&key) {
+                    self.warn(t!("warn.unknown_variable", source = source, name = name).into_owned());
+                } else if known.get(&format!("has_{key}")) == Some(&upon::Value::Bool(false)) {
+                    self.warn(t!("warn.empty_variable", source = source, name = name).into_owned());
+                }
+            }
+        }
+
         // transform the AST to make local links and images relative to `book` directory
         let offset = if let Some(f) = Path::new(file).parent() {
             f
@@ -958,13 +1497,182 @@ impl<'a> Book<'a> {
             misc::insert_title(&mut tokens);
         }
 
-        self.bar_set_message(Crowbar::Second, "");
+        // Options that only apply to this chapter
+        let mut chapter = Chapter::new(number, file, Vec::new());
+        // Chapter options are validated on a copy of the book options, made once per chapter
+        let mut checked: Option<BookOptions> = None;
+        for (key, value) in chapter_options {
+            match (key.as_str(), value) {
+                (Some("title"), Yaml::String(title)) => {
+                    let title = Parser::from(self).parse_inline(&title)?;
+                    if let Some(Token::Header(_, inner)) =
+                        tokens.iter_mut().find(|t| matches!(t, Token::Header(1, _)))
+                    {
+                        *inner = title;
+                    } else {
+                        tokens.insert(0, Token::Header(1, title));
+                    }
+                }
+                (Some("toc"), Yaml::Boolean(b)) => chapter.toc = b,
+                (Some("class"), Yaml::String(class)) => chapter.class = Some(class),
+                (Some(k), value) if !matches!(k, "title" | "toc" | "class") => {
+                    // Check that it is a valid option before keeping it
+                    let options = checked.get_or_insert_with(|| self.options.clone());
+                    match options.set_yaml(key.clone(), value.clone()) {
+                        Ok(_) => {
+                            chapter.options.insert(key, value);
+                        }
+                        Err(err) => error!(
+                            "{}",
+                            t!(
+                                "error.yaml_set",
+                                key = format!("chapter.{k}"),
+                                value = format!("{:?}", value),
+                                err = err
+                            )
+                        ),
+                    }
+                }
+                (k, value) => error!(
+                    "{}",
+                    t!(
+                        "error.chapter_option",
+                        source = &self.source,
+                        key = format!("chapter.{}", k.unwrap_or_default()),
+                        value = format!("{:?}", value)
+                    )
+                ),
+            }
+        }
+        chapter.content = tokens;
+
+        // Run external filters on the chapter
+        if let Ok(filters) = self.options.get_str_vec("filters") {
+            if !filters.is_empty() {
+                self.emit(Event::Status(t!("ui.filtering").into_owned()));
+                let mut metadata = BTreeMap::new();
+                for key in self.options.get_metadata() {
+                    if let Ok(value) = self.options.get_str(key) {
+                        metadata.insert(key.clone(), value.to_owned());
+                    }
+                }
+                chapter = filter::apply(filters, &self.root, metadata, chapter)?;
+                self.features = self.features | Features::from_tokens(&chapter.content);
+            }
+        }
 
-        self.chapters.push(Chapter::new(number, file, tokens));
+        self.chapters.push(chapter);
 
         Ok(self)
     }
 
+    /// Expands a pattern of the chapter list (e.g. `chapters/*.md`, or `chapters/` for all the
+    /// Markdown files of a directory) to the matching files, relative to the book's root.
+    ///
+    /// Files are sorted according to the `order` key of their YAML block if they have one,
+    /// and in natural order (`2.md` before `10.md`) otherwise. If `pattern` isn't a pattern,
+    /// returns it unchanged.
+    fn expand_chapter_pattern(&self, pattern: &str) -> Result<Vec<String>> {
+        let book_source = &self.options.book_source;
+        let is_dir = pattern.ends_with('/') || book_source.is_dir(&self.root.join(pattern));
+        // An existing file is used as is, even if its name contains wildcards (e.g. `[1].md`)
+        if !is_dir
+            && (!pattern.contains(['*', '?', '['])
+                || book_source.is_file(&self.root.join(pattern)))
+        {
+            return Ok(vec![pattern.to_owned()]);
+        }
+        let pattern = if is_dir {
+            format!("{}/*.md", pattern.trim_end_matches('/'))
+        } else {
+            pattern.to_owned()
+        };
+        let matcher = glob::Pattern::new(&pattern).map_err(|err| {
+            Error::config_parser(
+                &self.source,
+                t!("error.invalid_pattern", pattern = &pattern, error = err),
+            )
+        })?;
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..glob::MatchOptions::new()
+        };
+        // Only list the files of the directory before the first wildcard
+        let base: PathBuf = Path::new(&pattern)
+            .components()
+            .take_while(|c| !c.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+            .collect();
+        // Only walk subdirectories when the pattern can match files in them
+        let depth = if pattern.contains("**") {
+            usize::MAX
+        } else {
+            Path::new(&pattern).components().count() - base.components().count()
+        };
+
+        let mut files: Vec<(Option<f64>, String)> = vec![];
+        for path in book_source
+            .files_up_to(&self.root.join(&base), depth)
+            .unwrap_or_default()
+        {
+            let file = path.strip_prefix(&self.root).unwrap_or(&path);
+            let file = file.strip_prefix(".").unwrap_or(file);
+            if !matcher.matches_path_with(file, options) {
+                continue;
+            }
+            files.push((self.read_order(&path), file.to_string_lossy().into_owned()));
+        }
+        if files.is_empty() {
+            return Err(Error::config_parser(
+                &self.source,
+                t!("error.no_match", pattern = &pattern),
+            ));
+        }
+
+        files.sort_by(|(o1, f1), (o2, f2)| match (o1, o2) {
+            (Some(o1), Some(o2)) => o1
+                .partial_cmp(o2)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| misc::natural_cmp(f1, f2)),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => misc::natural_cmp(f1, f2),
+        });
+        Ok(files.into_iter().map(|(_, file)| file).collect())
+    }
+
+    /// Reads the `order` key of the YAML block at the beginning of a Markdown file, if any
+    fn read_order(&self, path: &Path) -> Option<f64> {
+        let content = self.options.book_source.read_to_string(path).ok()?;
+        let mut lines = content.lines();
+        if lines.next()?.trim_end() != "---" {
+            return None;
+        }
+        let yaml: Vec<&str> = lines
+            .take_while(|l| !matches!(l.trim_end(), "---" | "..."))
+            .collect();
+        let docs = YamlLoader::load_from_str(&yaml.join("\n")).ok()?;
+        let order = &docs.first()?["order"];
+        order.as_f64().or_else(|| order.as_i64().map(|n| n as f64))
+    }
+
+    /// Adds the chapters matching a pattern of the chapter list to the book.
+    ///
+    /// If `number` is a specified number, it only applies to the first file and the next
+    /// ones are numbered after it.
+    fn chapter_pattern(&self, number: Number, pattern: &str) -> Result<Vec<(Number, String)>> {
+        let mut number = number;
+        let mut files = vec![];
+        for file in self.expand_chapter_pattern(pattern)? {
+            files.push((number, file));
+            number = match number {
+                Number::Specified(_) => Number::Default,
+                Number::SpecifiedPart(_) => Number::DefaultPart,
+                number => number,
+            };
+        }
+        Ok(files)
+    }
+
     /// Adds a chapter, as a file name, to the book
     pub fn add_subchapter(&mut self, level: i32, file: &str) -> Result<&mut Self> {
         let number = {
@@ -975,23 +1683,24 @@ impl<'a> Book<'a> {
             }
         };
         self.add_chapter(number, file, false)?;
+        self.adjust_subchapter_levels(level, file)?;
+        Ok(self)
+    }
 
-        // Adjust header levels
-        {
-            let last = self.chapters.last_mut().unwrap();
-            for token in &mut last.content {
-                if let Token::Header(ref mut n, _) = *token {
-                    let new = *n + level;
-                    if !(0..=6).contains(&new) {
-                        return Err(Error::parser(Source::new(file),
-                                                     t!("error.heading", n = new)));
-                    }
-                    *n = new;
+    /// Adjusts the header levels of the last chapter, which is a subchapter
+    fn adjust_subchapter_levels(&mut self, level: i32, file: &str) -> Result<()> {
+        let last = self.chapters.last_mut().unwrap();
+        for token in &mut last.content {
+            if let Token::Header(ref mut n, _) = *token {
+                let new = *n + level;
+                if !(0..=6).contains(&new) {
+                    return Err(Error::parser(Source::new(file),
+                                                 t!("error.heading", n = new)));
                 }
+                *n = new;
             }
         }
-
-        Ok(self)
+        Ok(())
     }
 
     /// Adds a chapter, as a file name, to the book
@@ -1012,22 +1721,49 @@ impl<'a> Book<'a> {
         file: &str,
         add_title_if_empty: bool,
     ) -> Result<&mut Self> {
-        self.bar_set_message(
-            Crowbar::Main,
-            &t!("ui.parsing_file", file = misc::normalize(file)),
-        );
+        self.emit(Event::Status(
+            t!("ui.parsing_file", file = misc::normalize(file)).into_owned(),
+        ));
 
-        // try to open file
+        let content = self.read_chapter(&self.source, file)?;
+        let parsed = self.parse_chapter(file, &content)?;
+        self.add_parsed_chapter(number, file, parsed, add_title_if_empty)
+    }
+
+    /// Reads the content of a chapter file
+    fn read_chapter(&self, source: &Source, file: &str) -> Result<String> {
         let path = self.root.join(file);
-        let f = File::open(&path).map_err(|_| {
+        let content = self.options.book_source.read(&path).map_err(|_| {
             Error::file_not_found(
-                &self.source,
+                source,
                 t!("format.book_chapter"),
                 format!("{}", path.display()),
             )
         })?;
+        String::from_utf8(content).map_err(|_| {
+            Error::parser(
+                source,
+                t!(
+                    "error.utf8",
+                    file = misc::normalize(file)
+                ),
+            )
+        })
+    }
 
-        self.add_chapter_from_named_source(number, file, f, add_title_if_empty)
+    /// Parses the content of a chapter
+    ///
+    /// This doesn't modify the book, so several chapters can be parsed in parallel.
+    fn parse_chapter(&self, file: &str, content: &str) -> Result<ParsedFile> {
+        let mut parser = Parser::from(self);
+        parser.set_source_file(file);
+        let mut yaml_block = String::new();
+        let tokens = parser.parse_cached(&Cache::for_book(self), content, Some(&mut yaml_block))?;
+        Ok(ParsedFile {
+            parser,
+            tokens,
+            yaml_block,
+        })
     }
 
     /// Adds a chapter to the book from a source (any object implementing `Read`)
@@ -1089,6 +1825,7 @@ impl<'a> Book<'a> {
             "html.standalone.js" => html_single::JS,
             "html.js" => html::JS,
             "html.dir.template" => html_dir::TEMPLATE,
+            "html.dir.search.js" => html_dir::SEARCH_JS,
             "html.highlight.js" => highlight::JS,
             "html.highlight.css" => highlight::CSS,
             "tex.template" => latex::TEMPLATE,
@@ -1100,11 +1837,10 @@ impl<'a> Book<'a> {
             }
         };
         if let Ok(ref s) = option {
-            let mut f = File::open(s).map_err(|_| {
+            let content = self.options.book_source.read(Path::new(s)).map_err(|_| {
                 Error::file_not_found(&self.source, format!("template '{template}'"), s.to_owned())
             })?;
-            let mut res = String::new();
-            f.read_to_string(&mut res).map_err(|_| {
+            let res = String::from_utf8(content).map_err(|_| {
                 Error::config_parser(
                     &self.source,
                     t!("error.read_file", file = s),
@@ -1141,13 +1877,15 @@ impl<'a> Book<'a> {
     /// Returns the formatted (roman or arabic) number of chapter
     #[doc(hidden)]
     pub fn get_header_number(&self, header: Header, n: i32) -> Result<String> {
+        Self::header_number(&self.options, header, n)
+    }
+
+    fn header_number(options: &BookOptions, header: Header, n: i32) -> Result<String> {
         let boolean = match header {
-            Header::Part => self
-                .options
+            Header::Part => options
                 .get_bool("rendering.part.roman_numerals")
                 .unwrap(),
-            Header::Chapter => self
-                .options
+            Header::Chapter => options
                 .get_bool("rendering.chapter.roman_numerals")
                 .unwrap(),
         };
@@ -1175,6 +1913,23 @@ impl<'a> Book<'a> {
         header: Header,
         n: i32,
         title: String,
+        f: F,
+    ) -> Result<HeaderData>
+    where
+        F: FnMut(&str) -> Result<String>,
+    {
+        self.get_header_with(&self.options, header, n, title, f)
+    }
+
+    /// Same as `get_header`, but using some options instead of the book's ones
+    /// (e.g. options that only apply to a chapter)
+    #[doc(hidden)]
+    pub fn get_header_with<F>(
+        &self,
+        options: &BookOptions,
+        header: Header,
+        n: i32,
+        title: String,
         mut f: F,
     ) -> Result<HeaderData>
     where
@@ -1188,21 +1943,29 @@ impl<'a> Book<'a> {
         if !title.is_empty() {
             data.insert(format!("has_{header_type}_title"), true.into());
         }
-        let number = self.get_header_number(header, n)?;
-        let header_name = self
-            .options
+        let number = Self::header_number(options, header, n)?;
+        let header_name = options
             .get_str(&format!("rendering.{header_type}"))
             .map(|s| s.to_owned())
-            .unwrap_or_else(|_| lang::get_str(self.options.get_str("lang").unwrap(), header_type));
+            .unwrap_or_else(|_| lang::get_str(options.get_str("lang").unwrap(), header_type));
 
         data.insert(format!("{header_type}_title"), title.clone().into());
         data.insert(header_type.into(), header_name.clone().into());
         data.insert("number".into(), number.clone().into());
 
-        let res = self.registry.get_template(&format!("rendering.{header_type}.template"))
-            .expect(&format!("Error accessing template rendering.{header_type}.template"))
-            .render(&data)
-            .to_string()?;
+        let template_key = format!("rendering.{header_type}.template");
+        let template = options.get_str(&template_key).unwrap();
+        let res = if template == self.options.get_str(&template_key).unwrap() {
+            self.registry.get_template(&template_key)
+                .expect(&format!("Error accessing template {template_key}"))
+                .render(&data)
+                .to_string()?
+        } else {
+            // Template only set for this chapter, so not registered
+            self.compile_str(template, &self.source, &template_key)?
+                .render(&data)
+                .to_string()?
+        };
         Ok(HeaderData {
             text: res,
             number,
@@ -1229,6 +1992,113 @@ impl<'a> Book<'a> {
         self.get_header(Header::Part, n, title, f)
     }
 
+    /// Returns where notes must be displayed, according to `rendering.notes`
+    #[doc(hidden)]
+    pub fn get_notes(&self) -> Notes {
+        match self.options.get_str("rendering.notes").unwrap() {
+            "footnotes" => Notes::Footnotes,
+            "chapter_end" => Notes::ChapterEnd,
+            "book_end" => Notes::BookEnd,
+            "side" => Notes::Side,
+            value => {
+                error!("{}", t!("error.notes", value = value));
+                Notes::Footnotes
+            }
+        }
+    }
+
+    /// Returns true if notes numbering must restart at each chapter, according to
+    /// `rendering.notes.numbering`
+    #[doc(hidden)]
+    pub fn notes_per_chapter(&self) -> bool {
+        match self.options.get_str("rendering.notes.numbering").unwrap() {
+            "chapter" => true,
+            "book" => false,
+            value => {
+                error!("{}", t!("error.notes_numbering", value = value));
+                true
+            }
+        }
+    }
+
+    /// Returns true if content with this condition must be rendered for the given
+    /// format, according to the format key and to the `rendering.edition` option
+    #[doc(hidden)]
+    pub fn is_condition_met(&self, condition: &Condition, format: &str) -> bool {
+        condition.is_met(format, self.options.get_str("rendering.edition").ok())
+    }
+
+    /// Returns the chapters that must be rendered for the given format, i.e. all chapters
+    /// except those whose condition excludes it
+    #[doc(hidden)]
+    pub fn chapters_for(&self, format: &str) -> Vec<&Chapter> {
+        self.chapters
+            .iter()
+            .filter(|c| {
+                c.condition
+                    .as_ref()
+                    .map_or(true, |condition| self.is_condition_met(condition, format))
+            })
+            .collect()
+    }
+
+    /// Visits the content of all chapters, e.g. to collect information about it.
+    pub fn visit_chapters<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+        for chapter in &self.chapters {
+            visitor.visit_chapter(chapter);
+        }
+    }
+
+    /// Modifies the content of all chapters in place.
+    ///
+    /// This must be called after the book is loaded and before it is rendered.
+    pub fn visit_chapters_mut<V: VisitorMut + ?Sized>(&mut self, visitor: &mut V) {
+        for chapter in &mut self.chapters {
+            visitor.visit_chapter_mut(chapter);
+        }
+        self.update_features();
+    }
+
+    /// Transforms all chapters.
+    ///
+    /// This must be called after the book is loaded and before it is rendered.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crowbook::{Book, Fold, Token};
+    ///
+    /// /// Removes all images
+    /// struct NoImages;
+    /// impl Fold for NoImages {
+    ///     fn fold_token(&mut self, token: Token) -> Vec<Token> {
+    ///         match token {
+    ///             Token::Image(..) | Token::StandaloneImage(..) => vec![],
+    ///             token => self.walk_token(token),
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let mut book = Book::new();
+    /// book.read_config("title: Foo\n".as_bytes()).unwrap();
+    /// book.fold_chapters(&mut NoImages);
+    /// ```
+    pub fn fold_chapters<F: Fold + ?Sized>(&mut self, folder: &mut F) {
+        let chapters = mem::take(&mut self.chapters);
+        self.chapters = chapters
+            .into_iter()
+            .map(|chapter| folder.fold_chapter(chapter))
+            .collect();
+        self.update_features();
+    }
+
+    /// Updates the features used by the book after chapters have been modified
+    fn update_features(&mut self) {
+        for chapter in &self.chapters {
+            self.features = self.features | Features::from_tokens(&chapter.content);
+        }
+    }
+
     /// Returns a `Map of Key/Value` (used by `Upon` for templating), to be used (and completed)
     /// by renderers. It fills it with the metadata options.
     ///
@@ -1297,6 +2167,36 @@ impl<'a> Book<'a> {
         Ok(m)
     }
 
+    /// Returns the values of the variables that can be used in chapter text (e.g.
+    /// `{{version}}`), taken from the same map that is used for templates.
+    ///
+    /// Metadata are rendered by `f`, other values (e.g. `crowbook_version` or
+    /// `loc_*` strings) are only escaped by `escape`.
+    #[doc(hidden)]
+    pub fn get_variables<F, E>(&self, f: F, escape: E) -> Result<HashMap<String, String>>
+    where
+        F: FnMut(&str) -> Result<String>,
+        E: Fn(&str) -> String,
+    {
+        let metadata: Vec<String> = self
+            .options
+            .get_metadata()
+            .iter()
+            .map(|key| key.replace('.', "_"))
+            .collect();
+        let mut variables = HashMap::new();
+        for (key, value) in self.get_metadata(f)? {
+            let value = match value {
+                upon::Value::String(s) if metadata.contains(&key) => s,
+                upon::Value::String(s) => escape(&s),
+                upon::Value::Bool(b) => b.to_string(),
+                _ => continue,
+            };
+            variables.insert(key, value);
+        }
+        Ok(variables)
+    }
+
     /// Calls upon::engine::compile, does NOT register the compiled template 
     pub fn compile_str<'s, O>(&self, template: &'s str, source: O, template_name: &str) -> Result<upon::Template<'_, 's>>
     where
@@ -1325,7 +2225,11 @@ impl<'a> Book<'a> {
     /// ---
     /// or
     /// ...
-    fn parse_yaml(&mut self, yaml_block: &String) {
+    ///
+    /// Keys starting with `chapter.` only apply to the current chapter: they are
+    /// not set and are returned instead, without this prefix.
+    fn parse_yaml(&mut self, yaml_block: &String) -> Hash {
+        let mut chapter_options = Hash::new();
         // Checks that this is valid YAML
         match YamlLoader::load_from_str(yaml_block) {
             Ok(docs) => {
@@ -1334,6 +2238,14 @@ impl<'a> Book<'a> {
                 if !docs.is_empty() && docs[0].as_hash().is_some() {
                     let hash = docs[0].as_hash().unwrap();
                     for (key, value) in hash {
+                        if key.as_str() == Some("order") {
+                            // Only used to sort chapters matched by a pattern
+                            continue;
+                        }
+                        if let Some(k) = key.as_str().and_then(|k| k.strip_prefix("chapter.")) {
+                            chapter_options.insert(Yaml::String(k.to_owned()), value.clone());
+                            continue;
+                        }
                         match self
                             .options
                             //todo: remove clone
@@ -1399,33 +2311,51 @@ impl<'a> Book<'a> {
                 );
             }
         }
+        chapter_options
     }
 
-    // Update the cleaner according to autoclean and lang options
-    fn update_cleaner(&mut self) {
+    /// Returns the options of the book, as modified by the options that only apply
+    /// to this chapter, or `None` if it doesn't set any
+    #[doc(hidden)]
+    pub fn chapter_options(&self, chapter: &Chapter) -> Option<BookOptions> {
+        if chapter.options.is_empty() {
+            return None;
+        }
+        let mut options = self.options.clone();
+        for (key, value) in &chapter.options {
+            // Errors have already been reported when parsing the chapter
+            let _ = options.set_yaml(key.clone(), value.clone());
+        }
+        Some(options)
+    }
+
+    /// Returns a cleaner according to autoclean and lang options
+    pub(crate) fn new_cleaner(options: &BookOptions) -> Box<dyn Cleaner> {
         let params = CleanerParams {
-            smart_quotes: self.options.get_bool("input.clean.smart_quotes").unwrap(),
-            ligature_dashes: self
-                .options
+            smart_quotes: options.get_bool("input.clean.smart_quotes").unwrap(),
+            ligature_dashes: options
                 .get_bool("input.clean.ligature.dashes")
                 .unwrap(),
-            ligature_guillemets: self
-                .options
+            ligature_guillemets: options
                 .get_bool("input.clean.ligature.guillemets")
                 .unwrap(),
         };
-        if self.options.get_bool("input.clean").unwrap() {
-            let lang = self.options.get_str("lang").unwrap().to_lowercase();
-            let cleaner: Box<dyn Cleaner> = if lang.starts_with("fr") {
+        if options.get_bool("input.clean").unwrap() {
+            let lang = options.get_str("lang").unwrap().to_lowercase();
+            if lang.starts_with("fr") {
                 Box::new(French::new(params))
             } else {
                 Box::new(Default::new(params))
-            };
-            self.cleaner = cleaner;
+            }
         } else {
-            self.cleaner = Box::new(Off);
+            Box::new(Off)
         }
     }
+
+    // Update the cleaner according to autoclean and lang options
+    fn update_cleaner(&mut self) {
+        self.cleaner = Self::new_cleaner(&self.options);
+    }
 }
 
 impl std::default::Default for Book<'_> {
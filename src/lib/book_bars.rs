@@ -18,195 +18,109 @@
 // Progress bars implementation. Moved into a different file so it is possible
 // to make some dependencies (incidacitf) optional.
 
-use crate::book::{Book, Crowbar, CrowbarState};
+use crate::book::Book;
+use crate::progress::{Event, ProgressListener};
 
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use rust_i18n::t;
 
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::Mutex;
 use std::time::Duration;
 
-/// Store the progress bars needed for the book
-pub struct Bars {
-    /// Whether or not to use emoji
-    pub emoji: bool,
-    /// Container for the progress bars
-    pub multibar: Option<Arc<MultiProgress>>,
-    /// Main progress bar (actually a spinner)
-    pub mainbar: Option<ProgressBar>,
-    /// Secondary bar
-    pub secondbar: Option<ProgressBar>,
-    // /// Guard for thread
-    // pub guard: Option<thread::JoinHandle<()>>,
-    /// Spinners for each renderier
-    pub spinners: Vec<ProgressBar>,
+/// The types of bars
+#[derive(Copy, Clone)]
+enum Crowbar {
+    Main,
+    Second,
+    Spinner,
 }
 
-impl Bars {
-    /// Create a new bars storage
-    pub fn new() -> Bars {
-        Bars {
-            emoji: false,
-            multibar: None,
-            mainbar: None,
-            secondbar: None,
-            // guard: None,
-            spinners: vec![],
-        }
-    }
+/// The state of bars
+#[derive(Copy, Clone)]
+enum CrowbarState {
+    Running,
+    Success,
+    Error,
 }
 
-impl Default for Bars {
-    fn default() -> Self {
-        Self::new()
-    }
+/// Progress bars displaying the events of a book
+pub struct ProgressBars {
+    /// Whether or not to use emoji
+    emoji: bool,
+    /// Container for the progress bars
+    multibar: MultiProgress,
+    /// Main progress bar (actually a spinner)
+    mainbar: ProgressBar,
+    /// Secondary bar, displaying the progress of chapters loading
+    secondbar: Mutex<Option<ProgressBar>>,
+    /// Spinners for each renderer, by format
+    spinners: Mutex<HashMap<String, ProgressBar>>,
 }
 
-/// Return the style of a bar
-
-impl Book<'_> {
-    /// Adds a progress bar where where info should be written.
-    ///
-    /// See [indicatif doc](https://docs.rs/indicatif) for more information.
-    pub fn private_add_progress_bar(&mut self, emoji: bool) {
-        self.bars.emoji = emoji;
-        let multibar = Arc::new(MultiProgress::new());
-        self.bars.multibar = Some(multibar);
-        let b = self
-            .bars
-            .multibar
-            .as_ref()
-            .unwrap()
-            .add(ProgressBar::new_spinner());
-        b.enable_steady_tick(Duration::from_millis(200));
-        self.bars.mainbar = Some(b);
-        self.bar_set_style(Crowbar::Main, CrowbarState::Running);
-    }
-
-    /// Sets a finished message to the progress bar, if it is set
-    pub fn bar_finish(&self, bar: Crowbar, state: CrowbarState, msg: &str) {
-        self.bar_set_style(bar, state);
-        let pb = match bar {
-            Crowbar::Main => {
-                if let Some(ref bar) = self.bars.mainbar {
-                    bar
-                } else {
-                    return;
-                }
-            }
-            Crowbar::Second => {
-                if let Some(ref bar) = self.bars.secondbar {
-                    bar
-                } else {
-                    return;
-                }
-            }
-            Crowbar::Spinner(i) => {
-                if i < self.bars.spinners.len() {
-                    &self.bars.spinners[i]
-                } else {
-                    return;
-                }
-            }
+impl ProgressBars {
+    /// Creates the main progress bar
+    pub fn new(emoji: bool) -> ProgressBars {
+        let multibar = MultiProgress::new();
+        let mainbar = multibar.add(ProgressBar::new_spinner());
+        mainbar.enable_steady_tick(Duration::from_millis(200));
+        let bars = ProgressBars {
+            emoji,
+            multibar,
+            mainbar,
+            secondbar: Mutex::new(None),
+            spinners: Mutex::new(HashMap::new()),
         };
+        bars.set_style(&bars.mainbar, Crowbar::Main, CrowbarState::Running);
+        bars
+    }
 
+    /// Sets a finished message to a bar
+    fn finish(&self, pb: &ProgressBar, bar: Crowbar, state: CrowbarState, msg: &str) {
+        self.set_style(pb, bar, state);
         match bar {
             Crowbar::Second => pb.finish_and_clear(),
             _ => pb.finish_with_message(msg.to_owned()),
         };
     }
 
-    /// Adds a secondary progress bar to display progress of book parsing
-    pub fn add_second_bar(&mut self, msg: &str, len: u64) {
-        if let Some(ref multibar) = self.bars.multibar {
-            let bar = multibar.add(ProgressBar::new(len));
-            self.bar_set_style(Crowbar::Second, CrowbarState::Running);
-            bar.set_message(msg.to_owned());
-            self.bars.secondbar = Some(bar);
+    /// Updates the secondary bar, creating it if needed
+    fn chapter_loaded(&self, file: &str, current: usize, total: usize) {
+        let mut secondbar = self.secondbar.lock().unwrap();
+        let pb = secondbar.get_or_insert_with(|| {
+            let pb = self.multibar.add(ProgressBar::new(total as u64));
+            self.set_style(&pb, Crowbar::Second, CrowbarState::Running);
+            pb
+        });
+        pb.set_position(current as u64);
+        pb.set_message(t!("ui.processing_file", file = file));
+        if current >= total {
+            self.finish(pb, Crowbar::Second, CrowbarState::Success, "");
+            *secondbar = None;
         }
     }
 
-    /// Increment second bar
-    pub fn inc_second_bar(&self) {
-        if let Some(ref bar) = self.bars.secondbar {
-            bar.inc(1);
-        }
+    /// Adds a spinner labeled with the format, and sets mainbar to "rendering"
+    fn format_started(&self, format: &str) {
+        self.mainbar.set_message(t!("ui.rendering"));
+        let pb = self.multibar.add(ProgressBar::new_spinner());
+        pb.enable_steady_tick(Duration::from_millis(200));
+        pb.set_message(t!("ui.rendering_format"));
+        pb.set_prefix(format!("{format}:"));
+        self.set_style(&pb, Crowbar::Spinner, CrowbarState::Running);
+        self.spinners.lock().unwrap().insert(format.to_owned(), pb);
     }
 
-    /// Adds a spinner labeled key to the multibar, and set mainbar to "rendering"
-    pub fn add_spinner_to_multibar(&mut self, key: &str) -> usize {
-        if let Some(ref multibar) = self.bars.multibar {
-            if let Some(ref mainbar) = self.bars.mainbar {
-                mainbar.set_message(t!("ui.rendering"));
-            }
-
-            let bar = multibar.add(ProgressBar::new_spinner());
-            bar.enable_steady_tick(Duration::from_millis(200));
-            bar.set_message(t!("ui.waiting"));
-            bar.set_prefix(format!("{key}:"));
-            let i = self.bars.spinners.len();
-            self.bars.spinners.push(bar);
-            self.bar_set_style(Crowbar::Spinner(i), CrowbarState::Running);
-
-            i
-        } else {
-            0
+    /// Finishes the spinner of a format
+    fn format_finished(&self, format: &str, state: CrowbarState, msg: &str) {
+        if let Some(pb) = self.spinners.lock().unwrap().remove(format) {
+            self.finish(&pb, Crowbar::Spinner, state, msg);
         }
     }
 
-    pub fn bar_set_message(&self, bar: Crowbar, msg: &str) {
-        let bar = match bar {
-            Crowbar::Main => {
-                if let Some(ref bar) = self.bars.mainbar {
-                    bar
-                } else {
-                    return;
-                }
-            }
-            Crowbar::Second => {
-                if let Some(ref bar) = self.bars.secondbar {
-                    bar
-                } else {
-                    return;
-                }
-            }
-            Crowbar::Spinner(i) => {
-                if i < self.bars.spinners.len() {
-                    &self.bars.spinners[i]
-                } else {
-                    return;
-                }
-            }
-        };
-        bar.set_message(msg.to_owned());
-    }
-
     /// Sets the style of a  bar
-    fn bar_set_style(&self, bar: Crowbar, state: CrowbarState) {
-        let pb = match bar {
-            Crowbar::Main => {
-                if let Some(ref bar) = self.bars.mainbar {
-                    bar
-                } else {
-                    return;
-                }
-            }
-            Crowbar::Second => {
-                if let Some(ref bar) = self.bars.secondbar {
-                    bar
-                } else {
-                    return;
-                }
-            }
-            Crowbar::Spinner(i) => {
-                if i < self.bars.spinners.len() {
-                    &self.bars.spinners[i]
-                } else {
-                    return;
-                }
-            }
-        };
-        let emoji = self.bars.emoji;
+    fn set_style(&self, pb: &ProgressBar, bar: Crowbar, state: CrowbarState) {
+        let emoji = self.emoji;
         let mut style = match bar {
             Crowbar::Second => ProgressStyle::default_bar(),
             _ => ProgressStyle::default_spinner(),
@@ -218,9 +132,9 @@ impl Book<'_> {
             CrowbarState::Error => "red",
         };
         let tick_chars = match (bar, emoji) {
-            (Crowbar::Main, false) | (Crowbar::Spinner(_), false) => "-\\|/",
+            (Crowbar::Main, false) | (Crowbar::Spinner, false) => "-\\|/",
             (Crowbar::Main, true) => "🕛🕐🕑🕒🕓🕔🕔🕕🕖🕗🕘🕘🕙🕚",
-            (Crowbar::Spinner(_), true) => "◐◓◑◒",
+            (Crowbar::Spinner, true) => "◐◓◑◒",
             (_, _) => "",
         };
         let end_tick = match (state, emoji) {
@@ -240,7 +154,7 @@ impl Book<'_> {
             bar => {
                 style = style.tick_chars(&format!("{tick_chars}{end_tick}"));
                 match bar {
-                    Crowbar::Spinner(_) => {
+                    Crowbar::Spinner => {
                         style = style
                             .template(&format!(
                                 "{{spinner:.bold.{color}}} {{prefix}} {{wide_msg}}"
@@ -259,15 +173,53 @@ impl Book<'_> {
     }
 }
 
-impl Drop for Book<'_> {
+impl ProgressListener for ProgressBars {
+    fn on_event(&self, event: &Event) {
+        match *event {
+            Event::Status(ref msg) => self.mainbar.set_message(msg.clone()),
+            Event::ChapterLoaded {
+                ref file,
+                current,
+                total,
+            } => self.chapter_loaded(file, current, total),
+            Event::FormatStarted { ref format } => self.format_started(format),
+            Event::FormatFinished {
+                ref format,
+                ref path,
+            } => {
+                let path = path.as_deref().unwrap_or(format);
+                self.format_finished(format, CrowbarState::Success, &t!("ui.generated", path = path))
+            }
+            Event::FormatFailed {
+                ref format,
+                ref error,
+            } => self.format_finished(format, CrowbarState::Error, error),
+            Event::Warning(_) => (),
+            Event::Error(ref msg) => {
+                self.finish(&self.mainbar, Crowbar::Main, CrowbarState::Error, msg)
+            }
+            Event::Finished => self.finish(
+                &self.mainbar,
+                Crowbar::Main,
+                CrowbarState::Success,
+                &t!("ui.finished"),
+            ),
+        }
+    }
+}
+
+impl Drop for ProgressBars {
     fn drop(&mut self) {
-        if let Some(ref bar) = self.bars.secondbar {
+        if let Some(ref bar) = *self.secondbar.lock().unwrap() {
             bar.finish_and_clear();
         }
-        if let Some(ref bar) = self.bars.mainbar {
-            bar.finish();
-            // let guard = mem::replace(&mut self.bars.guard, None);
-            // guard.unwrap().join().unwrap();
-        }
+        self.mainbar.finish();
+    }
+}
+
+impl Book<'_> {
+    /// Adds a progress bar where where info should be written.
+    pub fn private_add_progress_bar(&mut self, emoji: bool) {
+        self.add_progress_listener(ProgressBars::new(emoji));
     }
 }
@@ -17,33 +17,8 @@
 
 // Progress bars non-implementation. Used when indicatif is not compiled.
 
-use crate::book::{Book, Crowbar, CrowbarState};
-
-/// Dummy bars implementation
-pub struct Bars {}
-
-impl Bars {
-    pub fn new() -> Bars {
-        Bars {}
-    }
-}
+use crate::book::Book;
 
 impl Book<'_> {
     pub fn private_add_progress_bar(&mut self, _: bool) {}
-
-    /// Sets a finished message to the progress bar, if it is set
-    pub fn bar_finish(&self, _: Crowbar, _: CrowbarState, _: &str) {}
-
-    /// Adds a secondary progress bar to display progress of book parsing
-    pub fn add_second_bar(&mut self, _: &str, _: u64) {}
-
-    /// Increment second bar
-    pub fn inc_second_bar(&self) {}
-
-    /// Adds a spinner labeled key to the multibar, and set mainbar to "rendering"
-    pub fn add_spinner_to_multibar(&mut self, _: &str) -> usize {
-        0
-    }
-
-    pub fn bar_set_message(&self, _: Crowbar, _: &str) {}
 }
@@ -17,6 +17,7 @@
 
 use crate::book::Book;
 use crate::error::{Error, Result, Source};
+use crate::sink::OutputSink;
 
 use std::fs::File;
 use std::io::Write;
@@ -45,26 +46,41 @@ pub trait BookRenderer: Sync {
         // Not optimal but avoid creating an empty file if it fails
         let mut content = vec![];
         self.render(book, &mut content)?;
-        let mut file = File::create(path).map_err(|err| {
-            Error::default(
-                Source::empty(),
-                t!(
-                    "error.renderer.file_creation",
-                    file = path.display(),
-                    err = err
-                ),
-            )
-        })?;
-        file.write_all(&content).map_err(|err| {
-            Error::default(
-                Source::empty(),
-                t!(
-                    "error.renderer.write",
-                    file = path.display(),
-                    err = err
-                ),
-            )
-        })?;
-        Ok(())
+        write_to_file(path, &content)
     }
+
+    /// Render the book, writing the files it produces to `sink`.
+    ///
+    /// The default implementation calls `render` and writes the result to a single file,
+    /// named by `auto_path(name)`. Renderers producing several files override it.
+    fn render_to_sink(&self, book: &Book, name: &str, sink: &mut dyn OutputSink) -> Result<()> {
+        let mut content = vec![];
+        self.render(book, &mut content)?;
+        sink.write_file(&self.auto_path(name)?, &content)
+    }
+}
+
+/// Creates a file and writes some content to it
+pub(crate) fn write_to_file(path: &Path, content: &[u8]) -> Result<()> {
+    let mut file = File::create(path).map_err(|err| {
+        Error::default(
+            Source::empty(),
+            t!(
+                "error.renderer.file_creation",
+                file = path.display(),
+                err = err
+            ),
+        )
+    })?;
+    file.write_all(content).map_err(|err| {
+        Error::default(
+            Source::empty(),
+            t!(
+                "error.renderer.write",
+                file = path.display(),
+                err = err
+            ),
+        )
+    })?;
+    Ok(())
 }
@@ -1,12 +1,17 @@
 use crate::book::Book;
 use crate::bookoption::BookOption;
 use crate::error::{Error, Result, Source};
+use crate::filter;
 use crate::style;
+use crate::theme::Theme;
+use crate::vfs::{BookSource, FileSystem};
 
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use yaml_rust::yaml::Hash;
 use yaml_rust::{Yaml, YamlLoader};
 use rust_i18n::t;
 
@@ -34,11 +39,17 @@ output.html:path                    # {output_html}
 output.html.dir:path                # {output_html_dir}
 output.tex:path                     # {output_tex}
 output.pdf:path                     # {output_pdf}
+output.json:path                    # {output_json}
 output.base_path:path:\"\"            # {output_base_path}
 
 # {render_opt}
 rendering.highlight:str:syntect                                      # {rendering_highlight}
 rendering.highlight.theme:str:InspiredGitHub                         # {rendering_highlight_theme}
+rendering.highlight.theme_file:path                                  # {rendering_highlight_theme_file}
+rendering.highlight.dark_theme:str                                   # {rendering_highlight_dark_theme}
+rendering.highlight.dark_theme_file:path                             # {rendering_highlight_dark_theme_file}
+rendering.highlight.syntaxes:path                                    # {rendering_highlight_syntaxes}
+rendering.highlight.classes:bool:false                               # {rendering_highlight_classes}
 rendering.initials:bool:false                                        # {rendering_initials}
 rendering.inline_toc:bool:false                                      # {inline_toc}
 rendering.inline_toc.name:str:\"{{{{loc_toc}}}}\"                        # {toc_name}
@@ -51,12 +62,16 @@ rendering.part.reset_counter:bool:true                                      # {r
 rendering.chapter.template:str:\"{{{{number}}}}. {{{{chapter_title}}}}\" # {chapter_template}
 
 rendering.part.template:str:\"{{{{number}}}}. {{{{part_title}}}}\" # {part_template}
+rendering.notes:str:footnotes                                        # {rendering_notes}
+rendering.notes.numbering:str:chapter                                # {rendering_notes_numbering}
+rendering.edition:str                                                # {rendering_edition}
 
 
 
 
 # {special_ops}
 import:path                  # {import_config}
+theme:str                    # {theme}
 
 # {html_opt}
 html.icon:path                      # {html_icon}
@@ -72,6 +87,8 @@ html.highlight.js:tpl               # {highlight_js}
 html.highlight.css:tpl              # {highlight_css}
 html.side_notes:bool:false          # {side_notes}
 html.escape_nb_spaces:bool:true     # {nb_spaces}
+html.images.max_width:int           # {html_img_max_width}
+html.images.max_height:int          # {html_img_max_height}
 html.chapter.template:str:\"<h1 id = 'link-{{{{link}}}}'>{{% if has_number %}}<span class = 'chapter-header'>{{{{header}}}} {{{{number}}}}</span>{{% if has_title %}}<br />{{% endif %}}{{% endif %}}{{{{title}}}}</h1>\" # {html_chapter_template}
 html.part.template:str:\"<h2 class = 'part'>{{{{header}}}} {{{{number}}}}</h2> <h1 id = 'link-{{{{link}}}}' class = 'part'>{{{{title}}}}</h1>\" # {html_part_template}
 
@@ -83,6 +100,10 @@ html.standalone.js:tpl                  # {single_js}
 
 # {html_dir_opt}
 html.dir.template:tpl               # {html_dir_template}
+html.dir.search:bool:false          # {html_dir_search}
+html.dir.search.js:tpl              # {html_dir_search_js}
+html.dir.images.srcset:str          # {html_dir_srcset}
+html.dir.force:bool:false           # {html_dir_force}
 
 # {epub_opt}
 epub.version:int:2                  # {epub_ver}
@@ -93,6 +114,15 @@ epub.chapter.xhtml:tpl              # {chapter_xhtml}
 epub.titlepage.xhtml:tpl            # {titlepage_xhtml}
 epub.toc.extras:bool:true           # {epub_toc}
 epub.escape_nb_spaces:bool:true     # {nb_spaces}
+epub.images.max_width:int           # {epub_img_max_width}
+epub.images.max_height:int          # {epub_img_max_height}
+epub.images.rasterize_svg:bool:false # {epub_img_svg}
+epub.accessibility.mode:strvec      # {epub_a11y_mode}
+epub.accessibility.features:strvec  # {epub_a11y_features}
+epub.accessibility.hazards:strvec   # {epub_a11y_hazards}
+epub.accessibility.summary:str      # {epub_a11y_summary}
+epub.accessibility.conforms_to:str  # {epub_a11y_conforms_to}
+epub.accessibility.page_source:str  # {epub_a11y_page_source}
 
 # {tex_opt}
 tex.cover:bool:false                # {tex_cover}
@@ -112,6 +142,8 @@ tex.title:bool:true                 # {tex_title}
 tex.font.size:int                   # {tex_font_size}
 tex.hyperref:bool:true              # {tex_hyperref}
 tex.stdpage:bool:false              # {tex_stdpage}
+tex.images.max_width:int            # {tex_img_max_width}
+tex.images.max_height:int           # {tex_img_max_height}
 
 
 # {rs_opt}
@@ -122,6 +154,12 @@ resources.base_path.links:path       # {rs_links}
 resources.base_path.images:path:.    # {rs_img}
 resources.base_path.files:path:.     # {rs_base_files}
 resources.base_path.templates:path:. # {rs_tmpl}
+resources.images.max_width:int       # {rs_img_max_width}
+resources.images.max_height:int      # {rs_img_max_height}
+resources.images.quality:int:85      # {rs_img_quality}
+resources.fetch_remote:bool:false    # {rs_fetch_remote}
+resources.fetch_remote.timeout:int:30 # {rs_fetch_timeout}
+resources.fetch_remote.offline:bool:false # {rs_fetch_offline}
 
 # {input_opt}    #[serde(flatten)]
 
@@ -130,6 +168,8 @@ input.clean.smart_quotes:bool:true  # {smart_quotes}
 input.clean.ligature.dashes:bool:false # {ligature_dashes}
 input.clean.ligature.guillemets:bool:false # {ligature_guillemets}
 input.yaml_blocks:bool:false        # {yaml}
+input.variables:bool:false          # {variables}
+filters:strvec                      # {filters}
 
 
 # {crowbook_opt}
@@ -137,6 +177,8 @@ crowbook.html_as_text:bool:true     # {html_as_text}
 crowbook.files_mean_chapters:bool   # {files_mean_chapters}
 crowbook.markdown.superscript:bool:false  # {superscript}
 crowbook.temp_dir:path:             # {tmp_dir}
+crowbook.cache:bool:false           # {cache}
+crowbook.cache_dir:path:.crowbook-cache # {cache_dir}
 crowbook.zip.command:str:zip        # {zip}
 
 # {deprecated_opt}
@@ -159,7 +201,7 @@ html.display_chapter:alias:html_single.one_chapter  # {renamed}
 temp_dir:alias:crowbook.temp_dir                    # {renamed}
 zip.command:alias:crowbook.zip.command              # {renamed}
 verbose:alias:crowbook.verbose                      # {renamed}
-html.script:alias:html_singe.js                     # {renamed}
+html.script:alias:html_single.js                    # {renamed}
 html.print_css:alias:html.css.print                 # {renamed}
 html.template:alias:html_single.html                # {renamed}
 html_dir.script:alias:html_dir.js                   # {renamed}
@@ -232,11 +274,17 @@ output.odt:path                                     # {removed}
                                          output_html = t!("opt.output_html"),
                                          output_tex = t!("opt.output_tex"),
                                          output_pdf = t!("opt.output_pdf"),
+                                         output_json = t!("opt.output_json"),
                                          output_html_dir = t!("opt.output_html_dir"),
                                          output_base_path = t!("opt.output_base_path"),
 
                                          rendering_highlight = t!("opt.rendering_highlight"),
                                          rendering_highlight_theme = t!("opt.rendering_highlight_theme"),
+                                         rendering_highlight_theme_file = t!("opt.rendering_highlight_theme_file"),
+                                         rendering_highlight_dark_theme = t!("opt.rendering_highlight_dark_theme"),
+                                         rendering_highlight_dark_theme_file = t!("opt.rendering_highlight_dark_theme_file"),
+                                         rendering_highlight_syntaxes = t!("opt.rendering_highlight_syntaxes"),
+                                         rendering_highlight_classes = t!("opt.rendering_highlight_classes"),
                                          rendering_initials = t!("opt.rendering_initials"),
                                          inline_toc = t!("opt.inline_toc"),
                                          toc_name = t!("opt.toc_name"),
@@ -245,11 +293,15 @@ output.odt:path                                     # {removed}
                                          chapter = t!("opt.chapter"),
                                          chapter_template = t!("opt.chapter_template"),
                                          part_template = t!("opt.part_template"),
+                                         rendering_notes = t!("opt.rendering_notes"),
+                                         rendering_notes_numbering = t!("opt.rendering_notes_numbering"),
+                                         rendering_edition = t!("opt.rendering_edition"),
                                          roman_numerals_parts = t!("opt.roman_numeral_parts"),
                                          roman_numerals_chapters = t!("opt.roman_numerals_chapters"),
                                          reset_counter = t!("opt.reset_counter"),
 
                                          import_config = t!("opt.import"),
+                                         theme = t!("opt.theme"),
 
                                          html_icon = t!("opt.html_icon"),
                                          html_header = t!("opt.html_header"),
@@ -263,6 +315,11 @@ output.odt:path                                     # {removed}
                                          highlight_css = t!("opt.highlight_css"),
                                          side_notes = t!("opt.side_notes"),
                                          nb_spaces = t!("opt.nb_spaces"),
+                                         html_img_max_width = t!("opt.html_img_max_width"),
+                                         html_img_max_height = t!("opt.html_img_max_height"),
+                                         epub_img_max_width = t!("opt.epub_img_max_width"),
+                                         epub_img_max_height = t!("opt.epub_img_max_height"),
+                                         epub_img_svg = t!("opt.epub_img_svg"),
                                          nb_spaces_tex = t!("opt.nb_spaces_tex"),
 
                                          one_chapter = t!("opt.one_chapter"),
@@ -272,6 +329,10 @@ output.odt:path                                     # {removed}
                                          html_chapter_template = t!("opt.html_chapter_template"),
                                          html_part_template = t!("opt.html_part_template"),
                                          html_dir_template = t!("opt.html_dir_template"),
+                                         html_dir_search = t!("opt.html_dir_search"),
+                                         html_dir_search_js = t!("opt.html_dir_search_js"),
+                                         html_dir_srcset = t!("opt.html_dir_srcset"),
+                                         html_dir_force = t!("opt.html_dir_force"),
 
                                          epub_ver = t!("opt.epub_ver"),
                                          epub_css = t!("opt.epub_css"),
@@ -279,6 +340,12 @@ output.odt:path                                     # {removed}
                                          chapter_xhtml = t!("opt.chapter_xhtml"),
                                          titlepage_xhtml = t!("opt.titlepage_xhtml"),
                                          epub_toc = t!("opt.epub_toc"),
+                                         epub_a11y_mode = t!("opt.epub_a11y_mode"),
+                                         epub_a11y_features = t!("opt.epub_a11y_features"),
+                                         epub_a11y_hazards = t!("opt.epub_a11y_hazards"),
+                                         epub_a11y_summary = t!("opt.epub_a11y_summary"),
+                                         epub_a11y_conforms_to = t!("opt.epub_a11y_conforms_to"),
+                                         epub_a11y_page_source = t!("opt.epub_a11y_page_source"),
 
                                          tex_cover = t!("opt.tex_cover"),
                                          tex_links = t!("opt.tex_links"),
@@ -295,6 +362,8 @@ output.odt:path                                     # {removed}
                                          tex_font_size = t!("opt.tex_font_size"),
                                          tex_hyperref = t!("opt.tex_hyperref"),
                                          tex_stdpage = t!("opt.tex_stdpage"),
+                                         tex_img_max_width = t!("opt.tex_img_max_width"),
+                                         tex_img_max_height = t!("opt.tex_img_max_height"),
 
                                          rs_files = t!("opt.rs_files"),
                                          rs_out = t!("opt.rs_out"),
@@ -303,6 +372,12 @@ output.odt:path                                     # {removed}
                                          rs_img = t!("opt.rs_img"),
                                          rs_base_files = t!("opt.rs_base_files"),
                                          rs_tmpl = t!("opt.rs_tmpl"),
+                                         rs_img_max_width = t!("opt.rs_img_max_width"),
+                                         rs_img_max_height = t!("opt.rs_img_max_height"),
+                                         rs_img_quality = t!("opt.rs_img_quality"),
+                                         rs_fetch_remote = t!("opt.rs_fetch_remote"),
+                                         rs_fetch_timeout = t!("opt.rs_fetch_timeout"),
+                                         rs_fetch_offline = t!("opt.rs_fetch_offline"),
 
                                          autoclean = t!("opt.autoclean"),
                                          smart_quotes = t!("opt.smart"),
@@ -310,9 +385,13 @@ output.odt:path                                     # {removed}
                                          ligature_guillemets = t!("opt.guillemets"),
                                          superscript = t!("opt.superscript"),
                                          yaml = t!("opt.yaml"),
+                                         variables = t!("opt.variables"),
+                                         filters = t!("opt.filters"),
                                          html_as_text = t!("opt.html_as_text"),
                                          files_mean_chapters = t!("opt.files_mean_chapters"),
                                          tmp_dir = t!("opt.tmp_dir"),
+                                         cache = t!("opt.cache"),
+                                         cache_dir = t!("opt.cache_dir"),
                                          zip = t!("opt.zip"),
 
                                          tex_theme = t!("opt.tex_theme"),
@@ -347,7 +426,7 @@ output.odt:path                                     # {removed}
 /// let res = options.set("epub.version", "foo");
 /// assert!(res.is_err());
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BookOptions {
     options: HashMap<String, BookOption>,
     defaults: HashMap<String, BookOption>,
@@ -362,6 +441,19 @@ pub struct BookOptions {
     valid_str_vecs: Vec<&'static str>,
     metadata: Vec<String>,
 
+    /// Profiles, i.e. named sets of options that are only applied when selected
+    profiles: BTreeMap<String, Hash>,
+
+    /// Keys that were set but are not valid options, with where they were set
+    unknown_keys: Vec<(String, Source)>,
+
+    /// Deprecated keys that were set, with their replacement if there is one
+    /// and where they were set
+    deprecated_keys: Vec<(String, Option<String>, Source)>,
+
+    /// Files of the theme to copy with the output, with their path relative to it
+    theme_files: Vec<(String, PathBuf)>,
+
     /// Source for errors (unnecessary copy :/)
     #[doc(hidden)]
     pub source: Source,
@@ -369,6 +461,10 @@ pub struct BookOptions {
     /// Root path of the book (unnecessary copy :/)
     #[doc(hidden)]
     pub root: PathBuf,
+
+    /// Where the files of the book are read from
+    #[doc(hidden)]
+    pub book_source: Arc<dyn BookSource>,
 }
 
 impl BookOptions {
@@ -387,8 +483,13 @@ impl BookOptions {
             valid_tpls: vec![],
             valid_str_vecs: vec![],
             metadata: vec![],
+            profiles: BTreeMap::new(),
+            unknown_keys: vec![],
+            deprecated_keys: vec![],
+            theme_files: vec![],
             root: PathBuf::new(),
             source: Source::empty(),
+            book_source: Arc::new(FileSystem),
         };
 
         // Load default options and types from OPTIONS
@@ -473,7 +574,17 @@ impl BookOptions {
             if let Yaml::Array(array) = value {
                 let mut inner: Vec<String> = vec![];
                 for value in array.into_iter() {
-                    if let Yaml::String(value) = value {
+                    let command = match &value {
+                        Yaml::String(value) => Some(value.clone()),
+                        // A filter can also be given as a list of arguments
+                        Yaml::Array(args) if key == "filters" => args
+                            .iter()
+                            .map(|arg| arg.as_str().map(String::from))
+                            .collect::<Option<Vec<_>>>()
+                            .map(|args| filter::join_command(&args)),
+                        _ => None,
+                    };
+                    if let Some(value) = command {
                         inner.push(value);
                     } else {
                         return Err(Error::book_option(
@@ -519,6 +630,19 @@ impl BookOptions {
         } else if self.valid_strings.contains(&key.as_ref()) {
             // value is a string
             if let Yaml::String(value) = value {
+                if &key == "theme" {
+                    // special case: sets the options of the theme that are not set yet
+                    let mut theme = Theme::load(&value, &self.root, &*self.book_source)?;
+                    theme.options.options.retain(|key, _| {
+                        match (self.options.get(key), self.defaults.get(key)) {
+                            (None, _) => true,
+                            (Some(value), Some(default)) => value == default,
+                            (Some(_), None) => false,
+                        }
+                    });
+                    self.merge(&theme.options)?;
+                    self.theme_files.extend(theme.get_files(&*self.book_source)?);
+                }
                 Ok(self.options.insert(key, BookOption::String(value)))
             } else {
                 Err(Error::book_option(
@@ -546,6 +670,7 @@ impl BookOptions {
                         )
                     })?;
                     let mut book = Book::new();
+                    book.options.book_source = self.book_source.clone();
                     book.load_file(file)?;
                     self.merge(&book.options)?;
                     Ok(None)
@@ -640,6 +765,8 @@ impl BookOptions {
             }
         } else if self.deprecated.contains_key(&key) {
             let opt = self.deprecated[&key].clone();
+            self.deprecated_keys
+                .push((key.clone(), opt.clone(), self.source.clone()));
             if let Some(new_key) = opt {
                 warn!(
                     "{}",
@@ -656,6 +783,49 @@ impl BookOptions {
                     t!("opt.err_deprecated", key = &key),
                 ))
             }
+        } else if key == "profiles" {
+            // special case: a hash of profiles
+            if let Yaml::Hash(hash) = value {
+                for (name, profile) in hash {
+                    if let Yaml::String(name) = name {
+                        self.set_yaml(Yaml::String(format!("profiles.{name}")), profile)?;
+                    } else {
+                        return Err(Error::book_option(
+                            &self.source,
+                            t!("opt.expected_string", key = format!("{:?}", name)),
+                        ));
+                    }
+                }
+                Ok(None)
+            } else {
+                Err(Error::book_option(
+                    &self.source,
+                    t!(
+                        "opt.expected_profiles",
+                        value = format!("{:?}", &value)
+                    ),
+                ))
+            }
+        } else if let Some(name) = key.strip_prefix("profiles.") {
+            // key is a profile, it is only stored and applied when selected
+            match value {
+                Yaml::Hash(hash) => {
+                    self.profiles.insert(name.to_owned(), hash);
+                    Ok(None)
+                }
+                Yaml::Null => {
+                    self.profiles.insert(name.to_owned(), Hash::new());
+                    Ok(None)
+                }
+                _ => Err(Error::book_option(
+                    &self.source,
+                    t!(
+                        "opt.expected_profile",
+                        key = &key,
+                        value = format!("{:?}", &value)
+                    ),
+                )),
+            }
         } else if key.starts_with("metadata.") {
             // key is a custom metadata
             // value must be a string
@@ -674,6 +844,7 @@ impl BookOptions {
             }
         } else {
             // key not recognized
+            self.unknown_keys.push((key.clone(), self.source.clone()));
             Err(Error::book_option(
                 self.source.clone(),
                 t!("opt.unrecognized", key = &key),
@@ -741,6 +912,84 @@ impl BookOptions {
         &self.metadata
     }
 
+    /// Returns the options that are set to another value than their default one,
+    /// sorted by key
+    #[doc(hidden)]
+    pub fn get_non_defaults(&self) -> Vec<(&str, &BookOption)> {
+        let mut options: Vec<_> = self
+            .options
+            .iter()
+            .filter(|(key, value)| self.defaults.get(*key) != Some(*value))
+            .map(|(key, value)| (key.as_str(), value))
+            .collect();
+        options.sort_by_key(|(key, _)| *key);
+        options
+    }
+
+    /// Returns the names of the profiles defined in the book configuration
+    pub fn get_profiles(&self) -> Vec<&str> {
+        self.profiles.keys().map(|s| s.as_str()).collect()
+    }
+
+    /// Returns the options set by a profile
+    #[doc(hidden)]
+    pub fn get_profile(&self, name: &str) -> Option<&Hash> {
+        self.profiles.get(name)
+    }
+
+    /// Returns the keys that were set but don't match any valid option, along
+    /// with the file and line where they were set
+    pub fn get_unknown_keys(&self) -> &[(String, Source)] {
+        &self.unknown_keys
+    }
+
+    /// Returns the deprecated keys that were set, along with the key that
+    /// replaces them (or `None` if the option has been removed) and the file
+    /// and line where they were set
+    pub fn get_deprecated_keys(&self) -> &[(String, Option<String>, Source)] {
+        &self.deprecated_keys
+    }
+
+    /// Returns the files of the theme that must be copied with the output, with
+    /// their path relative to the output and their actual path
+    pub fn get_theme_files(&self) -> &[(String, PathBuf)] {
+        &self.theme_files
+    }
+
+    /// Returns the current name of a deprecated option
+    ///
+    /// Returns `None` if `key` is not deprecated, `Some(None)` if the option
+    /// has been removed and `Some(Some(new_key))` if it has been renamed,
+    /// following successive renamings.
+    pub fn deprecated_key(key: &str) -> Option<Option<&'static str>> {
+        let mut deprecated: HashMap<&str, Option<&str>> = HashMap::new();
+        let mut in_deprecated = false;
+        for (comment, k, option_type, default_value) in Self::options_to_vec() {
+            match k {
+                None => in_deprecated = comment.trim() == t!("opt.deprecated"),
+                Some(k) if in_deprecated => {
+                    let new_key = if option_type == Some("alias") {
+                        default_value
+                    } else {
+                        None
+                    };
+                    deprecated.insert(k, new_key);
+                }
+                _ => {}
+            }
+        }
+
+        let mut current = *deprecated.get(key)?;
+        // Bounded, in case some alias would point to itself
+        for _ in 0..deprecated.len() {
+            match current {
+                Some(new_key) if deprecated.contains_key(new_key) => current = deprecated[new_key],
+                _ => break,
+            }
+        }
+        Some(current)
+    }
+
     /// Gets an option
     #[doc(hidden)]
     pub fn get(&self, key: &str) -> Result<&BookOption> {
@@ -810,6 +1059,7 @@ impl BookOptions {
             | "output.html"
             | "output.html.dir"
             | "output.pdf"
+            | "output.json"
             | "output.tex" => {
                 // Translate according to output.base_path
                 let base = self.get_path("output.base_path").unwrap();
@@ -883,6 +1133,12 @@ impl BookOptions {
     /// Option is not inserted either if new value is equal to default.
     #[doc(hidden)]
     pub fn merge(&mut self, other: &BookOptions) -> Result<()> {
+        self.theme_files.extend(other.theme_files.iter().cloned());
+        for (name, profile) in &other.profiles {
+            self.profiles
+                .entry(name.clone())
+                .or_insert_with(|| profile.clone());
+        }
         for (key, value) in &other.options {
             // Check if option was already set, and if it was to default or to something else
             if self.defaults.contains_key(key) {
@@ -1005,6 +1261,56 @@ impl BookOptions {
         out
     }
 
+    /// Returns a commented-out sample of some options, suitable for a book
+    /// configuration file
+    ///
+    /// Options are displayed in the same order and sections as in `description`,
+    /// with their description and default value.
+    ///
+    /// # Example
+    /// ```
+    /// use crowbook::BookOptions;
+    /// let sample = BookOptions::sample(&["subtitle", "rendering.num_depth"]);
+    /// assert!(sample.contains("# rendering.num_depth: 1"));
+    /// ```
+    pub fn sample(keys: &[&str]) -> String {
+        let mut out = String::new();
+        let mut section = None;
+        for (comment, key, _, default) in Self::options_to_vec() {
+            let key = match key {
+                None => {
+                    section = Some(comment.trim());
+                    continue;
+                }
+                Some(key) if keys.contains(&key) => key,
+                _ => continue,
+            };
+            if let Some(header) = section.take() {
+                if !out.is_empty() {
+                    out.push('\n');
+                }
+                out.push_str(&format!("## {header}\n"));
+            }
+            out.push_str(&format!("# {}\n", comment.trim()));
+            match default {
+                Some(default) if !default.is_empty() => {
+                    out.push_str(&format!("# {key}: {default}\n"))
+                }
+                _ => out.push_str(&format!("# {key}:\n")),
+            }
+        }
+        out
+    }
+
+    /// Returns the keys of the options that are templates
+    pub fn templates() -> Vec<&'static str> {
+        Self::options_to_vec()
+            .into_iter()
+            .filter(|(_, _, option_type, _)| *option_type == Some("tpl"))
+            .filter_map(|(_, key, _, _)| key)
+            .collect()
+    }
+
     /// OPTIONS to a vec of tuples (comment, key, type, default value)
     #[allow(clippy::type_complexity)]
     fn options_to_vec() -> Vec<(
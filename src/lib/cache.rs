@@ -0,0 +1,124 @@
+// Copyright (C) 2016-2023 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Cache of intermediate results (parsed chapters, highlighted code), so
+//! they are not computed again on next builds
+
+use crate::book::Book;
+use crate::error::Result;
+use crate::misc::StableHasher;
+
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use rust_i18n::t;
+
+/// A content-addressed cache, stored in a directory.
+///
+/// Entries are files named after a hash of everything that was used to compute
+/// them (see `Cache::key`), so they never need to be invalidated: when the input
+/// changes, another entry is used. Failing to read or write the cache is not an
+/// error, the result is just computed again.
+#[derive(Debug, Clone, Default)]
+pub struct Cache {
+    dir: Option<PathBuf>,
+}
+
+impl Cache {
+    /// Creates a cache storing its entries in `dir`
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Cache {
+        Cache {
+            dir: Some(dir.into()),
+        }
+    }
+
+    /// Returns a cache that never stores anything
+    pub fn disabled() -> Cache {
+        Cache { dir: None }
+    }
+
+    /// Returns the cache of a book, in `crowbook.cache_dir`
+    ///
+    /// The cache is disabled unless `crowbook.cache` is `true`, and if the book
+    /// was not loaded from a file (e.g. it was built from strings, or read
+    /// from a `MemorySource`).
+    pub fn for_book(book: &Book) -> Cache {
+        let from_file = book.source.file.as_ref().is_some_and(|f| Path::new(f).is_file());
+        if !from_file || !book.options.get_bool("crowbook.cache").unwrap() {
+            return Cache::disabled();
+        }
+        Cache::new(PathBuf::from(book.options.get_path("crowbook.cache_dir").unwrap()).join("build"))
+    }
+
+    /// Returns true if entries are actually stored
+    pub fn is_enabled(&self) -> bool {
+        self.dir.is_some()
+    }
+
+    /// Computes the key of an entry from everything its content depends on
+    ///
+    /// The version of Crowbook is always part of the key, since the format of
+    /// entries might change. The hash doesn't depend on the toolchain Crowbook was
+    /// built with, so entries stay valid across builds of the same version.
+    pub fn key<H: Hash + ?Sized>(value: &H) -> String {
+        let mut hasher = StableHasher::default();
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        value.hash(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    }
+
+    /// Returns the content of an entry, if it is in the cache
+    pub fn get(&self, kind: &str, key: &str) -> Option<Vec<u8>> {
+        fs::read(self.dir.as_ref()?.join(kind).join(key)).ok()
+    }
+
+    /// Stores an entry
+    pub fn put(&self, kind: &str, key: &str, content: &[u8]) {
+        let dir = match self.dir {
+            Some(ref dir) => dir.join(kind),
+            None => return,
+        };
+        let file = dir.join(key);
+        // Go through a temporary file, so an interrupted build doesn't leave
+        // an incomplete entry
+        let tmp = file.with_extension("part");
+        let res = fs::create_dir_all(&dir)
+            .and_then(|_| fs::write(&tmp, content))
+            .and_then(|_| fs::rename(&tmp, &file));
+        if let Err(err) = res {
+            debug!(
+                "{}",
+                t!("cache.write", file = file.display(), error = err)
+            );
+        }
+    }
+
+    /// Returns the text stored in an entry, or computes it and stores it if
+    /// it is not in the cache
+    pub fn get_or_insert_with<F>(&self, kind: &str, key: &str, f: F) -> Result<String>
+    where
+        F: FnOnce() -> Result<String>,
+    {
+        if let Some(content) = self.get(kind, key).and_then(|c| String::from_utf8(c).ok()) {
+            return Ok(content);
+        }
+        let content = f()?;
+        self.put(kind, key, content.as_bytes());
+        Ok(content)
+    }
+}
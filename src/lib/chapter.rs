@@ -16,10 +16,16 @@
 // along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::number::Number;
-use crate::token::Token;
+use crate::token::{Condition, Token};
+
+use serde::{Deserialize, Serialize};
+use yaml_rust::yaml::Hash;
 
 /// Represents the content of a chapter.
-#[derive(Debug)]
+///
+/// Chapters can be serialized (e.g. for external filters); the chapter's
+/// `options` are not part of this representation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Chapter {
     /// The numbering scheme of this chapter.
     pub number: Number,
@@ -27,6 +33,16 @@ pub struct Chapter {
     pub filename: String,
     /// The (already parsed) content of this chapter
     pub content: Vec<Token>,
+    /// Condition set on the chapter line in the book configuration file, if any
+    pub condition: Option<Condition>,
+    /// Options that only apply to this chapter, set in its YAML block with
+    /// the `chapter.` prefix (e.g. `chapter.rendering.initials`)
+    #[serde(skip)]
+    pub options: Hash,
+    /// Whether the chapter is listed in the table of contents (`chapter.toc`)
+    pub toc: bool,
+    /// Additional class of the chapter in HTML and EPUB (`chapter.class`)
+    pub class: Option<String>,
 }
 
 impl Chapter {
@@ -41,6 +57,10 @@ impl Chapter {
             number,
             filename: filename.into(),
             content,
+            condition: None,
+            options: Hash::new(),
+            toc: true,
+            class: None,
         }
     }
 }
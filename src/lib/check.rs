@@ -0,0 +1,540 @@
+// Copyright (C) 2016-2023 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::book::Book;
+use crate::error::{Error, Source};
+use crate::misc;
+use crate::resource_handler::ResourceHandler;
+use crate::text_view::view_as_text;
+use crate::token::Token;
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+use rust_i18n::t;
+use serde::Serialize;
+
+/// Severity of a problem found when checking a book
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Something that is probably a mistake, but doesn't prevent rendering
+    Warning,
+    /// Something that is wrong and will (likely) cause rendering to fail or be incorrect
+    Error,
+}
+
+impl Severity {
+    /// Returns a (non-localized) identifier for this severity
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+/// A problem found when checking a book
+#[derive(Debug, Clone, Serialize)]
+pub struct Problem {
+    /// Severity of the problem
+    pub severity: Severity,
+    /// Kind of problem, e.g. "unknown_option" or "missing_image"
+    pub kind: &'static str,
+    /// File (and line, if known) where the problem was found
+    #[serde(flatten)]
+    pub source: Source,
+    /// Human-readable description of the problem
+    pub message: String,
+}
+
+/// Checks a book for problems, without rendering it.
+///
+/// # Example
+///
+/// ```
+/// use crowbook::{Book, Check};
+/// let mut book = Book::new();
+/// book.options.set("autor", "Joan Doe").unwrap_err();
+/// let check = Check::new(&book);
+/// assert!(check.has_errors());
+/// assert_eq!(check.problems()[0].kind, "unknown_option");
+/// ```
+pub struct Check {
+    problems: Vec<Problem>,
+}
+
+impl Check {
+    /// Runs all checks on a (loaded) book
+    pub fn new(book: &Book) -> Check {
+        let mut check = Check { problems: vec![] };
+        check.check_options(book);
+        check.check_resources(book);
+        check.check_chapters(book);
+        check
+    }
+
+    /// Creates a check report for a book that could not even be loaded
+    pub fn from_error(err: &Error) -> Check {
+        Check {
+            problems: vec![Problem {
+                severity: Severity::Error,
+                kind: "load",
+                source: Source::empty(),
+                message: format!("{err}"),
+            }],
+        }
+    }
+
+    /// Returns the list of problems that were found
+    pub fn problems(&self) -> &[Problem] {
+        &self.problems
+    }
+
+    /// Returns true if at least one problem is an error
+    pub fn has_errors(&self) -> bool {
+        self.count(Severity::Error) > 0
+    }
+
+    /// Returns the number of problems of a given severity
+    pub fn count(&self, severity: Severity) -> usize {
+        self.problems
+            .iter()
+            .filter(|p| p.severity == severity)
+            .count()
+    }
+
+    /// Returns the report as a JSON document
+    pub fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct Report<'a> {
+            errors: usize,
+            warnings: usize,
+            problems: &'a [Problem],
+        }
+
+        let report = Report {
+            errors: self.count(Severity::Error),
+            warnings: self.count(Severity::Warning),
+            problems: &self.problems,
+        };
+        serde_json::to_string_pretty(&report).unwrap()
+    }
+
+    fn add(&mut self, severity: Severity, kind: &'static str, source: &Source, message: String) {
+        self.problems.push(Problem {
+            severity,
+            kind,
+            source: source.clone(),
+            message,
+        });
+    }
+
+    /// Unknown or deprecated option keys
+    fn check_options(&mut self, book: &Book) {
+        for (key, source) in book.options.get_unknown_keys() {
+            self.add(
+                Severity::Error,
+                "unknown_option",
+                source,
+                t!("check.unknown_option", key = key).into_owned(),
+            );
+        }
+        for (key, new_key, source) in book.options.get_deprecated_keys() {
+            if let Some(new_key) = new_key {
+                self.add(
+                    Severity::Warning,
+                    "deprecated_option",
+                    source,
+                    t!("check.deprecated_option", key = key, new_key = new_key).into_owned(),
+                );
+            } else {
+                self.add(
+                    Severity::Error,
+                    "removed_option",
+                    source,
+                    t!("check.removed_option", key = key).into_owned(),
+                );
+            }
+        }
+    }
+
+    /// Files referenced by options
+    fn check_resources(&mut self, book: &Book) {
+        for key in &["cover", "html.icon"] {
+            if let Ok(path) = book.options.get_path(key) {
+                if !book.options.book_source.is_file(Path::new(&path)) {
+                    self.add(
+                        Severity::Error,
+                        "missing_resource",
+                        &book.source,
+                        t!("check.missing_resource", key = key, file = path).into_owned(),
+                    );
+                }
+            }
+        }
+        if let Ok(files) = book.options.get_str_vec("resources.files") {
+            let base = book.options.get_path("resources.base_path.files").unwrap();
+            for file in files {
+                let path = Path::new(&base).join(file);
+                if !book.options.book_source.is_file(&path) && !book.options.book_source.is_dir(&path) {
+                    self.add(
+                        Severity::Error,
+                        "missing_resource",
+                        &book.source,
+                        t!(
+                            "check.missing_resource",
+                            key = "resources.files",
+                            file = path.display()
+                        )
+                        .into_owned(),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Content of the chapters
+    fn check_chapters(&mut self, book: &Book) {
+        let filenames: HashSet<&str> = book
+            .chapters
+            .iter()
+            .map(|c| c.filename.as_str())
+            .filter(|f| !f.is_empty())
+            .collect();
+        let mut seen = HashSet::new();
+
+        for chapter in &book.chapters {
+            let source = if chapter.filename.is_empty() {
+                book.source.clone()
+            } else {
+                Source::new(misc::normalize(book.root.join(&chapter.filename)))
+            };
+
+            if !chapter.filename.is_empty() && !seen.insert(chapter.filename.as_str()) {
+                self.add(
+                    Severity::Warning,
+                    "duplicate_chapter",
+                    &book.source,
+                    t!("check.duplicate_chapter", file = &chapter.filename).into_owned(),
+                );
+                // No need to report the same problems twice
+                continue;
+            }
+
+            if chapter
+                .content
+                .iter()
+                .all(|token| matches!(token, Token::Header(..)))
+            {
+                self.add(
+                    Severity::Warning,
+                    "empty_chapter",
+                    &source,
+                    t!("check.empty_chapter").into_owned(),
+                );
+            }
+
+            let mut last_level = None;
+            self.check_tokens(book, &source, &filenames, &chapter.content, &mut last_level);
+
+            if !chapter.filename.is_empty() {
+                if let Ok(content) = book.options.book_source.read_to_string(&book.root.join(&chapter.filename)) {
+                    self.check_footnotes(&source, &content);
+                }
+            }
+        }
+    }
+
+    fn check_tokens(
+        &mut self,
+        book: &Book,
+        source: &Source,
+        filenames: &HashSet<&str>,
+        tokens: &[Token],
+        last_level: &mut Option<i32>,
+    ) {
+        for (i, token) in tokens.iter().enumerate() {
+            match *token {
+                Token::Header(level, _) => {
+                    if let Some(last) = *last_level {
+                        if level > last + 1 {
+                            self.add(
+                                Severity::Warning,
+                                "heading_jump",
+                                source,
+                                t!("check.heading_jump", from = last, to = level).into_owned(),
+                            );
+                        }
+                    }
+                    *last_level = Some(level);
+                }
+                Token::Paragraph(ref inner) => {
+                    let text = view_as_text(inner);
+                    let text = book.clean(text.as_str());
+                    // In a quotation spanning several paragraphs, each paragraph opens
+                    // with a quotation mark and only the last one closes it
+                    let continued = match (opening_quote(&text), tokens.get(i + 1)) {
+                        (Some(quote), Some(Token::Paragraph(next))) => {
+                            opening_quote(&book.clean(view_as_text(next).as_str())) == Some(quote)
+                        }
+                        _ => false,
+                    };
+                    if let Some(quote) = unbalanced_quote(&text).filter(|_| !continued) {
+                        self.add(
+                            Severity::Warning,
+                            "unbalanced_quotes",
+                            source,
+                            t!(
+                                "check.unbalanced_quotes",
+                                quote = quote,
+                                text = excerpt(&text)
+                            )
+                            .into_owned(),
+                        );
+                    }
+                }
+                Token::Image(ref url, _, ref inner)
+                | Token::StandaloneImage(ref url, _, ref inner) => {
+                    if ResourceHandler::is_local(url)
+                        && !has_scheme(url)
+                        && !book.options.book_source.is_file(Path::new(url))
+                    {
+                        self.add(
+                            Severity::Error,
+                            "missing_image",
+                            source,
+                            t!("check.missing_image", file = url).into_owned(),
+                        );
+                    }
+                    if view_as_text(inner).trim().is_empty() {
+                        self.add(
+                            Severity::Warning,
+                            "missing_alt",
+                            source,
+                            t!("check.missing_alt", file = url).into_owned(),
+                        );
+                    }
+                }
+                Token::Link(ref url, _, _) if !is_valid_link(book, filenames, url) => {
+                    self.add(
+                        Severity::Error,
+                        "broken_link",
+                        source,
+                        t!("check.broken_link", url = url).into_owned(),
+                    );
+                }
+                _ => {}
+            }
+            if let Some(inner) = token.inner() {
+                self.check_tokens(book, source, filenames, inner, last_level);
+            }
+        }
+    }
+
+    /// Footnotes are resolved (and unused definitions dropped) when parsing,
+    /// so this looks at the Markdown source instead.
+    fn check_footnotes(&mut self, source: &Source, content: &str) {
+        let mut definitions: Vec<(String, usize)> = vec![];
+        let mut references: Vec<(String, usize)> = vec![];
+        let mut fence: Option<&str> = None;
+
+        for (i, line) in content.lines().enumerate() {
+            let trimmed = line.trim_start();
+            if let Some(f) = fence {
+                if trimmed.starts_with(f) {
+                    fence = None;
+                }
+                continue;
+            } else if trimmed.starts_with("```") {
+                fence = Some("```");
+                continue;
+            } else if trimmed.starts_with("~~~") {
+                fence = Some("~~~");
+                continue;
+            }
+
+            let mut rest = trimmed;
+            if let Some(name) = footnote_name(trimmed) {
+                let after = &trimmed[name.len() + 3..];
+                if let Some(after) = after.strip_prefix(':') {
+                    definitions.push((name.to_lowercase(), i + 1));
+                    rest = after;
+                }
+            }
+            let rest = strip_code_spans(rest);
+            let mut rest = rest.as_str();
+            while let Some(pos) = rest.find("[^") {
+                rest = &rest[pos..];
+                if let Some(name) = footnote_name(rest) {
+                    references.push((name.to_lowercase(), i + 1));
+                    rest = &rest[name.len() + 3..];
+                } else {
+                    rest = &rest[2..];
+                }
+            }
+        }
+
+        for (name, line) in &references {
+            if !definitions.iter().any(|(n, _)| n == name) {
+                let mut source = source.clone();
+                source.set_line(*line as u32);
+                self.add(
+                    Severity::Error,
+                    "undefined_footnote",
+                    &source,
+                    t!("check.undefined_footnote", name = name).into_owned(),
+                );
+            }
+        }
+        for (name, line) in &definitions {
+            if !references.iter().any(|(n, _)| n == name) {
+                let mut source = source.clone();
+                source.set_line(*line as u32);
+                self.add(
+                    Severity::Warning,
+                    "unused_footnote",
+                    &source,
+                    t!("check.unused_footnote", name = name).into_owned(),
+                );
+            }
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for problem in &self.problems {
+            let severity = match problem.severity {
+                Severity::Error => t!("check.error"),
+                Severity::Warning => t!("check.warning"),
+            };
+            if problem.source.file.is_some() {
+                write!(f, "{}: ", problem.source)?;
+            }
+            writeln!(f, "{}: {} [{}]", severity, problem.message, problem.kind)?;
+        }
+        if self.problems.is_empty() {
+            write!(f, "{}", t!("check.no_problem"))
+        } else {
+            write!(
+                f,
+                "{}",
+                t!(
+                    "check.summary",
+                    errors = self.count(Severity::Error),
+                    warnings = self.count(Severity::Warning)
+                )
+            )
+        }
+    }
+}
+
+/// Returns the name of the footnote if `s` starts with `[^name]`
+fn footnote_name(s: &str) -> Option<&str> {
+    let s = s.strip_prefix("[^")?;
+    let end = s.find(']')?;
+    let name = &s[..end];
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+/// Removes `inline code` from a line
+fn strip_code_spans(s: &str) -> String {
+    s.split('`').step_by(2).collect::<Vec<_>>().join(" ")
+}
+
+/// Returns true if the URL starts with a scheme, e.g. `mailto:` or `data:`
+fn has_scheme(url: &str) -> bool {
+    match url.find(':') {
+        Some(pos) => {
+            pos > 1
+                && url[..pos]
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+        }
+        None => false,
+    }
+}
+
+/// Checks that a local link points to a chapter of the book or to an existing file
+fn is_valid_link(book: &Book, filenames: &HashSet<&str>, url: &str) -> bool {
+    if !ResourceHandler::is_local(url) || has_scheme(url) {
+        return true;
+    }
+    let path = url.split('#').next().unwrap();
+    if path.is_empty() {
+        return true;
+    }
+    let as_md = format!("{}", Path::new(path).with_extension("md").display()).replace('\\', "/");
+    if filenames.contains(path) || filenames.contains(as_md.as_str()) {
+        return true;
+    }
+    if Path::new(path).extension().map(|e| e == "md").unwrap_or(false) {
+        return false;
+    }
+    let files = &book.options.book_source;
+    [Path::new(path).to_path_buf(), book.root.join(path)]
+        .iter()
+        .any(|p| files.is_file(p) || files.is_dir(p))
+}
+
+/// Returns the quotation mark a paragraph starts with, if any
+fn opening_quote(text: &str) -> Option<char> {
+    text.trim_start()
+        .chars()
+        .next()
+        .filter(|c| ['"', '“', '«', '„'].contains(c))
+}
+
+/// Returns the first quotation mark that isn't balanced in the text, if any
+fn unbalanced_quote(text: &str) -> Option<char> {
+    let count = |c: char| text.chars().filter(|&x| x == c).count();
+    if count('"') % 2 != 0 {
+        return Some('"');
+    }
+    // German-style quotes use „ to open and “ to close
+    let pairs: &[(char, char)] = if text.contains('„') {
+        &[('„', '“'), ('«', '»')]
+    } else {
+        &[('“', '”'), ('«', '»')]
+    };
+    for &(open, close) in pairs {
+        let (n_open, n_close) = (count(open), count(close));
+        if n_open > n_close {
+            return Some(open);
+        } else if n_close > n_open {
+            return Some(close);
+        }
+    }
+    None
+}
+
+/// Returns the beginning of a paragraph, to help locate it
+fn excerpt(text: &str) -> String {
+    let text = text.trim();
+    if text.chars().count() > 40 {
+        let s: String = text.chars().take(40).collect();
+        format!("{s}…")
+    } else {
+        text.to_owned()
+    }
+}
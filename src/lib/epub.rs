@@ -17,13 +17,16 @@
 
 use crate::book::Header;
 use crate::book::Book;
+use crate::book::Notes;
 use crate::book_renderer::BookRenderer;
 use crate::error::{Error, Result, Source};
 use crate::html::HtmlRenderer;
+use crate::images::ImageProcessor;
 use crate::lang;
 use crate::parser::Parser;
 use crate::renderer::Renderer;
 use crate::resource_handler;
+use crate::syntax;
 use crate::templates::epub::*;
 use crate::templates::epub3;
 use crate::text_view::view_as_text;
@@ -31,16 +34,14 @@ use crate::token::Token;
 
 use crowbook_text_processing::escape;
 use epub_builder::{
-    EpubBuilder, EpubContent, EpubVersion, ReferenceType, ZipCommand, ZipCommandOrLibrary,
-    ZipLibrary,
+    EpubBuilder, EpubContent, EpubVersion, MetadataOpf, MetadataOpfV3, ReferenceType,
+    ZipCommand, ZipCommandOrLibrary, ZipLibrary,
 };
 use upon::Template;
 use rust_i18n::t;
 
 use std::borrow::Cow;
 use std::convert::{AsMut, AsRef};
-use std::fs;
-use std::fs::File;
 use std::io::Write;
 
 use std::path::Path;
@@ -53,6 +54,8 @@ pub struct EpubRenderer<'a> {
     html: HtmlRenderer<'a>,
     chapter_title: String,
     chapter_title_raw: String,
+    /// Page breaks of the print edition, with the index of the chapter they are in
+    pages: Vec<(usize, String)>,
 }
 
 impl<'a> EpubRenderer<'a> {
@@ -66,11 +69,24 @@ impl<'a> EpubRenderer<'a> {
         )?;
         html.handler.set_images_mapping(true);
         html.handler.set_base64(false);
+        html.handler
+            .set_image_processor(ImageProcessor::for_format(&book.options, "epub"));
+        html.format = "epub";
+        if book.options.get_i32("epub.version").unwrap() == 2 {
+            html.set_xhtml11();
+        }
+        match html.notes {
+            // Margin notes don't make sense in an EPUB, use pop-up footnotes instead
+            Notes::Side => html.notes = Notes::Footnotes,
+            Notes::BookEnd => html.notes_file = Some(String::from("notes.xhtml")),
+            _ => (),
+        }
         Ok(EpubRenderer {
             html,
             toc: vec![],
             chapter_title: String::new(),
             chapter_title_raw: String::new(),
+            pages: vec![],
         })
     }
 
@@ -99,7 +115,8 @@ impl<'a> EpubRenderer<'a> {
         let mut maker = EpubBuilder::new(wrapper)
             .map_err(|err| Error::render(Source::empty(), format!("{}", err)))?;
         maker.escape_html(false);
-        if self.html.book.options.get_i32("epub.version").unwrap() == 3 {
+        let epub3 = self.html.book.options.get_i32("epub.version").unwrap() == 3;
+        if epub3 {
             maker.epub_version(EpubVersion::V30);
         }
 
@@ -154,10 +171,15 @@ impl<'a> EpubRenderer<'a> {
         //                                     "toc"));
         // }
 
-        for (i, chapter) in self.html.book.chapters.iter().enumerate() {
+        for (i, chapter) in self.html.book.chapters_for(self.html.format).into_iter().enumerate() {
             self.html
                 .handler
                 .add_link(chapter.filename.as_str(), filenamer(i));
+            for id in syntax::listing_ids(&chapter.content) {
+                self.html
+                    .handler
+                    .add_link(format!("#{id}"), format!("{}#{id}", filenamer(i)));
+            }
         }
 
         // Write cover.xhtml (if needs be)
@@ -195,6 +217,7 @@ impl<'a> EpubRenderer<'a> {
         }
 
         // Write chapters
+        HtmlRenderer::init_variables(self)?;
         let template_chapter_src = self.html.book.get_template("epub.chapter.xhtml")?;
         let template_chapter = self.html.book.compile_str(
             template_chapter_src.as_ref(),
@@ -202,10 +225,10 @@ impl<'a> EpubRenderer<'a> {
             "epub.chapter.xhtml",
         )?;
         let mut rendered = vec![];
-        for (i, chapter) in self.html.book.chapters.iter().enumerate() {
-            let n = chapter.number;
+        for (i, chapter) in self.html.book.chapters_for(self.html.format).into_iter().enumerate() {
+            self.html.book.cancellation_token().check()?;
             let v = &chapter.content;
-            self.html.chapter_config(i, n, filenamer(i));
+            self.html.chapter_config(chapter, filenamer(i));
             let this_chapter = self.render_chapter(v, &template_chapter)?;
             rendered.push(this_chapter);
         }
@@ -230,6 +253,28 @@ impl<'a> EpubRenderer<'a> {
         }
         self.html.source = Source::empty();
 
+        for (name, content) in self.accessibility_metadata() {
+            // EPUB 3 uses `<meta property="...">content</meta>`, EPUB 2 `<meta name content>`
+            if epub3 {
+                maker.add_metadata_opf(Box::new(MetadataOpfV3::new(name.to_owned(), content)));
+            } else {
+                maker.add_metadata_opf(Box::new(MetadataOpf {
+                    name: name.to_owned(),
+                    content,
+                }));
+            }
+        }
+
+        // Write notes.xhtml if notes are displayed at the end of the book
+        if let Some(notes) = self.render_notes(&template_chapter)? {
+            let title = lang::get_str(lang, "notes");
+            let content = EpubContent::new("notes.xhtml", notes.as_bytes())
+                .title(escape::html(title))
+                .reftype(ReferenceType::Notes);
+            maker.add_content(content)
+                .map_err(|err| Error::render(Source::empty(), format!("{}", err)))?;
+        }
+
         // Render the CSS file and write it
         let template_css_src = self.html.book.get_template("epub.css").unwrap();
         let template_css = self.html.book.compile_str(
@@ -245,26 +290,29 @@ impl<'a> EpubRenderer<'a> {
         let epub_css_add = self.html.book.options.get_str("epub.css.add").unwrap_or("".into()); 
         data.insert("additional_code".into(), epub_css_add.into());
         
-        let css = template_css.render(&data).to_string()?;
+        let mut css = template_css.render(&data).to_string()?;
+        if let Some(syntax_css) = self.html.syntax_css()? {
+            css.push_str(&syntax_css);
+        }
         maker.stylesheet(css.as_bytes())
             .map_err(|err| Error::render(Source::empty(), format!("{}", err)))?;
 
         // Write all images (including cover)
         let cover = self.html.book.options.get_path("cover");
-        for (source, dest) in self.html.handler.images_mapping() {
-            let f = fs::canonicalize(source).and_then(File::open).map_err(|_| {
+        for (source, file, dest) in self.html.handler.image_files() {
+            let content = self.html.handler.read_image(source, &file).map_err(|_| {
                 Error::file_not_found(
                     &self.html.source,
                     t!("epub.image_or_cover"),
-                    source.to_owned(),
+                    file.to_string_lossy().into_owned(),
                 )
             })?;
-            if cover.as_ref() == Ok(source) {
+            if cover.as_deref() == Ok(source) {
                 // Treat cover specially so it is properly tagged
-                maker.add_cover_image(dest, &f, self.get_format(dest))
+                maker.add_cover_image(&dest, content.as_slice(), self.get_format(&dest))
                     .map_err(|err| Error::render(Source::empty(), format!("{}", err)))?;
             } else {
-                maker.add_resource(dest, &f, self.get_format(dest))
+                maker.add_resource(&dest, content.as_slice(), self.get_format(&dest))
                     .map_err(|err| Error::render(Source::empty(), format!("{}", err)))?;
             }
         }
@@ -277,7 +325,8 @@ impl<'a> EpubRenderer<'a> {
                 .options
                 .get_path("resources.base_path.files")
                 .unwrap();
-            let list = resource_handler::get_files(list, &base_path_files)?;
+            let book_source = &self.html.book.options.book_source;
+            let list = resource_handler::get_files(list, &base_path_files, &**book_source)?;
             let data_path = Path::new(
                 self.html
                     .book
@@ -286,20 +335,41 @@ impl<'a> EpubRenderer<'a> {
             );
             for path in list {
                 let abs_path = Path::new(&base_path_files).join(&path);
-                let f = fs::canonicalize(&abs_path)
-                    .and_then(File::open)
-                    .map_err(|_| {
-                        Error::file_not_found(
-                            &self.html.book.source,
-                            t!("epub.resources"),
-                            abs_path.to_string_lossy().into_owned(),
-                        )
-                    })?;
-                maker.add_resource(data_path.join(&path), &f, self.get_format(path.as_ref()))
+                let content = book_source.read(&abs_path).map_err(|_| {
+                    Error::file_not_found(
+                        &self.html.book.source,
+                        t!("epub.resources"),
+                        abs_path.to_string_lossy().into_owned(),
+                    )
+                })?;
+                maker.add_resource(data_path.join(&path), content.as_slice(), self.get_format(path.as_ref()))
                     .map_err(|err| Error::render(Source::empty(), format!("{}", err)))?;
             }
         }
 
+        // Add files of the theme (fonts, images, ...)
+        for (dest, source) in self.html.book.options.get_theme_files() {
+            let content = self.html.book.options.book_source.read(source).map_err(|_| {
+                Error::file_not_found(
+                    &self.html.book.source,
+                    t!("epub.resources"),
+                    source.to_string_lossy().into_owned(),
+                )
+            })?;
+            maker.add_resource(dest, content.as_slice(), self.get_format(dest))
+                .map_err(|err| Error::render(Source::empty(), format!("{}", err)))?;
+        }
+
+        // The `zip` command can't be stopped once it runs
+        self.html.book.cancellation_token().check()?;
+        #[cfg(feature = "archive")]
+        if epub3 && !self.pages.is_empty() {
+            let mut epub = vec![];
+            maker.generate(&mut epub)
+                .map_err(|err| Error::render(Source::empty(), format!("{}", err)))?;
+            add_page_list(&epub, &self.pages, to)?;
+            return Ok(String::new());
+        }
         maker.generate(to)
             .map_err(|err| Error::render(Source::empty(), format!("{}", err)))?;
 
@@ -325,7 +395,7 @@ impl<'a> EpubRenderer<'a> {
     fn render_cover(&mut self) -> Result<String> {
         if let Ok(cover) = self.html.book.options.get_path("cover") {
             // Check that cover can be found
-            if fs::metadata(&cover).is_err() {
+            if !self.html.book.options.book_source.is_file(Path::new(&cover)) {
                 return Err(Error::file_not_found(
                     &self.html.book.source,
                     t!("epub.cover"),
@@ -354,22 +424,123 @@ impl<'a> EpubRenderer<'a> {
         }
     }
 
+    /// Returns the accessibility metadata (EPUB Accessibility 1.1 / schema.org)
+    /// of the book, as `(name, content)` pairs
+    ///
+    /// Default values depend on the images of the book, and on whether they
+    /// all have an alternative text, so this must be called once the chapters
+    /// are rendered.
+    pub fn accessibility_metadata(&self) -> Vec<(&'static str, String)> {
+        let options = &self.html.book.options;
+        let visual = self.html.book.features.image;
+        let alt_text = self.html.missing_alt == 0;
+        let list = |key: &str, default: Vec<&str>| -> Vec<String> {
+            match options.get_str_vec(key) {
+                Ok(list) => list.to_vec(),
+                Err(_) => default.into_iter().map(String::from).collect(),
+            }
+        };
+
+        let mut metadata = vec![];
+        let modes = list(
+            "epub.accessibility.mode",
+            if visual { vec!["textual", "visual"] } else { vec!["textual"] },
+        );
+        // Text alone is enough if images have a textual alternative
+        let sufficient = if (!visual || alt_text) && modes.iter().any(|m| m == "textual") {
+            String::from("textual")
+        } else {
+            modes.join(",")
+        };
+        for mode in modes {
+            metadata.push(("schema:accessMode", mode));
+        }
+        if !sufficient.is_empty() {
+            metadata.push(("schema:accessModeSufficient", sufficient));
+        }
+
+        let mut features = vec!["structuralNavigation", "tableOfContents", "readingOrder"];
+        if visual && alt_text {
+            features.push("alternativeText");
+        }
+        if !self.pages.is_empty() {
+            features.extend(["pageBreakMarkers", "pageNavigation"]);
+        }
+        for feature in list("epub.accessibility.features", features) {
+            metadata.push(("schema:accessibilityFeature", feature));
+        }
+        for hazard in list("epub.accessibility.hazards", vec!["none"]) {
+            metadata.push(("schema:accessibilityHazard", hazard));
+        }
+        let summary = match options.get_str("epub.accessibility.summary") {
+            Ok(summary) => summary.to_owned(),
+            Err(_) => lang::get_str(options.get_str("lang").unwrap(), "accessibility_summary"),
+        };
+        metadata.push(("schema:accessibilitySummary", summary));
+        if let Ok(conforms_to) = options.get_str("epub.accessibility.conforms_to") {
+            metadata.push(("dcterms:conformsTo", conforms_to.to_owned()));
+        }
+        if let Ok(source) = options.get_str("epub.accessibility.page_source") {
+            if !self.pages.is_empty() {
+                metadata.push(("a11y:pageBreakSource", source.to_owned()));
+            }
+        }
+        metadata
+    }
+
+    /// Render the notes that have been kept for the end of the book, if any
+    fn render_notes(&mut self, template: &Template) -> Result<Option<String>> {
+        if self.html.notes != Notes::BookEnd {
+            return Ok(None);
+        }
+        let mut content = String::new();
+        if self.html.book.options.get_i32("epub.version").unwrap() == 3 {
+            self.html.render_end_notes(&mut content, "section", "epub:type=\"endnotes\" role=\"doc-endnotes\"");
+        } else {
+            self.html.render_end_notes(&mut content, "div", "");
+        }
+        if content.is_empty() {
+            return Ok(None);
+        }
+        let title = lang::get_str(self.html.book.options.get_str("lang").unwrap(), "notes");
+        let mut data = self
+            .html
+            .book
+            .get_metadata(|s| self.render_vec(&Parser::new().parse_inline(s)?))?;
+        data.insert("content".into(), content.into());
+        data.insert("chapter_title_raw".into(), title.clone().into());
+        data.insert("chapter_title".into(), escape::html(title).into_owned().into());
+        Ok(Some(template.render(&data).to_string()?))
+    }
+
     /// Render a chapter
     ///
     /// Return chapter content and raw title
     pub fn render_chapter(&mut self, v: &[Token], template: &Template) -> Result<(String, String)> {
         let mut content = String::new();
+        let epub3 = self.html.book.options.get_i32("epub.version").unwrap() == 3;
+        let attributes = self.html.chapter_attributes();
+        if !attributes.is_empty() {
+            content.push_str(&format!("<div{attributes}>\n"));
+        }
 
         for token in v {
             content.push_str(&self.render_token(token)?);
             self.html.render_side_notes(&mut content);
         }
 
-        let epub3 = self.html.book.options.get_i32("epub.version").unwrap() == 3;
-        if epub3 {
-            self.html.render_end_notes(&mut content, "section", "epub:type=\"footnotes\"");
-        } else {
-            self.html.render_end_notes(&mut content, "div", "");
+        match self.html.notes {
+            Notes::BookEnd => (),
+            Notes::ChapterEnd if epub3 => {
+                self.html.render_end_notes(&mut content, "section", "epub:type=\"endnotes\" role=\"doc-endnotes\"")
+            }
+            _ if epub3 => {
+                self.html.render_end_notes(&mut content, "section", "epub:type=\"footnotes\"")
+            }
+            _ => self.html.render_end_notes(&mut content, "div", ""),
+        }
+        if !attributes.is_empty() {
+            content.push_str("</div>\n");
         }
 
         if self.chapter_title.is_empty() && self.html.current_numbering >= 1 {
@@ -383,17 +554,16 @@ impl<'a> EpubRenderer<'a> {
                 header = Header::Chapter;
             }
 
-            self.chapter_title = self
-                .html
-                .book
-                .get_header(header, number, "".to_owned(), |s| {
+            let book = self.html.book;
+            let options = self.html.chapter_options.clone();
+            let options = options.as_ref().unwrap_or(&book.options);
+            self.chapter_title = book
+                .get_header_with(options, header, number, "".to_owned(), |s| {
                     self.render_vec(&Parser::new().parse_inline(s)?)
                 })?
                 .text;
-            self.chapter_title_raw = self
-                .html
-                .book
-                .get_header(header, number, "".to_owned(), |s| {
+            self.chapter_title_raw = book
+                .get_header_with(options, header, number, "".to_owned(), |s| {
                     Ok(view_as_text(&Parser::new().parse_inline(s)?))
                 })?
                 .text;
@@ -404,6 +574,9 @@ impl<'a> EpubRenderer<'a> {
             .html
             .book
             .get_metadata(|s| self.render_vec(&Parser::new().parse_inline(s)?))?;
+        if let Some(lang) = self.html.chapter_lang() {
+            data.insert("lang".into(), lang.into());
+        }
         data.insert("content".into(), content.into());
         data.insert("chapter_title_raw".into(), self.chapter_title_raw.clone(). into());
         data.insert("chapter_title".into(), std::mem::take(&mut self.chapter_title).into());
@@ -418,12 +591,12 @@ impl<'a> EpubRenderer<'a> {
                 self.chapter_title = self.html.render_vec(vec)?;
                 self.chapter_title_raw = view_as_text(vec);
             } else {
-                warn!(
-                    "{}",
+                self.html.book.warn(
                     t!(
                         "epub.ambiguous_invisible",
                         source = self.html.source
                     )
+                    .into_owned(),
                 );
             }
         } else {
@@ -436,38 +609,37 @@ impl<'a> EpubRenderer<'a> {
                 header = Header::Chapter;
                 number = self.html.current_chapter[1] + 1;
             };
-            let res = self
-                .html
-                .book
-                .get_header(header, number, self.html.render_vec(vec)?, |s| {
+            let book = self.html.book;
+            let options = self.html.chapter_options.clone();
+            let options = options.as_ref().unwrap_or(&book.options);
+            let res = book
+                .get_header_with(options, header, number, self.html.render_vec(vec)?, |s| {
                     self.render_vec(&(Parser::new().parse_inline(s)?))
                 });
             let s = res?;
             if self.chapter_title.is_empty() {
                 self.chapter_title = s.text;
-                self.chapter_title_raw = self
-                    .html
-                    .book
-                    .get_header(header, number, view_as_text(vec), |s| {
+                self.chapter_title_raw = book
+                    .get_header_with(options, header, number, view_as_text(vec), |s| {
                         Ok(view_as_text(&(Parser::new().parse_inline(s)?)))
                     })?
                     .text;
             } else {
-                warn!(
-                    "{}",
+                self.html.book.warn(
                     t!(
                         "epub.ambiguous",
                         source = self.html.source
                     )
+                    .into_owned(),
                 );
-                warn!(
-                    "{}",
+                self.html.book.warn(
                     t!(
                         "epub.title_conflict",
                         source = self.html.source,
                         title1 = self.chapter_title,
                         title2 = s
                     )
+                    .into_owned(),
                 );
             }
         }
@@ -513,11 +685,11 @@ impl<'a> EpubRenderer<'a> {
                 let content = if html.verbatim {
                     Cow::Borrowed(text.as_ref())
                 } else {
-                    escape::html(html.book.clean(text.as_str()))
+                    escape::html(html.clean(text.as_str()))
                 };
                 let mut content = if html.first_letter {
                     html.first_letter = false;
-                    if html.book.options.get_bool("rendering.initials").unwrap() {
+                    if html.options().get_bool("rendering.initials").unwrap() {
                         // Use initial
                         let mut chars = content.chars();
                         let initial = chars.next().ok_or_else(|| {
@@ -542,7 +714,7 @@ impl<'a> EpubRenderer<'a> {
                     content
                 };
 
-                if html.book.options.get_bool("epub.escape_nb_spaces").unwrap() {
+                if html.options().get_bool("epub.escape_nb_spaces").unwrap() {
                     content = escape::nb_spaces_html(content);
                 }
                 Ok(content.into_owned())
@@ -554,18 +726,33 @@ impl<'a> EpubRenderer<'a> {
                 }
                 HtmlRenderer::static_render_token(this, token)
             }
+            Token::PageBreak(ref page) => {
+                let epub: &mut EpubRenderer = this.as_mut();
+                if epub.html.book.options.get_i32("epub.version").unwrap() != 3 {
+                    return HtmlRenderer::static_render_token(this, token);
+                }
+                // Chapters are added to `toc` once they are rendered
+                let chapter = epub.toc.len();
+                epub.pages.push((chapter, page.clone()));
+                Ok(format!(
+                    "<span epub:type=\"pagebreak\" role=\"doc-pagebreak\" id=\"page_{page}\" aria-label=\"{page}\"></span>"
+                ))
+            }
             Token::FootnoteReference(ref reference) => {
-                let epub3 = (this.as_ref() as &HtmlRenderer)
-                    .book
-                    .options
-                    .get_i32("epub.version")
-                    .unwrap()
-                    == 3;
+                let html: &mut HtmlRenderer = this.as_mut();
+                let epub3 = html.book.options.get_i32("epub.version").unwrap() == 3;
+                let hash = html.footnote_prefix;
+                let number = html.get_note_number(reference);
+                let (dest, _) = html.get_note_links(reference);
 
                 Ok(format!(
-                    "<a {} href = \"#note-dest-{reference}\"><sup id = \
-                            \"note-source-{reference}\">[{reference}]</sup></a>",
-                    if epub3 { "epub:type = \"noteref\"" } else { "" },
+                    "<a {} href = \"{dest}\"><sup id = \
+                            \"note-source-{hash}-{reference}\">[{number}]</sup></a>",
+                    if epub3 {
+                        "epub:type = \"noteref\" role = \"doc-noteref\""
+                    } else {
+                        ""
+                    },
                 ))
             }
             Token::FootnoteDefinition(ref reference, ref vec) => {
@@ -577,17 +764,25 @@ impl<'a> EpubRenderer<'a> {
                     == 3;
                 let inner_content = this.render_vec(vec)?;
                 let html: &mut HtmlRenderer = this.as_mut();
+                let hash = html.footnote_prefix;
+                let number = html.get_note_number(reference);
+                let (_, source) = html.get_note_links(reference);
                 let note_number = format!(
                     "<p class = \"note-number\">
-  <a href = \"#note-source-{reference}\">[{reference}]</a>
+  <a href = \"{source}\">[{number}]</a>
 </p>\n",
                 );
                 let inner = if epub3 {
+                    let (note_type, role) = if html.notes == Notes::Footnotes {
+                        ("footnote", " role = \"doc-footnote\"")
+                    } else {
+                        ("endnote", "")
+                    };
                     format!(
-                        "<aside epub:type = \"footnote\" id = \"note-dest-{reference}\">{inner_content}</aside>"
+                        "<aside epub:type = \"{note_type}\"{role} id = \"note-dest-{hash}-{reference}\">{inner_content}</aside>"
                     )
                 } else {
-                    format!("<a id = \"note-dest-{reference}\" />{inner_content}")
+                    format!("<a id = \"note-dest-{hash}-{reference}\" />{inner_content}")
                 };
                 html.add_footnote(note_number, inner);
 
@@ -603,6 +798,45 @@ fn filenamer(i: usize) -> String {
     format!("chapter_{i:03}.xhtml")
 }
 
+/// Copies an EPUB 3 file generated by `epub_builder`, adding a page list to its
+/// navigation document, with the page breaks of each chapter
+#[cfg(feature = "archive")]
+fn add_page_list(epub: &[u8], pages: &[(usize, String)], to: &mut dyn Write) -> Result<()> {
+    use std::io::{Cursor, Read};
+
+    let error = |err: &dyn std::fmt::Display| Error::render(Source::empty(), format!("{err}"));
+    let mut page_list = String::from(
+        "  <nav epub:type=\"page-list\" id=\"page-list\" hidden=\"hidden\">\n    <ol>\n",
+    );
+    for (chapter, page) in pages {
+        page_list.push_str(&format!(
+            "      <li><a href=\"{}#page_{page}\">{page}</a></li>\n",
+            filenamer(*chapter),
+        ));
+    }
+    page_list.push_str("    </ol>\n  </nav>\n");
+
+    let mut archive = zip::ZipArchive::new(Cursor::new(epub)).map_err(|e| error(&e))?;
+    let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).map_err(|e| error(&e))?;
+        if file.name() == "OEBPS/nav.xhtml" {
+            let mut nav = String::new();
+            file.read_to_string(&mut nav).map_err(|e| error(&e))?;
+            let nav = nav.replacen("</body>", &format!("{page_list}</body>"), 1);
+            writer
+                .start_file("OEBPS/nav.xhtml", zip::write::SimpleFileOptions::default())
+                .map_err(|e| error(&e))?;
+            writer.write_all(nav.as_bytes()).map_err(|e| error(&e))?;
+        } else {
+            // Keeps compression, so `mimetype` stays stored first
+            writer.raw_copy_file(file).map_err(|e| error(&e))?;
+        }
+    }
+    let epub = writer.finish().map_err(|e| error(&e))?.into_inner();
+    to.write_all(&epub).map_err(|e| error(&e))
+}
+
 derive_html! {EpubRenderer<'a>, EpubRenderer::static_render_token}
 
 pub struct Epub {}
@@ -22,8 +22,9 @@ use std::result;
 use std::string::FromUtf8Error;
 
 use rust_i18n::t;
+use serde::Serialize;
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize)]
 /// Source of an error.
 ///
 /// Contains (if it's possible) the file and ideally the line that the user should
@@ -210,6 +211,26 @@ impl Error {
         }
     }
 
+    /// Creates a new filter error.
+    ///
+    /// Error when an external filter fails or returns an invalid document.
+    pub fn filter<S: Into<Cow<'static, str>>, O: Into<Source>>(source: O, msg: S) -> Error {
+        Error {
+            source: source.into(),
+            inner: Inner::Filter(msg.into()),
+        }
+    }
+
+    /// Creates a new cancellation error.
+    ///
+    /// Error when rendering was stopped with a `CancellationToken`.
+    pub fn cancelled() -> Error {
+        Error {
+            source: Source::empty(),
+            inner: Inner::Cancelled,
+        }
+    }
+
     /// Change the source of an error.
     pub fn with_source<O: Into<Source>>(mut self, source: O) -> Error {
         self.source = source.into();
@@ -255,6 +276,16 @@ impl Error {
     pub fn is_invalid_option(&self) -> bool {
         matches!(self.inner, Inner::InvalidOption(..))
     }
+
+    /// Returns true if self is a filter error, false else.
+    pub fn is_filter(&self) -> bool {
+        matches!(self.inner, Inner::Filter(..))
+    }
+
+    /// Returns true if self is a cancellation error, false else.
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self.inner, Inner::Cancelled)
+    }
 }
 
 impl error::Error for Error {
@@ -268,8 +299,10 @@ impl error::Error for Error {
             | Inner::InvalidOption(ref s)
             | Inner::Render(ref s)
             | Inner::Template(ref s)
+            | Inner::Filter(ref s)
             | Inner::Syntect(ref s) => s.as_ref(),
             Inner::FileNotFound(..) => "File not found",
+            Inner::Cancelled => "Cancelled",
         }
     }
 }
@@ -336,6 +369,11 @@ impl fmt::Display for Error {
                 f.write_str(&t!("error.syntect"))?;
                 f.write_str(s)
             }
+            Inner::Filter(ref s) => {
+                f.write_str(&t!("error.filter"))?;
+                f.write_str(s)
+            }
+            Inner::Cancelled => f.write_str(&t!("error.cancelled")),
         }?;
         Ok(())
     }
@@ -412,4 +450,8 @@ enum Inner {
     Template(Cow<'static, str>),
     /// Error when parsing code syntax
     Syntect(Cow<'static, str>),
+    /// Error when running an external filter
+    Filter(Cow<'static, str>),
+    /// Rendering was cancelled
+    Cancelled,
 }
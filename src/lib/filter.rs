@@ -0,0 +1,194 @@
+// Copyright (C) 2016-2023 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! External filters, transforming the AST of a chapter before it is rendered
+
+use crate::chapter::Chapter;
+use crate::error::{Error, Result, Source};
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use rust_i18n::t;
+use serde::{Deserialize, Serialize};
+
+/// The document sent to a filter on its standard input, and that it must
+/// write back (modified or not) on its standard output.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilterDocument {
+    /// Version of Crowbook that produced the document
+    #[serde(default)]
+    pub crowbook_version: String,
+    /// Raw (unrendered) values of the book's metadata that are set
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+    /// The chapter being filtered
+    pub chapter: Chapter,
+}
+
+/// Runs a chapter through a list of filters, in order.
+///
+/// Each command is split into arguments like a shell would (see `split_command`:
+/// quotes group words, and a backslash escapes a quote or a space), then run
+/// from `root`. The chapter's options are not sent to filters, and are kept as
+/// they were.
+pub fn apply(
+    commands: &[String],
+    root: &Path,
+    metadata: BTreeMap<String, String>,
+    chapter: Chapter,
+) -> Result<Chapter> {
+    let source = Source::new(chapter.filename.as_str());
+    let mut doc = FilterDocument {
+        crowbook_version: env!("CARGO_PKG_VERSION").to_owned(),
+        metadata,
+        chapter,
+    };
+    for command in commands {
+        debug!(
+            "{}",
+            t!("filter.running", command = command, file = &doc.chapter.filename)
+        );
+        let input = serde_json::to_vec(&doc).map_err(|e| {
+            Error::filter(&source, t!("filter.serialize", command = command, error = e))
+        })?;
+        // Filters can't change these
+        let options = std::mem::take(&mut doc.chapter.options);
+        let metadata = std::mem::take(&mut doc.metadata);
+        let output = run(&source, command, root, input)?;
+        doc = serde_json::from_slice(&output).map_err(|e| {
+            Error::filter(&source, t!("filter.invalid", command = command, error = e))
+        })?;
+        doc.chapter.options = options;
+        doc.metadata = metadata;
+    }
+    Ok(doc.chapter)
+}
+
+/// Runs a single filter command, writing `input` to its standard input
+/// and returning its standard output
+fn run(source: &Source, command: &str, root: &Path, input: Vec<u8>) -> Result<Vec<u8>> {
+    let mut args = split_command(command).into_iter();
+    let program = args
+        .next()
+        .ok_or_else(|| Error::filter(source, t!("filter.empty")))?;
+    let program = program.as_str();
+    // Relative paths to a script are relative to the book, not the current directory
+    let local = root.join(program);
+    let mut cmd = if program.contains(std::path::is_separator) && local.exists() {
+        Command::new(local)
+    } else {
+        Command::new(program)
+    };
+    let mut child = cmd
+        .args(args)
+        .current_dir(if root.as_os_str().is_empty() { Path::new(".") } else { root })
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::filter(source, t!("filter.spawn", command = command, error = e)))?;
+
+    // Write in another thread so a filter that starts writing before it has
+    // read all its input can't block on a full pipe
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = thread::spawn(move || stdin.write_all(&input));
+    let output = child
+        .wait_with_output()
+        .map_err(|e| Error::filter(source, t!("filter.spawn", command = command, error = e)))?;
+    let written = writer.join().unwrap();
+
+    if !output.status.success() {
+        return Err(Error::filter(
+            source,
+            t!(
+                "filter.failed",
+                command = command,
+                status = output.status,
+                stderr = String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ));
+    }
+    // A filter is allowed to not read all of its input
+    match written {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+            return Err(Error::filter(source, t!("filter.spawn", command = command, error = e)))
+        }
+        _ => {}
+    }
+    Ok(output.stdout)
+}
+
+/// Splits a filter command into its program and arguments.
+///
+/// Arguments are separated by whitespace, except inside single or double
+/// quotes. Outside of single quotes, a backslash escapes a following quote,
+/// backslash or whitespace, and is kept as is otherwise (so Windows paths
+/// work unquoted).
+pub(crate) fn split_command(command: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut current: Option<String> = None;
+    let mut quote = None;
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('\''), c) => current.get_or_insert_with(String::new).push(c),
+            (_, '\\') => {
+                let arg = current.get_or_insert_with(String::new);
+                match chars.peek() {
+                    Some(&next) if matches!(next, '\'' | '"' | '\\') || next.is_whitespace() => {
+                        arg.push(next);
+                        chars.next();
+                    }
+                    _ => arg.push('\\'),
+                }
+            }
+            (Some(_), c) => current.get_or_insert_with(String::new).push(c),
+            (None, '\'' | '"') => {
+                current.get_or_insert_with(String::new);
+                quote = Some(c);
+            }
+            (None, c) if c.is_whitespace() => args.extend(current.take()),
+            (None, c) => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(current);
+    args
+}
+
+/// Joins a list of arguments into a command that `split_command` splits back
+/// into the same list
+pub(crate) fn join_command(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            if !arg.is_empty()
+                && !arg
+                    .chars()
+                    .any(|c| c.is_whitespace() || matches!(c, '\'' | '"' | '\\'))
+            {
+                arg.clone()
+            } else {
+                format!("'{}'", arg.replace('\'', "'\\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -18,13 +18,19 @@
 use crate::book::Header;
 use crate::book::HeaderData;
 use crate::book::Book;
+use crate::book::Notes;
+use crate::bookoptions::BookOptions;
+use crate::cleaner::Cleaner;
+use crate::cache::Cache;
+use crate::chapter::Chapter;
 use crate::error::{Error, Result, Source};
 use crate::lang;
 use crate::number::Number;
 use crate::parser::Parser;
 use crate::renderer::Renderer;
+use crate::remote::Fetcher;
 use crate::resource_handler::ResourceHandler;
-use crate::syntax::Syntax;
+use crate::syntax::{CodeInfo, Syntax};
 use crate::token::Data;
 use crate::token::Token;
 use crate::text_view;
@@ -32,7 +38,7 @@ use crate::text_view;
 use std::borrow::Cow;
 use std::convert::{AsMut, AsRef};
 use std::fmt::Write;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 
 use crowbook_text_processing::escape;
 use epub_builder::Toc;
@@ -65,12 +71,31 @@ pub struct HtmlRenderer<'a> {
     #[doc(hidden)]
     pub footnote_prefix: u32,
 
-    
+    /// Where notes are displayed
+    #[doc(hidden)]
+    pub notes: Notes,
+
+    /// File where notes are written, if they are not in the same file as the chapter
+    /// (e.g. a notes page at the end of the book)
+    #[doc(hidden)]
+    pub notes_file: Option<String>,
+
+    /// Values of the variables used in chapter text, if any
+    variables: Option<HashMap<String, String>>,
+
+    /// Numbers displayed for the notes of current chapter
+    note_numbers: HashMap<String, u32>,
+    note_counter: u32,
+
     filename: String,
 
     /// Book that must be rendered
     pub book: &'a Book<'a>,
 
+    /// Key of the output format, used to evaluate conditional content
+    #[doc(hidden)]
+    pub format: &'static str,
+
     /// Proofread or not
     pub proofread: bool,
 
@@ -90,6 +115,25 @@ pub struct HtmlRenderer<'a> {
     #[doc(hidden)]
     pub current_part: bool,
 
+    /// Whether current chapter is listed in the table of contents
+    #[doc(hidden)]
+    pub current_toc: bool,
+
+    /// Additional class of the current chapter
+    #[doc(hidden)]
+    pub current_class: Option<String>,
+
+    /// Options of the book as modified by the current chapter, if it sets some
+    #[doc(hidden)]
+    pub chapter_options: Option<BookOptions>,
+
+    /// Cleaner according to the options of the current chapter, if it sets some
+    chapter_cleaner: Option<Box<dyn Cleaner>>,
+
+    /// Number of images rendered without an alternative text
+    #[doc(hidden)]
+    pub missing_alt: usize,
+
     /// Resource handler
     #[doc(hidden)]
     pub handler: ResourceHandler,
@@ -111,6 +155,9 @@ pub struct HtmlRenderer<'a> {
 
     syntax: Option<Syntax>,
 
+    /// Whether markup must be valid XHTML 1.1 (for EPUB 2)
+    xhtml11: bool,
+
     part_template_html: upon::Template<'a, 'a>,
     chapter_template_html: upon::Template<'a, 'a>,
 }
@@ -121,7 +168,11 @@ impl<'a> HtmlRenderer<'a> {
             "syntect" => {
                 // Don't init syntect if codeblocks are not used
                 if book.features.codeblock {
-                    (Highlight::Syntect, Some(Syntax::new(theme)))
+                    let mut syntax = Syntax::new(&book.options, theme);
+                    syntax.set_cache(Cache::for_book(book));
+                    syntax.set_emitter(book.emitter());
+                    syntax.prepare("html", &book.chapters);
+                    (Highlight::Syntect, Some(syntax))
                 } else {
                     (Highlight::None, None)
                 }
@@ -141,19 +192,35 @@ impl<'a> HtmlRenderer<'a> {
     /// Creates a new HTML renderer
     pub fn new(book: &'a Book, theme: &str) -> Result<HtmlRenderer<'a>> {
         let (highlight, syntax) = Self::get_highlight(book, theme);
+        let notes = if book.options.get_bool("html.side_notes").unwrap() {
+            Notes::Side
+        } else {
+            book.get_notes()
+        };
 
         let mut html = HtmlRenderer {
             book,
+            format: "html",
             toc: Toc::new(),
             link_number: 0,
             current_chapter: [0, 0, 0, 0, 0, 0, 0],
             current_numbering: book.options.get_i32("rendering.num_depth").unwrap(),
             current_part: false,
+            current_toc: true,
+            current_class: None,
+            chapter_options: None,
+            chapter_cleaner: None,
+            missing_alt: 0,
             current_par: 0,
             current_hide: false,
             table_head: false,
             footnotes: vec![],
             footnote_prefix: 0,
+            notes,
+            notes_file: None,
+            variables: None,
+            note_numbers: HashMap::new(),
+            note_counter: 0,
             verbatim: false,
             filename: String::new(),
             handler: ResourceHandler::new(),
@@ -162,6 +229,7 @@ impl<'a> HtmlRenderer<'a> {
             first_paragraph: true,
             proofread: false,
             syntax,
+            xhtml11: false,
             highlight,
             part_template_html: book.compile_str(
                 book.options.get_str("html.part.template").unwrap(),
@@ -176,9 +244,116 @@ impl<'a> HtmlRenderer<'a> {
         };
         html.handler.set_images_mapping(true);
         html.handler.set_base64(true);
+        html.handler.set_fetcher(Fetcher::from_options(&book.options));
+        html.handler.set_book_source(book.options.book_source.clone());
+        html.handler.set_emitter(book.emitter());
         Ok(html)
     }
 
+    /// Computes the values of the variables used in chapter text, if the book contains any.
+    ///
+    /// Must be called before rendering chapters.
+    #[doc(hidden)]
+    pub fn init_variables<T>(this: &mut T) -> Result<()>
+    where
+        T: AsMut<HtmlRenderer<'a>> + AsRef<HtmlRenderer<'a>> + Renderer,
+    {
+        let book = this.as_ref().book;
+        if book.features.variables {
+            let variables = book.get_variables(
+                |s| this.render_vec(&Parser::new().parse_inline(s)?),
+                |s| escape::html(s).into_owned(),
+            )?;
+            this.as_mut().variables = Some(variables);
+        }
+        Ok(())
+    }
+
+    /// Returns the options that apply to the current chapter
+    #[doc(hidden)]
+    pub fn options(&self) -> &BookOptions {
+        self.chapter_options.as_ref().unwrap_or(&self.book.options)
+    }
+
+    /// Renders markup that is valid XHTML 1.1 (for EPUB 2), e.g. without figures nor
+    /// ARIA attributes for code listings
+    #[doc(hidden)]
+    pub fn set_xhtml11(&mut self) {
+        self.xhtml11 = true;
+        if let Some(ref syntax) = self.syntax {
+            syntax.prepare("xhtml11", &self.book.chapters);
+        }
+    }
+
+    /// Returns the language of the current chapter, if it differs from the book's
+    #[doc(hidden)]
+    pub fn chapter_lang(&self) -> Option<&str> {
+        let lang = self.options().get_str("lang").unwrap();
+        if lang != self.book.options.get_str("lang").unwrap() {
+            Some(lang)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the attributes (class and lang) to add to the element wrapping the
+    /// current chapter, prefixed by a space if there are some
+    #[doc(hidden)]
+    pub fn chapter_attributes(&self) -> String {
+        let mut res = String::new();
+        if let Some(ref class) = self.current_class {
+            write!(res, " class = \"{}\"", escape::html(class.as_str())).unwrap();
+        }
+        if let Some(lang) = self.chapter_lang() {
+            let lang = escape::html(lang);
+            write!(res, " lang = \"{lang}\" xml:lang = \"{lang}\"").unwrap();
+        }
+        res
+    }
+
+    /// Renders the table of contents
+    ///
+    /// Same markup as `Toc::render`, except that links get an `aria-label`
+    /// with the plain text of their title, since titles can contain markup
+    /// (e.g. around chapter numbers).
+    #[doc(hidden)]
+    pub fn render_toc(&self) -> String {
+        fn render(elements: &[TocElement], res: &mut String) {
+            res.push_str("<ul>\n");
+            for element in elements {
+                if element.title.is_empty() {
+                    continue;
+                }
+                write!(
+                    res,
+                    "<li><a href=\"{}\" aria-label=\"{}\">{}</a>",
+                    element.url,
+                    plain_text(&element.title),
+                    element.title
+                )
+                .unwrap();
+                if !element.children.is_empty() {
+                    res.push('\n');
+                    render(&element.children, res);
+                }
+                res.push_str("</li>\n");
+            }
+            res.push_str("</ul>\n");
+        }
+        let mut res = String::new();
+        render(&self.toc.elements, &mut res);
+        res
+    }
+
+    /// Cleans a string according to the options of the current chapter
+    #[doc(hidden)]
+    pub fn clean<'s>(&self, text: &'s str) -> Cow<'s, str> {
+        match self.chapter_cleaner {
+            Some(ref cleaner) => cleaner.clean(Cow::Borrowed(text)),
+            None => self.book.clean(text),
+        }
+    }
+
      /// Add a footnote which will be renderer later on
     #[doc(hidden)]
     pub fn add_footnote(&mut self, number: String, content: String) {
@@ -187,11 +362,16 @@ impl<'a> HtmlRenderer<'a> {
 
     /// Configure the Renderer for this chapter
     #[doc(hidden)]
-    pub fn chapter_config(&mut self, i: usize, n: Number, filename: String) {
-        self.source = Source::new(self.book.chapters[i].filename.as_str());
+    pub fn chapter_config(&mut self, chapter: &Chapter, filename: String) {
+        let n = chapter.number;
+        self.source = Source::new(chapter.filename.as_str());
         self.first_paragraph = true;
         self.current_hide = false;
-        let book_numbering = self.book.options.get_i32("rendering.num_depth").unwrap();
+        self.current_toc = chapter.toc;
+        self.current_class = chapter.class.clone();
+        self.chapter_options = self.book.chapter_options(chapter);
+        self.chapter_cleaner = self.chapter_options.as_ref().map(Book::new_cleaner);
+        let book_numbering = self.options().get_i32("rendering.num_depth").unwrap();
         match n {
             Number::Unnumbered | Number::UnnumberedPart => self.current_numbering = 0,
             Number::Default | Number::DefaultPart => self.current_numbering = book_numbering,
@@ -210,9 +390,45 @@ impl<'a> HtmlRenderer<'a> {
         } 
         self.current_part = n.is_part();
 
+        self.footnote_prefix += 1;
+        self.note_numbers.clear();
+        if self.book.notes_per_chapter() {
+            self.note_counter = 0;
+        }
+
         self.filename = filename;
     }
 
+    /// Returns the number displayed for a note, giving it the next available one
+    /// if this reference hasn't been met yet in the chapter
+    #[doc(hidden)]
+    pub fn get_note_number(&mut self, reference: &str) -> u32 {
+        if let Some(n) = self.note_numbers.get(reference) {
+            *n
+        } else {
+            self.note_counter += 1;
+            self.note_numbers.insert(reference.to_owned(), self.note_counter);
+            self.note_counter
+        }
+    }
+
+    /// Returns the link to a note definition and the link back to its reference
+    #[doc(hidden)]
+    pub fn get_note_links(&self, reference: &str) -> (String, String) {
+        let hash = self.footnote_prefix;
+        if let Some(ref file) = self.notes_file {
+            (
+                format!("{file}#note-dest-{hash}-{reference}"),
+                format!("{}#note-source-{hash}-{reference}", self.filename),
+            )
+        } else {
+            (
+                format!("#note-dest-{hash}-{reference}"),
+                format!("#note-source-{hash}-{reference}"),
+            )
+        }
+    }
+
     /// Renders a chapter to HTML
     pub fn render_html<T>(this: &mut T, tokens: &[Token], render_end_notes: bool) -> Result<String>
     where
@@ -223,8 +439,8 @@ impl<'a> HtmlRenderer<'a> {
             res.push_str(&this.render_token(token)?);
             this.as_mut().render_side_notes(&mut res);
         }
-        if render_end_notes {
-            this.as_mut().render_end_notes(&mut res, "section", "");
+        if render_end_notes && this.as_ref().notes != Notes::BookEnd {
+            this.as_mut().render_end_notes(&mut res, "section", "role = \"doc-endnotes\"");
         }
         Ok(res)
     }
@@ -244,10 +460,20 @@ impl<'a> HtmlRenderer<'a> {
             } else {
                 Header::Chapter
             };
-            self.book.get_header(header, number, c_title, |s| {
-                let mut parser = Parser::from(self.book);
-                self.render_vec(&parser.parse_inline(s)?)
-            })
+            let book = self.book;
+            let options = self.chapter_options.take();
+            let res = book.get_header_with(
+                options.as_ref().unwrap_or(&book.options),
+                header,
+                number,
+                c_title,
+                |s| {
+                    let mut parser = Parser::from(book);
+                    self.render_vec(&parser.parse_inline(s)?)
+                },
+            );
+            self.chapter_options = options;
+            res
         } else if self.current_numbering >= n {
             let numbers = self.get_numbers();
             Ok(HeaderData {
@@ -312,8 +538,7 @@ impl<'a> HtmlRenderer<'a> {
             self.current_chapter[n] += 1;
             let begin = if n == 0
                 && !self
-                    .book
-                    .options
+                    .options()
                     .get_bool("rendering.part.reset_counter")
                     .unwrap()
             {
@@ -346,8 +571,7 @@ impl<'a> HtmlRenderer<'a> {
             }
             if i != 1
                 || !self
-                    .book
-                    .options
+                    .options()
                     .get_bool("rendering.chapter.roman_numerals")
                     .unwrap()
             {
@@ -367,10 +591,10 @@ impl<'a> HtmlRenderer<'a> {
         output
     }
 
-    /// Display side notes if option is to true
+    /// Display side notes if notes must be displayed in the margin
     #[doc(hidden)]
     pub fn render_side_notes(&mut self, res: &mut String) {
-        if self.book.options.get_bool("html.side_notes").unwrap() {
+        if self.notes == Notes::Side {
             for (note_number, footnote) in self.footnotes.drain(..) {
                 write!(
                     res,
@@ -381,7 +605,7 @@ impl<'a> HtmlRenderer<'a> {
         }
     }
 
-    /// Display end notes, if side_notes option is set to false
+    /// Display end notes, if they haven't already been displayed as side notes
     ///
     /// * res: string to write to
     /// * section_tag: should be section for HTML5, id for XHTML
@@ -393,7 +617,7 @@ impl<'a> HtmlRenderer<'a> {
                 res,
                 "<{section_tag} class = \"notes\" {option}>
  <h2 class = \"notes\">{}</h2>\n",
-                lang::get_str(self.book.options.get_str("lang").unwrap(), "notes")
+                lang::get_str(self.options().get_str("lang").unwrap(), "notes")
             )
             .unwrap();
             res.push_str("<table class = \"notes\">\n");
@@ -428,6 +652,28 @@ impl<'a> HtmlRenderer<'a> {
         T: AsMut<HtmlRenderer<'a>> + AsRef<HtmlRenderer<'a>> + Renderer,
     {
         match *token {
+            Token::Conditional(ref condition, ref v) => {
+                let html = this.as_ref();
+                if html.book.is_condition_met(condition, html.format) {
+                    this.render_vec(v)
+                } else {
+                    Ok(String::new())
+                }
+            }
+            Token::Variable(ref name) => {
+                let html = this.as_ref();
+                match html
+                    .variables
+                    .as_ref()
+                    .and_then(|variables| variables.get(&name.replace('.', "_")))
+                {
+                    Some(value) => Ok(value.clone()),
+                    None => Ok(escape::html(format!("{{{{{name}}}}}")).into_owned()),
+                }
+            }
+            Token::PageBreak(ref page) => Ok(format!(
+                "<span role = \"doc-pagebreak\" id = \"page_{page}\" aria-label = \"{page}\"></span>"
+            )),
             Token::Annotation(ref annotation, ref v) => {
                 let content = this.as_mut().render_vec(v)?;
                 if this.as_ref().proofread {
@@ -458,9 +704,7 @@ impl<'a> HtmlRenderer<'a> {
                 let mut content = if this.as_ref().verbatim {
                     Cow::Borrowed(text.as_ref())
                 } else {
-                    escape::html(this.as_ref()
-                                      .book.clean(text.as_str())
-                    )
+                    escape::html(this.as_ref().clean(text.as_str()))
                 };
                 if this.as_ref().first_letter {
                     this.as_mut().first_letter = false;
@@ -468,8 +712,7 @@ impl<'a> HtmlRenderer<'a> {
 
                 if this
                     .as_ref()
-                    .book
-                    .options
+                    .options()
                     .get_bool("html.escape_nb_spaces")
                     .unwrap()
                 {
@@ -488,8 +731,7 @@ impl<'a> HtmlRenderer<'a> {
                 let class = if this.as_ref().first_letter
                     && this
                         .as_ref()
-                        .book
-                        .options
+                        .options()
                         .get_bool("rendering.initials")
                         .unwrap()
                 {
@@ -504,12 +746,12 @@ impl<'a> HtmlRenderer<'a> {
             }
             Token::Header(n, ref vec) => {
                 let data = this.as_mut().render_title(n, vec)?;
-                if n <= this
-                    .as_ref()
-                    .book
-                    .options
-                    .get_i32("rendering.num_depth")
-                    .unwrap()
+                if this.as_ref().current_toc
+                    && n <= this
+                        .as_ref()
+                        .options()
+                        .get_i32("rendering.num_depth")
+                        .unwrap()
                 {
                     let url = format!(
                         "{}#link-{}",
@@ -553,15 +795,12 @@ impl<'a> HtmlRenderer<'a> {
                 this.render_vec(vec)?
             )),
             Token::CodeBlock(ref language, ref s) => {
+                let xhtml11 = this.as_ref().xhtml11;
                 let output = if let Some(ref syntax) = this.as_ref().syntax {
-                    syntax.to_html(s, language)?
-                } else if language.is_empty() {
-                    format!("<pre><code>{s}</code></pre>\n")
+                    syntax.to_html(s, language, xhtml11)?
                 } else {
-                    format!(
-                        "<pre><code class = \"language-{language}\">{}</code></pre>\n",
-                        escape::html(s)
-                    )
+                    let emitter = this.as_ref().book.emitter();
+                    CodeInfo::parse_with(language, &emitter).html_fallback(s, xhtml11)
                 };
                 Ok(output)
             }
@@ -610,16 +849,27 @@ impl<'a> HtmlRenderer<'a> {
             | Token::StandaloneImage(ref url, ref title, ref alt) => {
                 let content = this.render_vec(alt)?;
                 let html: &mut HtmlRenderer = this.as_mut();
-                let url = html.handler.map_image(&html.source, url.as_str())?;
+                if content.trim().is_empty() {
+                    html.missing_alt += 1;
+                    html.book.warn(
+                        t!("html.missing_alt", source = html.source, file = url).into_owned(),
+                    );
+                }
+                let dest = html.handler.map_image(&html.source, url.as_str())?.into_owned();
+                let srcset = match html.handler.srcset(url) {
+                    Some(srcset) => format!(" srcset = \"{srcset}\""),
+                    None => String::new(),
+                };
+                let url = dest;
 
                 if token.is_image() {
                     Ok(format!(
-                        "<img src = \"{url}\" title = \"{title}\" alt = \"{content}\" />",
+                        "<img src = \"{url}\"{srcset} title = \"{title}\" alt = \"{content}\" />",
                     ))
                 } else {
                     Ok(format!(
                         "<div class = \"image\">
-  <img src = \"{url}\" title = \"{title}\" alt = \
+  <img src = \"{url}\"{srcset} title = \"{title}\" alt = \
                                 \"{content}\" />
 </div>",
                     ))
@@ -634,8 +884,11 @@ impl<'a> HtmlRenderer<'a> {
             )),
             Token::TableRow(ref vec) => Ok(format!("<tr>\n{}</tr>\n", this.render_vec(vec)?)),
             Token::TableCell(ref vec) => {
-                let tag = if this.as_ref().table_head { "th" } else { "td" };
-                Ok(format!("<{tag}>{}</{tag}>", this.render_vec(vec)?))
+                if this.as_ref().table_head {
+                    Ok(format!("<th scope = \"col\">{}</th>", this.render_vec(vec)?))
+                } else {
+                    Ok(format!("<td>{}</td>", this.render_vec(vec)?))
+                }
             }
             Token::TableHead(ref vec) => {
                 this.as_mut().table_head = true;
@@ -647,21 +900,25 @@ impl<'a> HtmlRenderer<'a> {
                 // Ensure links are not ambiguous when the same reference is used multiple times
                 // and only one file is generated 
                 let hash = this.as_ref().footnote_prefix;
+                let number = this.as_mut().get_note_number(reference);
+                let (dest, _) = this.as_ref().get_note_links(reference);
                 Ok(format!(
-                    "<a class = \"footnote_reference\" href = \"#note-dest-{hash}-{reference}\" id = \
-                     \"note-source-{hash}-{reference}\"><sup>[{reference}]</sup></a>",
+                    "<a class = \"footnote_reference\" role = \"doc-noteref\" href = \"{dest}\" id = \
+                     \"note-source-{hash}-{reference}\"><sup>[{number}]</sup></a>",
                 ))
             },
             Token::FootnoteDefinition(ref reference, ref vec) => {
                 let hash = this.as_ref().footnote_prefix;
+                let number = this.as_mut().get_note_number(reference);
+                let (_, source) = this.as_ref().get_note_links(reference);
                 let note_number = format!(
                     "<p class = \"note-number\">
-  <a rel = \"footnote\" href = \"#note-source-{hash}-{reference}\">[{reference}]</a>
+  <a rel = \"footnote\" role = \"doc-backlink\" href = \"{source}\">[{number}]</a>
 </p>\n",
                 );
 
                 let inner = format!(
-                    "<aside class = \"footnote\" id = \"note-dest-{hash}-{reference}\">{}</aside>",
+                    "<aside class = \"footnote\" role = \"doc-footnote\" id = \"note-dest-{hash}-{reference}\">{}</aside>",
                     this.render_vec(vec)?
                 );
                 this.as_mut().footnotes.push((note_number, inner));
@@ -715,6 +972,15 @@ impl<'a> HtmlRenderer<'a> {
         self.templatize(json)
     }
 
+    /// Returns the stylesheet for syntax highlighting, if code blocks are
+    /// highlighted with CSS classes (`rendering.highlight.classes`)
+    pub fn syntax_css(&self) -> Result<Option<String>> {
+        match self.syntax {
+            Some(ref syntax) => syntax.css(),
+            None => Ok(None),
+        }
+    }
+
     /// Get metadata useful for many HTML templates.
     /// This could be cached yes.
     pub fn get_metadata(&mut self) -> Result<BTreeMap<String, upon::Value>> {
@@ -727,6 +993,7 @@ impl<'a> HtmlRenderer<'a> {
         data.insert("json_data".into(), self.get_json_ld()?.into());
         data.insert("script".into(), self.book.get_template("html.js").unwrap().into());
         data.insert("highlight_code".into(), (self.highlight == Highlight::Js).into());
+        data.insert("syntax_css".into(), self.syntax_css()?.is_some().into());
         data.insert("footer".into(), HtmlRenderer::get_footer(self)?.into());
         data.insert("header".into(), HtmlRenderer::get_header(self)?.into());
 
@@ -808,6 +1075,23 @@ impl<'a> Renderer for HtmlRenderer<'a> {
     }
 }
 
+/// Returns the text of some HTML, without its tags, so it can be used as an
+/// attribute value
+fn plain_text(html: &str) -> String {
+    let mut res = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if in_tag => (),
+            '"' => res.push_str("&quot;"),
+            _ => res.push(c),
+        }
+    }
+    res.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
 /// This macro automatically generates AsRef and AsMut implementations
 /// for a type, to itself and to HtmlRenderer. Type must have a .html element
 /// and use a <'a> lifetime parameter.
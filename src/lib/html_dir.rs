@@ -15,36 +15,54 @@
 // You should have received a copy of the GNU Lesser General Public License
 // along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
 
-use crate::book::Book;
-use crate::book_renderer::BookRenderer;
+use crate::book::{Book, Header};
+use crate::book::Notes;
+use crate::book_renderer::{self, BookRenderer};
 use crate::error::{Error, Result, Source};
 use crate::html::Highlight;
 use crate::html::HtmlRenderer;
+use crate::images::ImageProcessor;
+use crate::lang;
 use crate::parser::Parser;
 use crate::renderer::Renderer;
 use crate::resource_handler;
+use crate::search::SearchIndex;
+#[cfg(feature = "archive")]
+use crate::sink::ZipSink;
+use crate::sink::{DirSink, OutputSink};
+use crate::syntax;
 use crate::templates::img;
 use crate::text_view::view_as_text;
 use crate::token::Token;
 
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
 use std::convert::{AsMut, AsRef};
 use std::fmt::Write;
 use std::fs;
-use std::fs::File;
 use std::io;
-use std::io::Read;
 use std::path::Path;
 use std::path::PathBuf;
+use crowbook_text_processing::escape;
+use epub_builder::TocElement;
 use rust_i18n::t;
 
 /// Multiple files HTML renderer
 ///
-/// Renders HTML in a given directory.
+/// Renders HTML in a given directory, or in any `OutputSink`.
 pub struct HtmlDirRenderer<'a> {
     html: HtmlRenderer<'a>,
+    /// Directory where files are written, when rendering to a directory
+    dest: PathBuf,
+    /// Files written during this build, relative to `dest`
+    written: RefCell<BTreeSet<String>>,
 }
 
+/// Name of the file listing the files generated in the destination
+/// directory, so the next build only deletes these ones
+const MANIFEST: &str = ".crowbook-manifest";
+
 impl<'a> HtmlDirRenderer<'a> {
     /// Creates a new HtmlDirRenderer
     pub fn new(book: &'a Book) -> Result<HtmlDirRenderer<'a>> {
@@ -56,62 +74,53 @@ impl<'a> HtmlDirRenderer<'a> {
         )?;
         html.handler.set_images_mapping(true);
         html.handler.set_base64(false);
-        Ok(HtmlDirRenderer { html })
+        html.handler
+            .set_image_processor(ImageProcessor::for_format(&book.options, "html.dir"));
+        html.format = "html.dir";
+        if html.notes == Notes::BookEnd {
+            html.notes_file = Some(String::from("notes.html"));
+        }
+        Ok(HtmlDirRenderer {
+            html,
+            dest: PathBuf::new(),
+            written: RefCell::new(BTreeSet::new()),
+        })
     }
 
-    /// Render a book
+    /// Render a book in a directory
+    ///
+    /// Files generated by a previous build that are no longer needed are deleted.
     pub fn render_book(&mut self, dest_path: &Path) -> Result<()> {
+        let previous = self.prepare_dir(dest_path)?;
+        self.dest = dest_path.to_path_buf();
+        self.write_files(&mut DirSink::new(dest_path))?;
+        self.clean_dir(previous)
+    }
+
+    /// Render a book, writing its files to `sink`
+    pub fn render_to_sink(&mut self, sink: &mut dyn OutputSink) -> Result<()> {
+        self.write_files(sink)
+    }
+
+    /// Writes all the files of the book
+    fn write_files(&mut self, sink: &mut dyn OutputSink) -> Result<()> {
         // Add internal files to resource handler
-        for (i, chapter) in self.html.book.chapters.iter().enumerate() {
+        for (i, chapter) in self.html.book.chapters_for(self.html.format).into_iter().enumerate() {
             self.html
                 .handler
                 .add_link(chapter.filename.as_str(), filenamer(i));
-        }
-
-        if let Ok(metadata) = fs::metadata(dest_path) {
-            if metadata.is_file() {
-                return Err(Error::render(
-                    &self.html.book.source,
-                    t!("html.exist_not_dir",
-                        path = dest_path.display()
-                    ),
-                ));
-            } else if metadata.is_dir() {
-                debug!(
-                    "{}",
-                    t!("html.delete_dir",
-                        path = dest_path.display()
-                    )
-                );
-                fs::remove_dir_all(dest_path).map_err(|e| {
-                    Error::render(
-                        &self.html.book.source,
-                        t!("html.delete_dir_error",
-                            path = dest_path.display(),
-                            error = e
-                        ),
-                    )
-                })?;
+            for id in syntax::listing_ids(&chapter.content) {
+                self.html
+                    .handler
+                    .add_link(format!("#{id}"), format!("{}#{id}", filenamer(i)));
             }
         }
 
-        fs::DirBuilder::new()
-            .recursive(true)
-            .create(dest_path)
-            .map_err(|e| {
-                Error::render(
-                    &self.html.book.source,
-                    t!("html.create_dir_error",
-                       path = dest_path.display(),
-                       error = e
-                    ),
-                )
-            })?;
-
         // Write CSS
-        self.write_css()?;
+        self.write_css(sink)?;
         // Write print.css
         self.write_file(
+            sink,
             "print.css",
             self.html
                 .book
@@ -120,13 +129,19 @@ impl<'a> HtmlDirRenderer<'a> {
                 .as_bytes(),
         )?;
         // Write index.html and chapter_xxx.html
-        self.write_html()?;
+        self.write_html(sink)?;
         // Write menu.svg
-        self.write_file("menu.svg", img::MENU_SVG)?;
+        self.write_file(sink, "menu.svg", img::MENU_SVG)?;
+
+        // Write the stylesheet for syntax highlighting, if it uses classes
+        if let Some(css) = self.html.syntax_css()? {
+            self.write_file(sink, "syntax.css", css.as_bytes())?;
+        }
 
         // Write highlight files if they are needed
         if self.html.highlight == Highlight::Js {
             self.write_file(
+                sink,
                 "highlight.js",
                 self.html
                     .book
@@ -135,6 +150,7 @@ impl<'a> HtmlDirRenderer<'a> {
                     .as_bytes(),
             )?;
             self.write_file(
+                sink,
                 "highlight.css",
                 self.html
                     .book
@@ -145,25 +161,24 @@ impl<'a> HtmlDirRenderer<'a> {
         }
 
         // Write all images (including cover)
-        for (source, dest) in self.html.handler.images_mapping() {
-            let mut f = fs::canonicalize(source).and_then(File::open).map_err(|_| {
-                Error::file_not_found(
-                    &self.html.book.source,
-                    t!("epub.image_or_cover"),
-                    source.clone(),
-                )
-            })?;
-            let mut content = vec![];
-            f.read_to_end(&mut content).map_err(|e| {
+        for (source, file, dest) in self.html.handler.image_files() {
+            let content = self.html.handler.read_image(source, &file).map_err(|e| {
+                if e.kind() == io::ErrorKind::NotFound {
+                    return Error::file_not_found(
+                        &self.html.book.source,
+                        t!("epub.image_or_cover"),
+                        file.to_string_lossy().into_owned(),
+                    );
+                }
                 Error::render(
                     &self.html.book.source,
                     t!("html.reading_image_error",
-                        file = source,
+                        file = file.display(),
                         error = e
                     ),
                 )
             })?;
-            self.write_file(dest, &content)?;
+            self.write_file(sink, &dest, &content)?;
         }
 
         // Write additional files
@@ -181,42 +196,171 @@ impl<'a> HtmlDirRenderer<'a> {
                     .get_relative_path("resources.out_path")
                     .unwrap(),
             );
-            let list = resource_handler::get_files(list, &files_path)?;
+            let book_source = &self.html.book.options.book_source;
+            let list = resource_handler::get_files(list, &files_path, &**book_source)?;
             for path in list {
                 let abs_path = Path::new(&files_path).join(&path);
-                let mut f = fs::canonicalize(&abs_path)
-                    .and_then(File::open)
-                    .map_err(|_| {
-                        Error::file_not_found(
+                let content = book_source.read(&abs_path).map_err(|e| {
+                    if e.kind() == io::ErrorKind::NotFound {
+                        return Error::file_not_found(
                             &self.html.book.source,
                             t!("epub.resources"),
                             abs_path.to_string_lossy().into_owned(),
-                        )
-                    })?;
-                let mut content = vec![];
-                f.read_to_end(&mut content).map_err(|e| {
+                        );
+                    }
                     Error::render(
                         &self.html.book.source,
                         t!("html.resource_error", error = e),
                     )
                 })?;
-                self.write_file(data_path.join(&path).to_str().unwrap(), &content)?;
+                self.write_file(sink, data_path.join(&path).to_str().unwrap(), &content)?;
             }
         }
 
+        // Write files of the theme (fonts, images, ...)
+        for (dest, source) in self.html.book.options.get_theme_files() {
+            let content = self.html.book.options.book_source.read(source).map_err(|e| {
+                Error::render(
+                    &self.html.book.source,
+                    t!("html.resource_error", error = e),
+                )
+            })?;
+            self.write_file(sink, dest, &content)?;
+        }
         Ok(())
     }
 
+    /// Makes sure the destination directory exists and can be written to,
+    /// and returns the files that were generated by the previous build
+    fn prepare_dir(&self, dest_path: &Path) -> Result<BTreeSet<String>> {
+        let source = &self.html.book.source;
+        let mut previous = BTreeSet::new();
+        if let Ok(metadata) = fs::metadata(dest_path) {
+            if metadata.is_file() {
+                return Err(Error::render(
+                    source,
+                    t!("html.exist_not_dir",
+                        path = dest_path.display()
+                    ),
+                ));
+            }
+            match fs::read_to_string(dest_path.join(MANIFEST)) {
+                Ok(manifest) => {
+                    previous.extend(
+                        manifest
+                            .lines()
+                            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                            .map(String::from),
+                    );
+                }
+                Err(_) => {
+                    let files: Vec<String> = fs::read_dir(dest_path)
+                        .map(|entries| {
+                            entries
+                                .filter_map(|e| e.ok())
+                                .map(|e| e.file_name().to_string_lossy().into_owned())
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    let is_chapter =
+                        |f: &str| f.starts_with("chapter_") && f.ends_with(".html");
+                    let previous_build = files.iter().any(|f| f == "index.html")
+                        && files.iter().any(|f| f == "menu.svg" || is_chapter(f));
+                    if previous_build {
+                        // Built by a version that didn't write a manifest: its chapters
+                        // can be replaced, other files are kept
+                        debug!("{}", t!("html.adopt_dir", path = dest_path.display()));
+                        previous.extend(files.into_iter().filter(|f| is_chapter(f)));
+                    } else if !files.is_empty()
+                        && !self.html.book.options.get_bool("html.dir.force").unwrap()
+                    {
+                        // This directory wasn't created by Crowbook
+                        return Err(Error::render(
+                            source,
+                            t!("html.not_generated",
+                                path = dest_path.display()
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+
+        fs::DirBuilder::new()
+            .recursive(true)
+            .create(dest_path)
+            .map_err(|e| {
+                Error::render(
+                    source,
+                    t!("html.create_dir_error",
+                       path = dest_path.display(),
+                       error = e
+                    ),
+                )
+            })?;
+        Ok(previous)
+    }
+
+    /// Deletes the files of the previous build that were not generated
+    /// this time, and writes the new manifest
+    fn clean_dir(&self, previous: BTreeSet<String>) -> Result<()> {
+        let written = self.written.borrow();
+        for file in previous.difference(&written) {
+            let path = self.dest.join(file);
+            // Never follow a manifest outside of the directory
+            if !Path::new(file)
+                .components()
+                .all(|c| matches!(c, std::path::Component::Normal(_)))
+            {
+                continue;
+            }
+            if !path.is_file() {
+                continue;
+            }
+            debug!("{}", t!("html.delete_stale", file = path.display()));
+            fs::remove_file(&path).map_err(|e| {
+                Error::render(
+                    &self.html.book.source,
+                    t!("html.delete_file_error",
+                        file = path.display(),
+                        error = e
+                    ),
+                )
+            })?;
+            // Remove directories that are now empty, e.g. images/
+            let mut dir = path.parent();
+            while let Some(d) = dir {
+                if d == self.dest || fs::remove_dir(d).is_err() {
+                    break;
+                }
+                dir = d.parent();
+            }
+        }
+
+        let mut manifest = format!("# {}\n", t!("html.manifest_header"));
+        for file in written.iter() {
+            manifest.push_str(file);
+            manifest.push('\n');
+        }
+        DirSink::new(&self.dest).write_file(MANIFEST, manifest.as_bytes())
+    }
+
     // Render each chapter and write them, and index.html too
-    fn write_html(&mut self) -> Result<()> {
+    fn write_html(&mut self, sink: &mut dyn OutputSink) -> Result<()> {
+        HtmlRenderer::init_variables(self)?;
         let mut chapters = vec![];
 
         let mut titles = vec![];
         let mut titles_raw = vec![];
-        for (i, chapter) in self.html.book.chapters.iter().enumerate() {
-            let n = chapter.number;
+        let mut index = if self.html.book.options.get_bool("html.dir.search").unwrap() {
+            Some(SearchIndex::new(self.html.book.options.get_str("lang").unwrap()))
+        } else {
+            None
+        };
+        for (i, chapter) in self.html.book.chapters_for(self.html.format).into_iter().enumerate() {
+            self.html.book.cancellation_token().check()?;
             let v = &chapter.content;
-            self.html.chapter_config(i, n, filenamer(i));
+            self.html.chapter_config(chapter, filenamer(i));
             let mut title = String::new();
             let mut title_raw = String::new();
             for token in v {
@@ -226,19 +370,22 @@ impl<'a> HtmlDirRenderer<'a> {
                             title = self.html.render_vec(vec)?;
                             title_raw = view_as_text(vec);
                         } else {
-                            title = self
-                                .html
-                                .book
-                                .get_chapter_header(
+                            let book = self.html.book;
+                            let options = self.html.chapter_options.clone();
+                            let options = options.as_ref().unwrap_or(&book.options);
+                            title = book
+                                .get_header_with(
+                                    options,
+                                    Header::Chapter,
                                     self.html.current_chapter[1] + 1,
                                     self.html.render_vec(vec)?,
                                     |s| self.render_vec(&Parser::new().parse_inline(s)?),
                                 )?
                                 .text;
-                            title_raw = self
-                                .html
-                                .book
-                                .get_chapter_header(
+                            title_raw = book
+                                .get_header_with(
+                                    options,
+                                    Header::Chapter,
                                     self.html.current_chapter[1] + 1,
                                     view_as_text(vec),
                                     |s| Ok(view_as_text(&Parser::new().parse_inline(s)?)),
@@ -252,14 +399,44 @@ impl<'a> HtmlDirRenderer<'a> {
                     }
                 }
             }
+            if let Some(ref mut index) = index {
+                let text = search_text(self.html.book, self.html.format, v);
+                let title = if title_raw.is_empty() {
+                    self.html.book.options.get_str("title").unwrap()
+                } else {
+                    title_raw.as_str()
+                };
+                index.add(&filenamer(i), title, &text);
+            }
             titles.push(title);
             titles_raw.push(title_raw);
 
-            let chapter = HtmlRenderer::render_html(self, v, true);
+            let attributes = self.html.chapter_attributes();
+            let chapter = HtmlRenderer::render_html(self, v, true).map(|content| {
+                if attributes.is_empty() {
+                    content
+                } else {
+                    format!("<div{attributes}>\n{content}\n</div>")
+                }
+            });
             chapters.push(chapter);
         }
         self.html.source = Source::empty();
-        let toc = self.html.toc.render(false, false);
+
+        // If notes are displayed at the end of the book, they get their own page
+        let mut notes = String::new();
+        self.html.render_end_notes(&mut notes, "section", "role = \"doc-endnotes\"");
+        let notes_title = lang::get_str(self.html.book.options.get_str("lang").unwrap(), "notes");
+        if !notes.is_empty() {
+            self.html
+                .toc
+                .add(TocElement::new("notes.html", notes_title.clone()));
+        }
+        let toc = self.html.render_toc();
+
+        if let Some(index) = index {
+            self.write_search(sink, &index, &toc)?;
+        }
 
         // render all chapters
         let template_src = self.html.book.get_template("html.dir.template")?;
@@ -298,9 +475,7 @@ impl<'a> HtmlDirRenderer<'a> {
             };
 
             // Render each HTML document
-            let mut data = self
-                .html
-                .get_metadata()?;
+            let mut data = self.get_metadata()?;
             data.insert("content".into(), content?.into());
             data.insert("chapter_title".into(), titles[i].clone().into());
             data.insert("chapter_title_raw".into(), titles_raw[i].clone().into());
@@ -309,27 +484,28 @@ impl<'a> HtmlDirRenderer<'a> {
             data.insert("next_chapter".into(), next_chapter.into());
             data.insert("is_chapter".into(), true.into());
             
-            if let Ok(favicon) = self.html.book.options.get_path("html.icon") {
-                let favicon = self
-                    .html
-                    .handler
-                    .map_image(&self.html.book.source, favicon)?;
-                data.insert(
-                    "favicon".into(),
-                    format!("<link rel = \"icon\" href = \"{favicon}\">").into(),
-                );
-            } else {
-                data.insert("favicon".into(), "".into());
-            }
 
 
             let res = template.render(&data).to_string()?;
-            self.write_file(&filenamer(i), res.as_bytes())?;
+            self.write_file(sink, &filenamer(i), res.as_bytes())?;
+        }
+
+        if !notes.is_empty() {
+            let mut data = self.get_metadata()?;
+            data.insert("content".into(), notes.into());
+            data.insert("chapter_title".into(), notes_title.clone().into());
+            data.insert("chapter_title_raw".into(), notes_title.into());
+            data.insert("toc".into(), toc.clone().into());
+            data.insert("prev_chapter".into(), "".into());
+            data.insert("next_chapter".into(), "".into());
+            data.insert("is_chapter".into(), true.into());
+            let res = template.render(&data).to_string()?;
+            self.write_file(sink, "notes.html", res.as_bytes())?;
         }
 
         let mut content = if let Ok(cover) = self.html.book.options.get_path("cover") {
             // checks first that cover exists
-            if fs::metadata(&cover).is_err() {
+            if !self.html.book.options.book_source.is_file(Path::new(&cover)) {
                 return Err(Error::file_not_found(
                     &self.html.book.source,
                     t!("epub.cover"),
@@ -382,9 +558,9 @@ impl<'a> HtmlDirRenderer<'a> {
             write!(
                 content,
                 "<h1>{}</h1>
-<div id = \"toc\">
+<nav id = \"toc\" role = \"doc-toc\">
 {}
-</div>
+</nav>
 ",
                 self.html.get_toc_name()?,
                 &toc
@@ -404,22 +580,78 @@ impl<'a> HtmlDirRenderer<'a> {
             )?;
         }
         // Render index.html and write it too
-        let mut data = self
-            .html
-            .get_metadata()?;
+        let mut data = self.get_metadata()?;
         data.insert("content".into(), content.into());
         data.insert("toc".into(), toc.into());
         data.insert("is_chapter".into(), false.into());
-        if let Ok(favicon) = self.html.book.options.get_path("html.icon") {
+        let template_src = self.html.book.get_template("html.dir.template")?;
+        let template = self.html.book.compile_str(
+            template_src.as_ref(),
+            &self.html.book.source,
+            "html.dir.template",
+        )?;
+        let res = template.render(&data).to_string()?;
+        self.write_file(sink, "index.html", res.as_bytes())?;
+
+        Ok(())
+    }
+
+    // Returns the data used by all pages of the template
+    fn get_metadata(&mut self) -> Result<BTreeMap<String, upon::Value>> {
+        let mut data = self.html.get_metadata()?;
+        let favicon = if let Ok(favicon) = self.html.book.options.get_path("html.icon") {
             let favicon = self
                 .html
                 .handler
                 .map_image(&self.html.book.source, favicon)?;
-            data.insert(
-                "favicon".into(),
-                format!("<link rel = \"icon\" href = \"{favicon}\">").into(),
-            );
-        }
+            format!("<link rel = \"icon\" href = \"{favicon}\">")
+        } else {
+            String::new()
+        };
+        data.insert("favicon".into(), favicon.into());
+        data.insert(
+            "search".into(),
+            self.html.book.options.get_bool("html.dir.search").unwrap().into(),
+        );
+        Ok(data)
+    }
+
+    // Write the search index, the search script and search.html
+    fn write_search(
+        &mut self,
+        sink: &mut dyn OutputSink,
+        index: &SearchIndex,
+        toc: &str,
+    ) -> Result<()> {
+        self.write_file(sink, "search_index.js", index.to_js().as_bytes())?;
+        self.write_file(
+            sink,
+            "search.js",
+            self.html
+                .book
+                .get_template("html.dir.search.js")?
+                .as_bytes(),
+        )?;
+
+        let lang = self.html.book.options.get_str("lang").unwrap();
+        let title = lang::get_str(lang, "search");
+        let content = format!(
+            "<h1>{title}</h1>
+<div id = \"search-results\" data-none = \"{none}\"></div>
+<script src = \"search_index.js\"></script>
+<script src = \"search.js\"></script>
+",
+            title = escape::html(title.as_str()),
+            none = escape::html(lang::get_str(lang, "search_none")),
+        );
+        let mut data = self.get_metadata()?;
+        data.insert("content".into(), content.into());
+        data.insert("chapter_title".into(), title.clone().into());
+        data.insert("chapter_title_raw".into(), title.into());
+        data.insert("toc".into(), toc.into());
+        data.insert("prev_chapter".into(), "".into());
+        data.insert("next_chapter".into(), "".into());
+        data.insert("is_chapter".into(), true.into());
         let template_src = self.html.book.get_template("html.dir.template")?;
         let template = self.html.book.compile_str(
             template_src.as_ref(),
@@ -427,13 +659,11 @@ impl<'a> HtmlDirRenderer<'a> {
             "html.dir.template",
         )?;
         let res = template.render(&data).to_string()?;
-        self.write_file("index.html", res.as_bytes())?;
-
-        Ok(())
+        self.write_file(sink, "search.html", res.as_bytes())
     }
 
     // Render the CSS file and write it
-    fn write_css(&self) -> Result<()> {
+    fn write_css(&self, sink: &mut dyn OutputSink) -> Result<()> {
         // Render the CSS
         let template_css_src = self.html.book.get_template("html.css")?;
         let template_css = self.html.book.compile_str(
@@ -449,60 +679,43 @@ impl<'a> HtmlDirRenderer<'a> {
         let css = template_css.render(&data).to_string()?;
 
         // Write it
-        self.write_file("stylesheet.css", css.as_bytes())
+        self.write_file(sink, "stylesheet.css", css.as_bytes())
     }
 
-    // Write content to a file
-    fn write_file(&self, file: &str, content: &[u8]) -> Result<()> {
-        let dir_name = if self.html.proofread {
-            self.html
-                .book
-                .options
-                .get_path("output.proofread.html.dir")
-                .unwrap()
-        } else {
-            self.html.book.options.get_path("output.html.dir").unwrap()
-        };
-        let dest_path = PathBuf::from(&dir_name);
-        assert!(dest_path.starts_with(dir_name),
-                "multifile HTML renderer is asked to create a file ({dest_path}) outside of its directory, no way!",
-                dest_path = dest_path.display());
-        let dest_file = dest_path.join(file);
-        let dest_dir = dest_file.parent().unwrap();
-        if fs::metadata(dest_dir).is_err() {
-            // dir does not exist, create it
-            fs::DirBuilder::new()
-                .recursive(true)
-                .create(dest_dir)
-                .map_err(|e| {
-                    Error::render(
-                        &self.html.book.source,
-                        t!("html.create_dir_error",
-                            path = dest_dir.display(),
-                            error = e
-                        ),
-                    )
-                })?;
+    // Write content to a file, and record it in the manifest
+    fn write_file(&self, sink: &mut dyn OutputSink, file: &str, content: &[u8]) -> Result<()> {
+        let file = file.replace('\\', "/");
+        sink.write_file(&file, content)?;
+        self.written.borrow_mut().insert(file);
+        Ok(())
+    }
+}
+
+/// Returns the text of some tokens to index for search, skipping content
+/// that isn't rendered in this format
+fn search_text(book: &Book, format: &str, tokens: &[Token]) -> String {
+    let mut text = String::new();
+    for token in tokens {
+        match *token {
+            Token::Conditional(ref condition, ref v) => {
+                if book.is_condition_met(condition, format) {
+                    text.push_str(&search_text(book, format, v));
+                }
+            }
+            Token::Image(..)
+            | Token::StandaloneImage(..)
+            | Token::FootnoteReference(..) => (),
+            _ => match token.inner() {
+                Some(v) => {
+                    text.push_str(&search_text(book, format, v));
+                    // Don't glue the words of two paragraphs together
+                    text.push(' ');
+                }
+                None => text.push_str(&view_as_text(std::slice::from_ref(token))),
+            },
         }
-        let mut f = File::create(&dest_file).map_err(|e| {
-            Error::render(
-                &self.html.book.source,
-                t!("html.create_file_error",
-                   file = dest_file.display(),
-                   error = e
-                ),
-            )
-        })?;
-        io::Write::write_all(&mut f, content).map_err(|e| {
-            Error::render(
-                &self.html.book.source,
-                t!("html.write_file_error",
-                   file = dest_file.display(),
-                   error = e
-                ),
-            )
-        })
     }
+    text
 }
 
 /// Generate a file name given an int
@@ -519,6 +732,18 @@ impl BookRenderer for HtmlDir {
         Ok(String::from("output_html"))
     }
 
+    /// Writes a zip archive of the files
+    #[cfg(feature = "archive")]
+    fn render(&self, book: &Book, to: &mut dyn io::Write) -> Result<()> {
+        let mut sink = ZipSink::new(io::Cursor::new(vec![]));
+        HtmlDirRenderer::new(book)?.render_to_sink(&mut sink)?;
+        let content = sink.finish()?.into_inner();
+        io::Write::write_all(to, &content).map_err(|e| {
+            Error::render(&book.source, t!("html.write_zip_error", error = e))
+        })
+    }
+
+    #[cfg(not(feature = "archive"))]
     fn render(&self, _: &Book, _: &mut dyn io::Write) -> Result<()> {
         Err(Error::render(
             Source::empty(),
@@ -527,7 +752,16 @@ impl BookRenderer for HtmlDir {
     }
 
     fn render_to_file(&self, book: &Book, path: &Path) -> Result<()> {
+        if cfg!(feature = "archive") && path.extension().is_some_and(|e| e == "zip") {
+            let mut content = vec![];
+            self.render(book, &mut content)?;
+            return book_renderer::write_to_file(path, &content);
+        }
         HtmlDirRenderer::new(book)?.render_book(path)?;
         Ok(())
     }
+
+    fn render_to_sink(&self, book: &Book, _: &str, sink: &mut dyn OutputSink) -> Result<()> {
+        HtmlDirRenderer::new(book)?.render_to_sink(sink)
+    }
 }
@@ -15,13 +15,15 @@
 // You should have received a copy of the GNU Lesser General Public License
 // along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
 
-use crate::book::Book;
+use crate::book::{Book, Header};
 use crate::book_renderer::BookRenderer;
 use crate::error::{Error, Result, Source};
 use crate::html::Highlight;
 use crate::html::HtmlRenderer;
+use crate::images::ImageProcessor;
 use crate::parser::Parser;
 use crate::renderer::Renderer;
+use crate::syntax;
 use crate::templates::img;
 use crate::token::Token;
 use crate::misc;
@@ -30,6 +32,7 @@ use std::convert::{AsMut, AsRef};
 use std::fmt::Write;
 use std::io;
 use rust_i18n::t;
+use crowbook_text_processing::escape;
 
 /// Single file HTML renderer
 ///
@@ -49,6 +52,8 @@ impl<'a> HtmlSingleRenderer<'a> {
         )?;
         html.handler.set_images_mapping(true);
         html.handler.set_base64(true);
+        html.handler
+            .set_image_processor(ImageProcessor::for_format(&book.options, "html"));
         Ok(HtmlSingleRenderer { html })
     }
 
@@ -99,17 +104,21 @@ impl<'a> HtmlSingleRenderer<'a> {
         //     .get_bool("html.standalone.one_chapter")
         //     .unwrap();
 
-        for (i, chapter) in self.html.book.chapters.iter().enumerate() {
+        HtmlRenderer::init_variables(self)?;
+
+        for (i, chapter) in self.html.book.chapters_for(self.html.format).into_iter().enumerate() {
             self.html
                 .handler
                 .add_link(chapter.filename.as_str(), format!("#chapter-{i}"));
+            for id in syntax::listing_ids(&chapter.content) {
+                self.html.handler.add_link(format!("#{id}"), format!("#{id}"));
+            }
         }
 
-        for (i, chapter) in self.html.book.chapters.iter().enumerate() {
-            let n = chapter.number;
+        for (i, chapter) in self.html.book.chapters_for(self.html.format).into_iter().enumerate() {
+            self.html.book.cancellation_token().check()?;
             let v = &chapter.content;
-            self.html.chapter_config(i, n, String::new());
-            self.html.footnote_prefix += 1;
+            self.html.chapter_config(chapter, String::new());
 
             let mut title = String::new();
             for token in v {
@@ -118,10 +127,12 @@ impl<'a> HtmlSingleRenderer<'a> {
                         if self.html.current_hide || self.html.current_numbering == 0 {
                             title = self.html.render_vec(vec)?;
                         } else {
-                            title = self
-                                .html
-                                .book
-                                .get_chapter_header(
+                            let book = self.html.book;
+                            let options = self.html.chapter_options.clone();
+                            title = book
+                                .get_header_with(
+                                    options.as_ref().unwrap_or(&book.options),
+                                    Header::Chapter,
                                     self.html.current_chapter[1] + 1,
                                     self.html.render_vec(vec)?,
                                     |s| self.render_vec(&Parser::new().parse_inline(s)?),
@@ -137,11 +148,21 @@ impl<'a> HtmlSingleRenderer<'a> {
             }
             titles.push(title);
 
+            let class = match self.html.current_class {
+                Some(ref class) => format!("chapter {}", escape::html(class.as_str())),
+                None => String::from("chapter"),
+            };
+            let lang = match self.html.chapter_lang() {
+                Some(lang) => format!(" lang = \"{}\"", escape::html(lang)),
+                None => String::new(),
+            };
             chapters.push(format!(
-                "<div id = \"chapter-{}\" class = \"chapter\">
+                "<div id = \"chapter-{}\" class = \"{}\"{}>
   {}
 </div>",
                 i,
+                class,
+                lang,
                 HtmlRenderer::render_html(self, v, render_notes_chapter)?
             ));
         }
@@ -194,9 +215,9 @@ impl<'a> HtmlSingleRenderer<'a> {
                 )?;
             }
         }
-        self.html.render_end_notes(&mut content, "section", "");
+        self.html.render_end_notes(&mut content, "section", "role = \"doc-endnotes\"");
 
-        let toc = self.html.toc.render(false, false);
+        let toc = self.html.render_toc();
         // If display_toc, display the toc inline
         if self
             .html
@@ -206,10 +227,10 @@ impl<'a> HtmlSingleRenderer<'a> {
             .unwrap()
         {
             content = format!(
-                "<div id = \"toc\">
+                "<nav id = \"toc\" role = \"doc-toc\">
   <h1>{title}</h1>
   {toc}
-</div>
+</nav>
 {content}",
                 title = self.html.get_toc_name()?,
                 toc = &toc,
@@ -320,6 +341,9 @@ impl<'a> HtmlSingleRenderer<'a> {
             );
             data.insert("highlight_js".into(), highlight_js.into());
         } 
+        if let Some(syntax_css) = self.html.syntax_css()? {
+            data.insert("syntax_style".into(), syntax_css.into());
+        }
         let template_src = self.html.book.get_template("html.standalone.template")?;
         let template = self.html.book.compile_str(
             template_src.as_ref(),
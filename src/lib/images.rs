@@ -0,0 +1,419 @@
+// Copyright (C) 2025 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Processing of images for output formats: resizing, re-encoding, and
+//! conversion of the formats an output doesn't support
+
+use crate::bookoptions::BookOptions;
+#[cfg(feature = "images")]
+use crate::error::Error;
+use crate::error::{Result, Source};
+#[cfg(feature = "images")]
+use crate::misc::StableHasher;
+use crate::progress::Emitter;
+use crate::vfs::{BookSource, FileSystem};
+
+#[cfg(feature = "images")]
+use std::fs;
+#[cfg(feature = "images")]
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use rust_i18n::t;
+
+/// Scale used to rasterize SVG images, relative to their size in CSS pixels
+#[cfg(feature = "images")]
+const SVG_SCALE: f32 = 2.0;
+
+/// Settings used to process the images of an output format
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ImageSettings {
+    /// Maximum width of images, in pixels
+    pub max_width: Option<u32>,
+    /// Maximum height of images, in pixels
+    pub max_height: Option<u32>,
+    /// Quality used to encode JPEG images, from 1 to 100
+    pub quality: u8,
+    /// Extensions of the image formats that must be converted
+    pub unsupported: Vec<&'static str>,
+    /// Widths of the smaller versions of images, for `srcset`
+    pub srcset: Vec<u32>,
+}
+
+impl ImageSettings {
+    /// Returns the settings of an output format ("html", "html.dir", "epub", "tex" or "pdf")
+    ///
+    /// `max_width` and `max_height` can be set for HTML, EPUB and LaTeX with
+    /// e.g. `epub.images.max_width`, or for all formats with
+    /// `resources.images.max_width`.
+    pub fn new(options: &BookOptions, format: &str) -> ImageSettings {
+        let prefix = match format {
+            "html" | "html.dir" => "html",
+            "pdf" => "tex",
+            _ => format,
+        };
+        let size = |key: &str| {
+            options
+                .get_i32(&format!("{prefix}.images.{key}"))
+                .or_else(|_| options.get_i32(&format!("resources.images.{key}")))
+                .ok()
+                .filter(|n| *n > 0)
+                .map(|n| n as u32)
+        };
+        let quality = options.get_i32("resources.images.quality").unwrap();
+        // AVIF is listed so a warning is displayed, but it can't be decoded, hence converted
+        let unsupported = match prefix {
+            "epub" if options.get_i32("epub.version").unwrap() == 2 => {
+                let mut list = vec!["webp", "avif"];
+                if options.get_bool("epub.images.rasterize_svg").unwrap() {
+                    list.push("svg");
+                }
+                list
+            }
+            "epub" => {
+                if options.get_bool("epub.images.rasterize_svg").unwrap() {
+                    vec!["avif", "svg"]
+                } else {
+                    vec!["avif"]
+                }
+            }
+            // (pdf|xe|lua)latex only include PNG, JPEG and PDF files
+            "tex" => vec!["webp", "avif", "gif", "svg"],
+            _ => vec![],
+        };
+        let mut srcset = vec![];
+        if format == "html.dir" {
+            if let Ok(widths) = options.get_str("html.dir.images.srcset") {
+                for width in widths.split(',').filter(|w| !w.trim().is_empty()) {
+                    match width.trim().parse::<u32>() {
+                        Ok(width) if width > 0 => srcset.push(width),
+                        _ => error!("{}", t!("images.srcset", value = width)),
+                    }
+                }
+            }
+        }
+        srcset.sort_unstable();
+        srcset.dedup();
+        ImageSettings {
+            max_width: size("max_width"),
+            max_height: size("max_height"),
+            quality: quality.clamp(1, 100) as u8,
+            unsupported,
+            srcset,
+        }
+    }
+
+    /// Returns true if images are always used as is with these settings
+    pub fn is_noop(&self) -> bool {
+        self.max_width.is_none()
+            && self.max_height.is_none()
+            && self.unsupported.is_empty()
+            && self.srcset.is_empty()
+    }
+}
+
+/// An image that was processed for an output format
+#[derive(Debug, Clone)]
+pub struct ProcessedImage {
+    /// File containing the image, which is the original one if it didn't need to change
+    pub file: PathBuf,
+    /// Extension (and format) of this file
+    pub extension: String,
+    /// Width of the image, in pixels, if it is known
+    pub width: Option<u32>,
+    /// Smaller versions of the image, with their width, for `srcset`
+    pub variants: Vec<(u32, PathBuf)>,
+}
+
+/// Processes images according to some settings.
+///
+/// Processed images are written in the `images` subdirectory of a cache
+/// directory, and named after a hash of the original file and of the
+/// settings, so they are only processed again if one of them changes.
+#[derive(Debug, Clone)]
+pub struct ImageProcessor {
+    settings: ImageSettings,
+    #[cfg_attr(not(feature = "images"), allow(dead_code))]
+    cache: PathBuf,
+    /// Where warnings are sent
+    pub(crate) emitter: Emitter,
+}
+
+impl ImageProcessor {
+    /// Creates a new image processor, writing files in `cache`
+    pub fn new<P: Into<PathBuf>>(settings: ImageSettings, cache: P) -> ImageProcessor {
+        ImageProcessor {
+            settings,
+            cache: cache.into().join("images"),
+            emitter: Emitter::default(),
+        }
+    }
+
+    /// Returns a processor for an output format, using `crowbook.cache_dir`,
+    /// or `None` if images don't need processing for this format
+    pub fn for_format(options: &BookOptions, format: &str) -> Option<ImageProcessor> {
+        let settings = ImageSettings::new(options, format);
+        if settings.is_noop() {
+            None
+        } else {
+            Some(ImageProcessor::new(
+                settings,
+                options.get_path("crowbook.cache_dir").unwrap(),
+            ))
+        }
+    }
+
+    /// Returns the settings of this processor
+    pub fn settings(&self) -> &ImageSettings {
+        &self.settings
+    }
+
+    /// Processes an image file
+    ///
+    /// Returns `None` if the original file can be used as is.
+    pub fn process(&self, source: &Source, file: &Path) -> Result<Option<ProcessedImage>> {
+        self.process_in(&FileSystem, source, file)
+    }
+
+    /// Processes an image file, reading it from `files`
+    ///
+    /// Processed images are still written to the cache directory on the file system.
+    pub fn process_in(
+        &self,
+        files: &dyn BookSource,
+        source: &Source,
+        file: &Path,
+    ) -> Result<Option<ProcessedImage>> {
+        let extension = file
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let kind = if extension == "svgz" { "svg" } else { extension.as_str() };
+        let convert = self.settings.unsupported.contains(&kind);
+        match extension.as_str() {
+            // Vector images don't need resizing
+            "svg" | "svgz" if !convert => return Ok(None),
+            // Animations would be lost
+            "gif" if !convert => return Ok(None),
+            "png" | "jpg" | "jpeg" | "webp" | "gif" | "svg" | "svgz" => (),
+            _ => {
+                if convert {
+                    self.emitter
+                        .warn(t!("images.unsupported", file = file.display()).into_owned());
+                }
+                return Ok(None);
+            }
+        }
+        self.process_file(files, source, file, &extension, convert)
+    }
+
+    #[cfg(feature = "images")]
+    fn process_file(
+        &self,
+        files: &dyn BookSource,
+        source: &Source,
+        file: &Path,
+        extension: &str,
+        convert: bool,
+    ) -> Result<Option<ProcessedImage>> {
+        use image::imageops::FilterType;
+        use image::{DynamicImage, ImageDecoder};
+
+        let read_error = |err: &dyn std::fmt::Display| {
+            Error::render(
+                source,
+                t!("images.read", file = file.display(), error = err),
+            )
+        };
+        let data = files.read(file).map_err(|e| read_error(&e))?;
+        let is_svg = extension == "svg" || extension == "svgz";
+
+        // Only the header is read here: decoding the whole image is only needed when it
+        // isn't in the cache yet
+        let header = if is_svg {
+            None
+        } else {
+            let decoder = image::ImageReader::new(std::io::Cursor::new(&data))
+                .with_guessed_format()
+                .map_err(|e| read_error(&e))?
+                .into_decoder()
+                .map_err(|e| read_error(&e))?;
+            Some((decoder.dimensions(), decoder.color_type().has_alpha()))
+        };
+
+        // If the image only needs smaller versions for srcset, keep the original one
+        let mut original = None;
+        if let (false, Some(((width, height), _))) = (convert, header) {
+            if !self.too_large(width, height) {
+                if !self.settings.srcset.iter().any(|w| *w < width) {
+                    return Ok(None);
+                }
+                original = Some(width);
+            }
+        }
+
+        let mut hasher = StableHasher::default();
+        data.hash(&mut hasher);
+        self.settings.hash(&mut hasher);
+        let key = format!("{:016x}", hasher.finish());
+
+        // Decoding is done lazily, as everything might already be in the cache
+        let mut image: Option<DynamicImage> = None;
+        let mut decode = || -> Result<DynamicImage> {
+            if let Some(ref image) = image {
+                return Ok(image.clone());
+            }
+            let mut decoded = if is_svg {
+                rasterize(&data, file).map_err(|e| read_error(&e))?
+            } else {
+                image::load_from_memory(&data).map_err(|e| read_error(&e))?
+            };
+            if self.too_large(decoded.width(), decoded.height()) {
+                decoded = decoded.resize(
+                    self.settings.max_width.unwrap_or(u32::MAX),
+                    self.settings.max_height.unwrap_or(u32::MAX),
+                    FilterType::Lanczos3,
+                );
+            }
+            image = Some(decoded.clone());
+            Ok(decoded)
+        };
+
+        let target = match (extension, header) {
+            ("jpg" | "jpeg", _) => "jpg",
+            ("png" | "svg" | "svgz" | "gif", _) | (_, None) | (_, Some((_, true))) => "png",
+            (_, Some((_, false))) => "jpg",
+        };
+
+        fs::create_dir_all(&self.cache).map_err(|e| self.write_error(source, &self.cache, &e))?;
+        let (main, width) = if let Some(width) = original {
+            (file.to_owned(), Some(width))
+        } else {
+            let main = self.cache.join(format!("{key}.{target}"));
+            let width = match fs::metadata(&main) {
+                Ok(_) => image::image_dimensions(&main).ok().map(|(w, _)| w),
+                Err(_) => {
+                    let image = decode()?;
+                    self.write(source, &image, &main, target)?;
+                    Some(image.width())
+                }
+            };
+            (main, width)
+        };
+
+        let mut variants = vec![];
+        for w in &self.settings.srcset {
+            if width.map(|width| *w >= width).unwrap_or(true) {
+                continue;
+            }
+            let path = self.cache.join(format!("{key}-{w}w.{target}"));
+            if fs::metadata(&path).is_err() {
+                let image = decode()?.resize(*w, u32::MAX, FilterType::Lanczos3);
+                self.write(source, &image, &path, target)?;
+            }
+            variants.push((*w, path));
+        }
+
+        Ok(Some(ProcessedImage {
+            extension: if original.is_some() {
+                extension.to_owned()
+            } else {
+                target.to_owned()
+            },
+            file: main,
+            width,
+            variants,
+        }))
+    }
+
+    #[cfg(not(feature = "images"))]
+    fn process_file(
+        &self,
+        _: &dyn BookSource,
+        _: &Source,
+        file: &Path,
+        _: &str,
+        _: bool,
+    ) -> Result<Option<ProcessedImage>> {
+        self.emitter
+            .warn(t!("images.no_support", file = file.display()).into_owned());
+        Ok(None)
+    }
+
+    /// Returns true if an image is larger than the maximum dimensions
+    #[cfg(feature = "images")]
+    fn too_large(&self, width: u32, height: u32) -> bool {
+        self.settings.max_width.map(|w| width > w).unwrap_or(false)
+            || self.settings.max_height.map(|h| height > h).unwrap_or(false)
+    }
+
+    #[cfg(feature = "images")]
+    fn write_error(&self, source: &Source, path: &Path, err: &dyn std::fmt::Display) -> Error {
+        Error::render(
+            source,
+            t!("images.write", file = path.display(), error = err),
+        )
+    }
+
+    /// Encodes an image to a file, in PNG or JPEG
+    #[cfg(feature = "images")]
+    fn write(
+        &self,
+        source: &Source,
+        image: &image::DynamicImage,
+        path: &Path,
+        format: &str,
+    ) -> Result<()> {
+        let mut content = vec![];
+        let res = if format == "jpg" {
+            let encoder =
+                image::codecs::jpeg::JpegEncoder::new_with_quality(&mut content, self.settings.quality);
+            image::DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(encoder)
+        } else {
+            image.write_to(std::io::Cursor::new(&mut content), image::ImageFormat::Png)
+        };
+        res.map_err(|e| self.write_error(source, path, &e))?;
+        fs::write(path, content).map_err(|e| self.write_error(source, path, &e))
+    }
+}
+
+/// Renders a SVG image to a bitmap
+#[cfg(feature = "images")]
+fn rasterize(data: &[u8], file: &Path) -> std::result::Result<image::DynamicImage, String> {
+    use resvg::{tiny_skia, usvg};
+
+    let mut options = usvg::Options {
+        resources_dir: file.parent().map(|p| p.to_owned()),
+        ..usvg::Options::default()
+    };
+    options.fontdb_mut().load_system_fonts();
+    let tree = usvg::Tree::from_data(data, &options).map_err(|e| e.to_string())?;
+    let size = tree.size().to_int_size().scale_by(SVG_SCALE).ok_or("invalid size")?;
+    let mut pixmap =
+        tiny_skia::Pixmap::new(size.width(), size.height()).ok_or("invalid size")?;
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(SVG_SCALE, SVG_SCALE),
+        &mut pixmap.as_mut(),
+    );
+    let mut buffer = image::RgbaImage::new(size.width(), size.height());
+    for (pixel, color) in buffer.pixels_mut().zip(pixmap.pixels()) {
+        let color = color.demultiply();
+        *pixel = image::Rgba([color.red(), color.green(), color.blue(), color.alpha()]);
+    }
+    Ok(image::DynamicImage::ImageRgba8(buffer))
+}
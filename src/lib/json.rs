@@ -0,0 +1,241 @@
+// Copyright (C) 2016-2023 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! JSON representation of a parsed book, which can be loaded back with `Book::from_json`
+
+use crate::book::Book;
+use crate::book_renderer::BookRenderer;
+use crate::bookoption::BookOption;
+use crate::cleaner::Cleaner;
+use crate::chapter::Chapter;
+use crate::error::{Error, Result, Source};
+use crate::visitor::VisitorMut;
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+use rayon::prelude::*;
+use rust_i18n::t;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
+use yaml_rust::yaml::{Hash, Yaml};
+
+/// The whole book, as written by the `json` output format
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonBook {
+    #[serde(default)]
+    crowbook_version: String,
+    /// Options that are not set to their default value
+    #[serde(default)]
+    options: BTreeMap<String, Value>,
+    /// Raw values of the metadata that are set; only informative, since they
+    /// are also in `options`
+    #[serde(default)]
+    metadata: BTreeMap<String, String>,
+    chapters: Vec<JsonChapter>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonChapter {
+    #[serde(flatten)]
+    chapter: Chapter,
+    /// Options that only apply to this chapter (without the `chapter.` prefix)
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    options: Map<String, Value>,
+}
+
+impl JsonBook {
+    fn new(book: &Book) -> JsonBook {
+        let mut options = BTreeMap::new();
+        for (key, value) in book.options.get_non_defaults() {
+            // Don't let a book loaded from JSON overwrite its own source
+            let value = match (key, value) {
+                ("output.json", _) => continue,
+                ("output", BookOption::StringVec(formats)) => Value::Array(
+                    formats
+                        .iter()
+                        .filter(|f| *f != "json")
+                        .map(|f| Value::String(f.clone()))
+                        .collect(),
+                ),
+                _ => option_to_json(value),
+            };
+            options.insert(key.to_owned(), value);
+        }
+        // Text has already been cleaned
+        options.insert("input.clean".to_owned(), Value::Bool(false));
+
+        let mut metadata = BTreeMap::new();
+        for key in book.options.get_metadata() {
+            if let Ok(value) = book.options.get_str(key) {
+                metadata.insert(key.clone(), value.to_owned());
+            }
+        }
+
+        let chapters = book
+            .chapters
+            .par_iter()
+            .map(|chapter| {
+                let mut chapter = chapter.clone();
+                ChapterCleaner {
+                    book,
+                    cleaner: book.chapter_options(&chapter).as_ref().map(Book::new_cleaner),
+                }
+                .visit_chapter_mut(&mut chapter);
+                let options = chapter
+                    .options
+                    .iter()
+                    .filter_map(|(key, value)| {
+                        let key = key.as_str()?;
+                        if key.starts_with("input.clean") {
+                            None
+                        } else {
+                            Some((key.to_owned(), yaml_to_json(value)))
+                        }
+                    })
+                    .collect();
+                JsonChapter { chapter, options }
+            })
+            .collect();
+
+        JsonBook {
+            crowbook_version: env!("CARGO_PKG_VERSION").to_owned(),
+            options,
+            metadata,
+            chapters,
+        }
+    }
+}
+
+/// Reads a book written by the `json` output format, returning its options
+/// and its chapters
+#[doc(hidden)]
+pub fn read<R: Read>(source: &Source, reader: R) -> Result<(Hash, Vec<Chapter>)> {
+    let book: JsonBook = serde_json::from_reader(reader)
+        .map_err(|e| Error::config_parser(source, t!("json.invalid", error = e)))?;
+    let options = book
+        .options
+        .into_iter()
+        .map(|(key, value)| (Yaml::String(key), json_to_yaml(value)))
+        .collect();
+    let chapters = book
+        .chapters
+        .into_iter()
+        .map(|c| {
+            let mut chapter = c.chapter;
+            chapter.options = c
+                .options
+                .into_iter()
+                .map(|(key, value)| (Yaml::String(key), json_to_yaml(value)))
+                .collect::<Hash>();
+            chapter
+        })
+        .collect();
+    Ok((options, chapters))
+}
+
+/// Cleans the text of a chapter
+struct ChapterCleaner<'a, 'b> {
+    book: &'a Book<'b>,
+    /// Cleaner according to the options of the chapter, if it sets some
+    cleaner: Option<Box<dyn Cleaner>>,
+}
+
+impl VisitorMut for ChapterCleaner<'_, '_> {
+    fn visit_str_mut(&mut self, text: &mut String) {
+        let cleaned = match self.cleaner {
+            Some(ref cleaner) => cleaner.clean(Cow::Borrowed(text.as_str())).into_owned(),
+            None => self.book.clean(text.as_str()).into_owned(),
+        };
+        *text = cleaned;
+    }
+}
+
+fn option_to_json(option: &BookOption) -> Value {
+    match *option {
+        BookOption::String(ref s) | BookOption::Path(ref s) => Value::String(s.clone()),
+        BookOption::Bool(b) => Value::Bool(b),
+        BookOption::Char(c) => Value::String(c.to_string()),
+        BookOption::Int(i) => Value::from(i),
+        BookOption::Float(f) => f
+            .to_string()
+            .parse::<f64>()
+            .ok()
+            .and_then(Number::from_f64)
+            .map_or(Value::Null, Value::Number),
+        BookOption::StringVec(ref v) => {
+            Value::Array(v.iter().map(|s| Value::String(s.clone())).collect())
+        }
+    }
+}
+
+fn yaml_to_json(yaml: &Yaml) -> Value {
+    match *yaml {
+        Yaml::String(ref s) => Value::String(s.clone()),
+        Yaml::Boolean(b) => Value::Bool(b),
+        Yaml::Integer(i) => Value::from(i),
+        Yaml::Real(ref s) => s
+            .parse::<f64>()
+            .ok()
+            .and_then(Number::from_f64)
+            .map_or(Value::Null, Value::Number),
+        Yaml::Array(ref v) => Value::Array(v.iter().map(yaml_to_json).collect()),
+        Yaml::Hash(ref h) => Value::Object(
+            h.iter()
+                .filter_map(|(k, v)| Some((k.as_str()?.to_owned(), yaml_to_json(v))))
+                .collect(),
+        ),
+        Yaml::Null | Yaml::Alias(_) | Yaml::BadValue => Value::Null,
+    }
+}
+
+fn json_to_yaml(value: Value) -> Yaml {
+    match value {
+        Value::String(s) => Yaml::String(s),
+        Value::Bool(b) => Yaml::Boolean(b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Yaml::Integer(i),
+            None => Yaml::Real(n.to_string()),
+        },
+        Value::Array(v) => Yaml::Array(v.into_iter().map(json_to_yaml).collect()),
+        Value::Object(m) => Yaml::Hash(
+            m.into_iter()
+                .map(|(k, v)| (Yaml::String(k), json_to_yaml(v)))
+                .collect(),
+        ),
+        Value::Null => Yaml::Null,
+    }
+}
+
+/// JSON renderer
+///
+/// Writes the parsed book (options, chapters and their content) as JSON
+pub struct Json {}
+
+impl BookRenderer for Json {
+    fn auto_path(&self, book_name: &str) -> Result<String> {
+        Ok(format!("{book_name}.json"))
+    }
+
+    fn render(&self, book: &Book, to: &mut dyn Write) -> Result<()> {
+        book.cancellation_token().check()?;
+        serde_json::to_writer_pretty(&mut *to, &JsonBook::new(book))
+            .and_then(|_| writeln!(to).map_err(serde_json::Error::io))
+            .map_err(|e| Error::render(&book.source, t!("json.write_error", error = e)))
+    }
+}
@@ -16,31 +16,39 @@
 // along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::book::Book;
+use crate::book::Notes;
+use crate::bookoptions::BookOptions;
+use crate::cleaner::Cleaner;
+use crate::cache::Cache;
 use crate::book_renderer::BookRenderer;
 use crate::error::{Error, Result, Source};
+use crate::images::ImageProcessor;
+use crate::lang;
 use crate::number::Number;
 use crate::parser::Parser;
 use crate::renderer::Renderer;
+use crate::remote::Fetcher;
 use crate::resource_handler::ResourceHandler;
-use crate::syntax::Syntax;
+use crate::syntax::{self, CodeInfo, Syntax};
 use crate::token::Data;
 use crate::token::Token;
+use crate::sink::OutputSink;
 use crate::zipper::Zipper;
 
 use crowbook_text_processing::escape;
 
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fmt::Write;
-use std::fs;
-use std::fs::File;
 use std::io;
-use std::io::Read;
 use std::iter::Iterator;
 use rust_i18n::t;
 
 /// LaTeX renderer
 pub struct LatexRenderer<'a> {
     book: &'a Book<'a>,
+    /// Key of the output format (`tex` or `pdf`), used to evaluate conditional content
+    format: &'static str,
     current_chapter: Number,
     handler: ResourceHandler,
     source: Source,
@@ -52,6 +60,21 @@ pub struct LatexRenderer<'a> {
     syntax: Option<Syntax>,
     hyperref: bool,
     enum_level: usize,
+    notes: Notes,
+    /// Values of the variables used in chapter text
+    variables: HashMap<String, String>,
+    /// Content of the notes of current chapter
+    note_definitions: HashMap<&'a str, &'a [Token]>,
+    /// Numbers of the notes of current chapter that have already been referenced
+    note_numbers: HashMap<&'a str, u32>,
+    note_counter: u32,
+    /// Texts of the notes referenced in the current block, written after it
+    pending_notes: Vec<String>,
+    /// Options of the book as modified by the current chapter, if it sets some
+    chapter_options: Option<BookOptions>,
+    chapter_cleaner: Option<Box<dyn Cleaner>>,
+    /// Whether some chapter uses initials, even if the book doesn't
+    use_initials: bool,
 }
 
 impl<'a> LatexRenderer<'a> {
@@ -59,19 +82,29 @@ impl<'a> LatexRenderer<'a> {
     pub fn new(book: &'a Book) -> LatexRenderer<'a> {
         let mut handler = ResourceHandler::new();
         handler.set_images_mapping(true);
+        handler.set_image_processor(ImageProcessor::for_format(&book.options, "tex"));
+        handler.set_fetcher(Fetcher::from_options(&book.options));
+        handler.set_book_source(book.options.book_source.clone());
+        handler.set_emitter(book.emitter());
         let syntax = if book.options.get_str("rendering.highlight").unwrap() == "syntect"
             && book.features.codeblock
         {
-            Some(Syntax::new(
+            let mut syntax = Syntax::new(
+                &book.options,
                 book.options
                     .get_str("tex.highlight.theme")
                     .unwrap_or_else(|_| book.options.get_str("rendering.highlight.theme").unwrap()),
-            ))
+            );
+            syntax.set_cache(Cache::for_book(book));
+            syntax.set_emitter(book.emitter());
+            syntax.prepare("tex", &book.chapters);
+            Some(syntax)
         } else {
             None
         };
         LatexRenderer {
             book,
+            format: "tex",
             current_chapter: Number::Default,
             handler,
             source: Source::empty(),
@@ -83,9 +116,23 @@ impl<'a> LatexRenderer<'a> {
             syntax,
             hyperref: book.options.get_bool("tex.hyperref").unwrap(),
             enum_level: 0,
+            notes: book.get_notes(),
+            variables: HashMap::new(),
+            note_definitions: HashMap::new(),
+            note_numbers: HashMap::new(),
+            note_counter: 0,
+            pending_notes: vec![],
+            chapter_options: None,
+            chapter_cleaner: None,
+            use_initials: false,
         }
     }
 
+    /// Returns the options that apply to the current chapter
+    fn options(&self) -> &BookOptions {
+        self.chapter_options.as_ref().unwrap_or(&self.book.options)
+    }
+
     /// Get latex equivalent for HN:
     ///
     /// * 1 -> chapter
@@ -113,31 +160,71 @@ impl<'a> LatexRenderer<'a> {
 
     /// Render pdf to a file
     pub fn render_pdf(&mut self, to: &mut dyn io::Write) -> Result<String> {
-        let content = self.render_book()?;
+        self.format = "pdf";
         debug!("{}", t!("latex.attempting"));
         let mut zipper = Zipper::new(&self.book.options.get_path("crowbook.temp_dir").unwrap())?;
-        zipper.write("result.tex", content.as_bytes(), false)?;
+        zipper.set_cancellation_token(self.book.cancellation_token().clone());
+        self.render_to_sink("result.tex", &mut zipper)?;
+
+        zipper.generate_pdf(
+            self.book.options.get_str("tex.command").unwrap(),
+            "result.tex",
+            to,
+        )
+    }
+
+    /// Writes the LaTeX file, named `tex_file`, and the images it uses to a sink
+    pub fn render_to_sink(&mut self, tex_file: &str, sink: &mut dyn OutputSink) -> Result<()> {
+        let content = self.render_book()?;
+        sink.write_file(tex_file, content.as_bytes())?;
 
         // write image files
-        for (source, dest) in self.handler.images_mapping() {
-            let mut f = fs::canonicalize(source).and_then(File::open).map_err(|_| {
-                Error::file_not_found(&self.source, t!("format.image"), source.to_owned())
-            })?;
-            let mut content = vec![];
-            f.read_to_end(&mut content).map_err(|e| {
+        for (source, file, dest) in self.handler.image_files() {
+            let content = self.handler.read_image(source, &file).map_err(|e| {
+                if e.kind() == io::ErrorKind::NotFound {
+                    return Error::file_not_found(
+                        &self.source,
+                        t!("format.image"),
+                        file.to_string_lossy().into_owned(),
+                    );
+                }
                 Error::render(
                     &self.source,
                     t!("latex.image_error", error = e),
                 )
             })?;
-            zipper.write(dest, &content, true)?;
+            sink.write_file(&dest, &content)?;
         }
+        Ok(())
+    }
 
-        zipper.generate_pdf(
-            self.book.options.get_str("tex.command").unwrap(),
-            "result.tex",
-            to,
-        )
+    /// Writes the texts of the notes referenced in the block that has just been rendered
+    ///
+    /// Notes are split between a mark, where they are referenced, and their text,
+    /// after the block, since notes are fragile in tables and titles.
+    fn write_pending_notes(&mut self, content: &mut String) {
+        for note in self.pending_notes.drain(..) {
+            content.push_str(&note);
+            content.push('\n');
+        }
+    }
+
+    /// Collect the footnote definitions of a chapter, including those in conditional
+    /// blocks that are rendered for this format
+    fn collect_note_definitions(&mut self, tokens: &'a [Token]) {
+        for token in tokens {
+            match *token {
+                Token::FootnoteDefinition(ref reference, ref note) => {
+                    self.note_definitions.insert(reference.as_str(), note.as_slice());
+                }
+                Token::Conditional(ref condition, ref inner)
+                    if self.book.is_condition_met(condition, self.format) =>
+                {
+                    self.collect_note_definitions(inner);
+                }
+                _ => (),
+            }
+        }
     }
 
     /// Render latex in a string
@@ -146,77 +233,91 @@ impl<'a> LatexRenderer<'a> {
 
         // set tex numbering and toc display to book's parameters
         let numbering = self.book.options.get_i32("rendering.num_depth").unwrap() - 1;
+        let per_chapter_notes = self.book.notes_per_chapter();
+        // Languages of chapters that are not in the language of the book
+        let mut other_langs: Vec<&'static str> = vec![];
+
+        if self.book.features.variables {
+            self.variables = self.book.get_variables(
+                |s| self.render_vec(&Parser::new().parse_inline(s)?),
+                |s| escape::tex(s).into_owned(),
+            )?;
+        }
 
-        for (i, chapter) in self.book.chapters.iter().enumerate() {
+        for (i, chapter) in self.book.chapters_for(self.format).into_iter().enumerate() {
             self.handler
                 .add_link(chapter.filename.as_str(), format!("chapter-{i}"));
+            for id in syntax::listing_ids(&chapter.content) {
+                self.handler.add_link(format!("#{id}"), id);
+            }
         }
 
-        for (i, chapter) in self.book.chapters.iter().enumerate() {
+        for (i, chapter) in self.book.chapters_for(self.format).into_iter().enumerate() {
+            self.book.cancellation_token().check()?;
             let n = chapter.number;
             self.current_chapter = n;
             let v = &chapter.content;
             self.source = Source::new(chapter.filename.as_str());
+            self.note_definitions.clear();
+            self.note_numbers.clear();
+            if per_chapter_notes {
+                self.note_counter = 0;
+            }
+            self.collect_note_definitions(v);
+            self.chapter_options = self.book.chapter_options(chapter);
+            self.chapter_cleaner = self.chapter_options.as_ref().map(Book::new_cleaner);
+            if !chapter.toc {
+                content.push_str("\\addtocontents{toc}{\\protect\\setcounter{tocdepth}{-2}}\n");
+            }
+            let chapter_lang = match self.options().get_str("lang").unwrap() {
+                lang if lang == self.book.options.get_str("lang").unwrap() => None,
+                lang => match tex_lang(lang) {
+                    Some(tex_lang) => Some(tex_lang),
+                    None => {
+                        self.book.warn(t!("latex.lang_error", lang = lang).into_owned());
+                        None
+                    }
+                },
+            };
+            if let Some(tex_lang) = chapter_lang {
+                if !other_langs.contains(&tex_lang) {
+                    other_langs.push(tex_lang);
+                }
+                writeln!(content, "\\begin{{otherlanguage}}{{{tex_lang}}}")?;
+            }
             let mut offset = 0;
             if !v.is_empty() && v[0].is_header() {
                 content.push_str(&self.render_token(&v[0])?);
+                self.write_pending_notes(&mut content);
                 offset = 1;
             }
             writeln!(content, "\\label{{chapter-{i}}}")?;
-            content.push_str(&self.render_vec(&v[offset..])?);
+            let has_notes = !self.note_definitions.is_empty();
+            for token in &v[offset..] {
+                content.push_str(&self.render_token(token)?);
+                self.write_pending_notes(&mut content);
+            }
+            if self.notes == Notes::ChapterEnd && has_notes {
+                content.push_str("\\theendnotes\n");
+            }
+            if chapter_lang.is_some() {
+                content.push_str("\\end{otherlanguage}\n");
+            }
+            if !chapter.toc {
+                content.push_str("\\addtocontents{toc}{\\protect\\setcounter{tocdepth}{\\arabic{tocdepth}}}\n");
+            }
         }
         self.source = Source::empty();
+        self.chapter_options = None;
+        self.chapter_cleaner = None;
 
-        let tex_lang = String::from(match self.book.options.get_str("lang").unwrap() {
-            "af" => "afrikaans",
-            "sq" => "albanian",
-            "eu" => "basque",
-            "bg" => "bulgarian",
-            "ca" => "catalan",
-            "hr" => "croatian",
-            "cs" => "czech",
-            "da" => "danish",
-            "nl" => "dutch",
-            "en" => "english",
-            "eo" => "esperanto",
-            "et" => "estonian",
-            "fi" => "finnish",
-            "fr" => "francais",
-            "gl" => "galician",
-            "el" => "greek",
-            "de" => "ngerman",
-            "he" => "hebrew",
-            "hu" => "hungarian",
-            "it" => "italian",
-            "is" => "icelandic",
-            "id" => "indonesian",
-            "ga" => "irish",
-            "la" => "latin",
-            "ms" => "malay",
-            "nn" => "norsk",
-            "pl" => "polish",
-            "pt" => "portuguese",
-            "ro" => "romanian",
-            "ru" => "russian",
-            "gd" => "scottish",
-            "sr" => "serbian",
-            "sk" => "slovak",
-            "sl" => "slovene",
-            "es" => "spanish",
-            "sw" => "swedish",
-            "tr" => "turkish",
-            "uk" => "ukrainian",
-            "cy" => "welsh",
-            _ => {
-                warn!(
-                    "{}",
-                    t!("latex.lang_error",
-                       lang = self.book.options.get_str("lang").unwrap()
-                    )
-                );
-                "english"
-            }
-        });
+        let lang = self.book.options.get_str("lang").unwrap();
+        let mut tex_lang = String::new();
+        for other in &other_langs {
+            write!(tex_lang, "{other},")?;
+        }
+        // Babel uses the last language as the main one
+        tex_lang.push_str(tex_lang_or_default(self.book, lang));
 
         let template_src = self.book.get_template("tex.template")?;
 
@@ -308,7 +409,18 @@ impl<'a> LatexRenderer<'a> {
         
         let part_name = self.book.options.get_str("rendering.part").unwrap_or("".into());
         data.insert("part_name".into(), part_name.into());
-        data.insert("initials".into(), self.book.options.get_bool("rendering.initials").unwrap().into());
+        data.insert(
+            "initials".into(),
+            (self.use_initials || self.book.options.get_bool("rendering.initials").unwrap()).into(),
+        );
+        data.insert("use_endnotes".into(),
+                    matches!(self.notes, Notes::ChapterEnd | Notes::BookEnd).into());
+        data.insert("notes_chapter_end".into(), (self.notes == Notes::ChapterEnd).into());
+        data.insert("notes_book_end".into(), (self.notes == Notes::BookEnd).into());
+        data.insert("use_sidenotes".into(), (self.notes == Notes::Side).into());
+        data.insert("notes_global".into(), (!per_chapter_notes).into());
+        data.insert("notes_name".into(),
+                    lang::get_str(self.book.options.get_str("lang").unwrap(), "notes").into());
         // Insert xelatex if tex.command is set to xelatex or tectonic
         if (self.book.options.get_str("tex.command") == Ok("xelatex"))
             | (self.book.options.get_str("tex.command") == Ok("tectonic"))
@@ -322,13 +434,72 @@ impl<'a> LatexRenderer<'a> {
     }
 }
 
+/// Returns the name babel uses for a language
+fn tex_lang(lang: &str) -> Option<&'static str> {
+    Some(match lang {
+        "af" => "afrikaans",
+        "sq" => "albanian",
+        "eu" => "basque",
+        "bg" => "bulgarian",
+        "ca" => "catalan",
+        "hr" => "croatian",
+        "cs" => "czech",
+        "da" => "danish",
+        "nl" => "dutch",
+        "en" => "english",
+        "eo" => "esperanto",
+        "et" => "estonian",
+        "fi" => "finnish",
+        "fr" => "francais",
+        "gl" => "galician",
+        "el" => "greek",
+        "de" => "ngerman",
+        "he" => "hebrew",
+        "hu" => "hungarian",
+        "it" => "italian",
+        "is" => "icelandic",
+        "id" => "indonesian",
+        "ga" => "irish",
+        "la" => "latin",
+        "ms" => "malay",
+        "nn" => "norsk",
+        "pl" => "polish",
+        "pt" => "portuguese",
+        "ro" => "romanian",
+        "ru" => "russian",
+        "gd" => "scottish",
+        "sr" => "serbian",
+        "sk" => "slovak",
+        "sl" => "slovene",
+        "es" => "spanish",
+        "sw" => "swedish",
+        "tr" => "turkish",
+        "uk" => "ukrainian",
+        "cy" => "welsh",
+        _ => return None,
+    })
+}
+
+/// Returns the name babel uses for the language of the book, warning and
+/// defaulting to english if it isn't supported
+fn tex_lang_or_default(book: &Book, lang: &str) -> &'static str {
+    tex_lang(lang).unwrap_or_else(|| {
+        book.warn(t!("latex.lang_error", lang = lang).into_owned());
+        "english"
+    })
+}
+
 impl<'a> Renderer for LatexRenderer<'a> {
     fn render_token(&mut self, token: &Token) -> Result<String> {
         match *token {
             Token::Str(ref text) => {
                 let content = if self.escape {
-                    let mut escaped = escape::tex(self.book.clean(text.as_str()));
-                    if self.book.options.get_bool("tex.escape_nb_spaces").unwrap() {
+                    let text = match self.chapter_cleaner {
+                        Some(ref cleaner) => cleaner.clean(Cow::Borrowed(text.as_str())),
+                        None => self.book.clean(text.as_str()),
+                    };
+                    let mut escaped = escape::tex(text);
+                    if self.options().get_bool("tex.escape_nb_spaces").unwrap() {
                         escaped = escape::nb_spaces_tex(escaped)
                     }
                     escaped
@@ -337,7 +508,8 @@ impl<'a> Renderer for LatexRenderer<'a> {
                 };
                 if self.first_letter {
                     self.first_letter = false;
-                    if self.book.options.get_bool("rendering.initials").unwrap() {
+                    if self.options().get_bool("rendering.initials").unwrap() {
+                        self.use_initials = true;
                         let mut chars = content.chars().peekable();
                         let initial = chars.next().ok_or_else(|| {
                             Error::parser(
@@ -400,8 +572,7 @@ impl<'a> Renderer for LatexRenderer<'a> {
                     }
                     if self.current_chapter.is_part() {
                         if self
-                            .book
-                            .options
+                            .options()
                             .get_bool("rendering.part.reset_counter")
                             .unwrap()
                         {
@@ -451,11 +622,7 @@ impl<'a> Renderer for LatexRenderer<'a> {
                 let mut res: String = if let Some(ref syntax) = self.syntax {
                     syntax.to_tex(code, language)?
                 } else {
-                    format!(
-                        "\\begin{{spverbatim}}
-{code}
-\\end{{spverbatim}}"
-                    )
+                    CodeInfo::parse_with(language, &self.book.emitter()).tex_fallback(code)
                 };
                 res = format!(
                     "\\begin{{mdcodeblock}}
@@ -534,16 +701,25 @@ impl<'a> Renderer for LatexRenderer<'a> {
                         .get_bool("tex.links_as_footnotes")
                         .unwrap()
                     {
-                        Ok(format!(
-                            "\\href{{{url}}}{{{content}}}\\protect\\footnote{{\\url{{{url}}}}}"
-                        ))
+                        if self.notes == Notes::Footnotes {
+                            // Share the numbering of notes, that are numbered explicitly
+                            self.note_counter += 1;
+                            let n = self.note_counter;
+                            self.pending_notes
+                                .push(format!("\\footnotetext[{n}]{{\\url{{{url}}}}}"));
+                            Ok(format!("\\href{{{url}}}{{{content}}}\\protect\\footnotemark[{n}]"))
+                        } else {
+                            Ok(format!(
+                                "\\href{{{url}}}{{{content}}}\\protect\\footnote{{\\url{{{url}}}}}"
+                            ))
+                        }
                     } else {
                         Ok(format!("\\href{{{url}}}{{{content}}}"))
                     }
                 }
             }
             Token::StandaloneImage(ref url, _, _) => {
-                if ResourceHandler::is_local(url) {
+                if ResourceHandler::is_local(url) || self.handler.fetches(url) {
                     let img = self.handler.map_image(&self.source, url.as_str())?;
                     Ok(format!("\\mdstandaloneimage{{{img}}}\n"))
                 } else {
@@ -558,7 +734,7 @@ impl<'a> Renderer for LatexRenderer<'a> {
                 }
             }
             Token::Image(ref url, _, _) => {
-                if ResourceHandler::is_local(url) {
+                if ResourceHandler::is_local(url) || self.handler.fetches(url) {
                     Ok(format!(
                         "\\mdimage{{{}}}",
                         self.handler.map_image(&self.source, url.as_str())?
@@ -574,12 +750,30 @@ impl<'a> Renderer for LatexRenderer<'a> {
                     Ok(String::new())
                 }
             }
-            Token::FootnoteReference(ref reference) => Ok(format!("\\footnotemark[{reference}]")),
-            Token::FootnoteDefinition(ref reference, ref v) => Ok(format!(
-                "\\footnotetext[{}]{{{}}}",
-                reference,
-                self.render_vec(v)?
-            )),
+            Token::FootnoteReference(ref reference) => {
+                let (mark, text) = match self.notes {
+                    Notes::Footnotes => ("\\footnotemark", "\\footnotetext"),
+                    Notes::ChapterEnd | Notes::BookEnd => ("\\endnotemark", "\\endnotetext"),
+                    Notes::Side => ("\\mdsidenotemark", "\\mdsidenotetext"),
+                };
+                // A note referenced several times keeps the number it got the first time
+                if let Some(n) = self.note_numbers.get(reference.as_str()) {
+                    return Ok(format!("\\protect{mark}[{n}]"));
+                }
+                match self.note_definitions.get_key_value(reference.as_str()) {
+                    Some((&reference, &v)) => {
+                        self.note_counter += 1;
+                        let n = self.note_counter;
+                        self.note_numbers.insert(reference, n);
+                        let note = self.render_vec(v)?;
+                        self.pending_notes
+                            .push(format!("{text}[{n}]{{{}}}", note.trim_end()));
+                        Ok(format!("\\protect{mark}[{n}]"))
+                    }
+                    None => Ok(String::new()),
+                }
+            }
+            Token::FootnoteDefinition(..) => Ok(String::new()),
             Token::Table(n, ref vec) => {
                 let mut cols = String::new();
                 for _ in 0..n {
@@ -609,6 +803,18 @@ impl<'a> Renderer for LatexRenderer<'a> {
                 Ok(res)
             }
             Token::TableCell(ref vec) => self.render_vec(vec),
+            Token::Variable(ref name) => match self.variables.get(&name.replace('.', "_")) {
+                Some(value) => Ok(value.clone()),
+                None => Ok(escape::tex(format!("{{{{{name}}}}}")).into_owned()),
+            },
+            Token::PageBreak(_) => Ok(String::new()),
+            Token::Conditional(ref condition, ref vec) => {
+                if self.book.is_condition_met(condition, self.format) {
+                    self.render_vec(vec)
+                } else {
+                    Ok(String::new())
+                }
+            }
             Token::Annotation(ref annotation, ref vec) => {
                 let content = self.render_vec(vec)?;
                 if self.proofread {
@@ -654,6 +860,10 @@ impl BookRenderer for Latex {
         })?;
         Ok(())
     }
+
+    fn render_to_sink(&self, book: &Book, name: &str, sink: &mut dyn OutputSink) -> Result<()> {
+        LatexRenderer::new(book).render_to_sink(&self.auto_path(name)?, sink)
+    }
 }
 
 impl BookRenderer for ProofLatex {
@@ -672,6 +882,10 @@ impl BookRenderer for ProofLatex {
         })?;
         Ok(())
     }
+
+    fn render_to_sink(&self, book: &Book, name: &str, sink: &mut dyn OutputSink) -> Result<()> {
+        LatexRenderer::new(book).render_to_sink(&self.auto_path(name)?, sink)
+    }
 }
 
 impl BookRenderer for Pdf {
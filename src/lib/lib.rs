@@ -113,15 +113,32 @@ pub use book::Book;
 pub use book_renderer::BookRenderer;
 pub use bookoption::BookOption;
 pub use bookoptions::BookOptions;
+pub use cache::Cache;
 pub use chapter::Chapter;
+pub use check::{Check, Problem, Severity};
 pub use error::{Error, Result, Source};
+pub use images::{ImageProcessor, ImageSettings, ProcessedImage};
+pub use migrate::Migration;
+#[doc(hidden)]
+pub use misc::natural_cmp;
 pub use number::Number;
 pub use parser::Parser;
+pub use progress::{CancellationToken, Event, ProgressListener};
+pub use remote::Fetcher;
 pub use renderer::Renderer;
 pub use resource_handler::ResourceHandler;
+pub use search::SearchIndex;
+#[cfg(feature = "archive")]
+pub use sink::ZipSink;
+pub use sink::{DirSink, MemorySink, OutputSink};
 pub use stats::Stats;
+pub use syntax::CodeInfo;
+pub use theme::Theme;
+pub use token::Condition;
 pub use token::Data;
 pub use token::Token;
+pub use vfs::{BookSource, FileSystem, MemorySource};
+pub use visitor::{Fold, Visitor, VisitorMut};
 
 rust_i18n::i18n!("lang/lib", fallback="en");
 
@@ -130,21 +147,34 @@ mod html;
 mod book;
 mod book_renderer;
 mod bookoptions;
+mod cache;
 mod chapter;
+mod check;
 mod cleaner;
 mod epub;
 mod error;
+mod filter;
 mod html_dir;
 mod html_single;
+mod images;
+mod json;
 mod lang;
 mod latex;
+mod migrate;
 mod number;
 mod parser;
+mod progress;
+mod remote;
 mod renderer;
 mod resource_handler;
+mod search;
+mod sink;
 mod stats;
 mod syntax;
+mod theme;
 mod token;
+mod vfs;
+mod visitor;
 
 #[cfg(feature = "binary")]
 mod style;
@@ -157,8 +187,6 @@ use style_stubs as style;
 mod book_bars;
 #[cfg(not(feature = "indicatif"))]
 mod book_bars_stubs;
-#[cfg(not(feature = "indicatif"))]
-use book_bars_stubs as book_bars;
 
 mod bookoption;
 mod misc;
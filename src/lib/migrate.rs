@@ -0,0 +1,255 @@
+// Copyright (C) 2016-2023 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::bookoptions::BookOptions;
+use crate::error::{Error, Result, Source};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rust_i18n::t;
+
+/// Where we are in the YAML part of a book configuration file
+#[derive(PartialEq)]
+enum Section {
+    /// Top-level options
+    Top,
+    /// Inside `profiles:`; options are indented deeper than profile names
+    Profiles(Option<usize>),
+    /// Inside `profiles.name:`
+    Profile,
+}
+
+/// Result of the migration of a book configuration file, replacing
+/// deprecated option names with their current ones.
+///
+/// This works on lines rather than on parsed YAML, so comments, formatting
+/// and the list of chapters are kept as they are.
+///
+/// # Example
+///
+/// ```
+/// use crowbook::Migration;
+/// let migration = Migration::new("author: Me\nnumbering: 2\n\n+ chapter.md\n");
+/// assert_eq!(migration.content, "author: Me\nrendering.num_depth: 2\n\n+ chapter.md\n");
+/// assert_eq!(migration.changes.len(), 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Migration {
+    /// The migrated file (empty if the content didn't come from a file)
+    pub file: PathBuf,
+    /// The migrated content
+    pub content: String,
+    /// The changes that were made, with their line number
+    pub changes: Vec<(usize, String)>,
+    /// Files imported with `import`, relative to the book directory
+    pub imports: Vec<String>,
+}
+
+impl Migration {
+    /// Migrates the content of a book configuration file
+    pub fn new(content: &str) -> Migration {
+        let mut migration = Migration {
+            file: PathBuf::new(),
+            content: String::with_capacity(content.len()),
+            changes: vec![],
+            imports: vec![],
+        };
+
+        let mut section = Section::Top;
+        // Lines more indented than this are the continuation of the previous key
+        let mut continuation: Option<usize> = None;
+        let mut comment_continuation = false;
+
+        let mut offset = 0;
+        for (i, line) in content.split_inclusive('\n').enumerate() {
+            let start = offset;
+            offset += line.len();
+            let trimmed = line.trim();
+            let indent = line.len() - line.trim_start().len();
+
+            if indent == 0
+                && line.starts_with(|c| match c {
+                    '-' | '+' | '!' | '@' => true,
+                    _ => c.is_ascii_digit(),
+                })
+            {
+                // Start of the chapter list, which we keep as is
+                migration.content.push_str(&content[start..]);
+                break;
+            }
+
+            if let Some(n) = continuation {
+                if trimmed.is_empty() || indent > n {
+                    if comment_continuation && !trimmed.is_empty() {
+                        migration.content.push_str("# ");
+                    }
+                    migration.content.push_str(line);
+                    continue;
+                }
+                continuation = None;
+                comment_continuation = false;
+            }
+
+            let (key, rest) = match split_key(line) {
+                Some(x) if !trimmed.starts_with('#') => x,
+                _ => {
+                    migration.content.push_str(line);
+                    continue;
+                }
+            };
+
+            let is_option = if indent == 0 {
+                section = if key == "profiles" {
+                    Section::Profiles(None)
+                } else if key.starts_with("profiles.") {
+                    Section::Profile
+                } else {
+                    Section::Top
+                };
+                true
+            } else {
+                match section {
+                    Section::Top => false,
+                    Section::Profile => true,
+                    Section::Profiles(None) => {
+                        section = Section::Profiles(Some(indent));
+                        false
+                    }
+                    Section::Profiles(Some(n)) => indent > n,
+                }
+            };
+
+            if !is_option {
+                migration.content.push_str(line);
+                continue;
+            }
+
+            let value = rest.trim_start_matches(':').trim();
+            if (value.starts_with('|') || value.starts_with('>') || value.is_empty())
+                && !key.starts_with("profiles")
+            {
+                // Block scalar, list or hash: following indented lines belong to this key
+                continuation = Some(indent);
+            }
+
+            let new_key = BookOptions::deprecated_key(key);
+            let current_key = match new_key {
+                Some(Some(new_key)) => new_key,
+                _ => key,
+            };
+            if current_key == "import" && indent == 0 {
+                migration.imports.push(unquote(value).to_owned());
+            }
+            match new_key {
+                None => migration.content.push_str(line),
+                Some(Some(new_key)) => {
+                    migration.content.push_str(&line[..indent]);
+                    migration.content.push_str(new_key);
+                    migration.content.push_str(rest);
+                    migration.changes.push((
+                        i + 1,
+                        t!("migrate.renamed", old_key = key, new_key = new_key).into_owned(),
+                    ));
+                }
+                Some(None) => {
+                    migration.content.push_str(&line[..indent]);
+                    migration.content.push_str("# ");
+                    migration
+                        .content
+                        .push_str(&t!("migrate.comment", key = key));
+                    migration.content.push('\n');
+                    migration.content.push_str("# ");
+                    migration.content.push_str(line);
+                    if !line.ends_with('\n') {
+                        migration.content.push('\n');
+                    }
+                    comment_continuation = continuation.is_some();
+                    migration
+                        .changes
+                        .push((i + 1, t!("migrate.removed", key = key).into_owned()));
+                }
+            }
+        }
+        migration
+    }
+
+    /// Migrates a book configuration file, and the files it imports
+    ///
+    /// Files are not written, see `write`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Vec<Migration>> {
+        let mut migrations: Vec<Migration> = vec![];
+        let mut files = vec![path.as_ref().to_owned()];
+        while let Some(file) = files.pop() {
+            if migrations.iter().any(|m| m.file == file) {
+                // Already done, don't loop on circular imports
+                continue;
+            }
+            let content = fs::read_to_string(&file).map_err(|_| {
+                Error::file_not_found(
+                    Source::empty(),
+                    t!("format.book"),
+                    format!("{}", file.display()),
+                )
+            })?;
+            let mut migration = Migration::new(&content);
+            let root = file.parent().unwrap_or(Path::new(""));
+            for import in &migration.imports {
+                files.push(root.join(import));
+            }
+            migration.file = file;
+            migrations.push(migration);
+        }
+        Ok(migrations)
+    }
+
+    /// Returns true if the migration changed something
+    pub fn is_changed(&self) -> bool {
+        !self.changes.is_empty()
+    }
+
+    /// Writes the migrated content back to the file
+    pub fn write(&self) -> Result<()> {
+        fs::write(&self.file, &self.content).map_err(|err| {
+            Error::default(
+                Source::new(format!("{}", self.file.display())),
+                t!("migrate.write_error", error = err),
+            )
+        })
+    }
+}
+
+/// Splits a `key: value` line, returning the key and what follows it
+fn split_key(line: &str) -> Option<(&str, &str)> {
+    let trimmed = line.trim_start();
+    let indent = line.len() - trimmed.len();
+    let end = trimmed.find(':')?;
+    let key = &trimmed[..end];
+    if key.is_empty()
+        || !key
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '.' || c == '_' || c == '-')
+    {
+        return None;
+    }
+    Some((key, &line[indent + end..]))
+}
+
+/// Removes YAML quotes around a value
+fn unquote(s: &str) -> &str {
+    s.trim_matches(|c| c == '"' || c == '\'')
+}
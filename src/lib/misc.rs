@@ -19,6 +19,8 @@
 
 use crate::token::Token;
 
+use std::cmp::Ordering;
+use std::hash::Hasher;
 use std::io::Result;
 use std::path::{Path, PathBuf};
 use base64::Engine;
@@ -61,7 +63,89 @@ pub fn insert_title(tokens: &mut Vec<Token>) {
     tokens.insert(0, Token::Header(1, vec![]));
 }
 
+/// Adds a suffix to a file name, before its extension (e.g. `book.pdf` -> `book-print.pdf`)
+pub fn add_suffix(path: &str, suffix: &str) -> String {
+    let p = Path::new(path);
+    match (p.file_stem(), p.extension()) {
+        (Some(stem), Some(ext)) => format!(
+            "{}",
+            p.with_file_name(format!(
+                "{}-{suffix}.{}",
+                stem.to_string_lossy(),
+                ext.to_string_lossy()
+            ))
+            .display()
+        ),
+        _ => format!("{path}-{suffix}"),
+    }
+}
+
+/// Compares two strings in "natural" order, that is, comparing numbers by their value
+/// (e.g. `chapter_2.md` comes before `chapter_10.md`)
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek(), b.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let mut n1 = String::new();
+                while let Some(c) = a.next_if(|c| c.is_ascii_digit()) {
+                    n1.push(c);
+                }
+                let mut n2 = String::new();
+                while let Some(c) = b.next_if(|c| c.is_ascii_digit()) {
+                    n2.push(c);
+                }
+                let (t1, t2) = (n1.trim_start_matches('0'), n2.trim_start_matches('0'));
+                let ord = t1
+                    .len()
+                    .cmp(&t2.len())
+                    .then_with(|| t1.cmp(t2))
+                    .then_with(|| n1.len().cmp(&n2.len()));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(y);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
 /// Convert to base 64
 pub fn u8_to_base64(s: &[u8]) -> String {
     base64::engine::general_purpose::STANDARD_NO_PAD.encode(s)
 }
+
+/// Hasher whose results don't depend on the process or the version of Rust, unlike
+/// `DefaultHasher`, so they can be used to name files that are kept between runs.
+///
+/// This is the 64-bit FNV-1a algorithm.
+pub struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> StableHasher {
+        StableHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
@@ -1,10 +1,13 @@
+use serde::{Deserialize, Serialize};
+
 /// Numbering for a given chapter or part
 ///
 /// This Enum is only public so it can be passed to `Book` methods, but
 /// library users should **not** do exhaustive matches on the variants,
 /// since it is possible new variants will be added without being
 /// considered a breaking change
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "t", content = "c")]
 pub enum Number {
     /// Chapter's title is hidden
     Hidden,
@@ -16,9 +16,12 @@
 // along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::book::Book;
+use crate::cache::Cache;
 use crate::error::{Error, Result, Source};
-use crate::token::Token;
+use crate::progress::Emitter;
+use crate::token::{Condition, Token};
 
+use std::borrow::Cow;
 use std::convert::AsRef;
 use std::fs::File;
 use std::io::Read;
@@ -29,8 +32,9 @@ use std::path::Path;
 use comrak::nodes::{AstNode, ListType, NodeValue};
 use comrak::{parse_document, Arena, ComrakOptions};
 use rust_i18n::t;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 /// The list of features used in a document.
 ///
 /// This is used by the renderers to only require some packages if they
@@ -47,6 +51,7 @@ pub struct Features {
     pub superscript: bool,
     pub strikethrough: bool,
     pub taskitem: bool,
+    pub variables: bool,
 }
 
 impl Features {
@@ -64,6 +69,40 @@ impl Features {
             superscript: false,
             strikethrough: false,
             taskitem: false,
+            variables: false,
+        }
+    }
+
+    /// Returns the features used by a list of tokens, e.g. for content that
+    /// wasn't produced by `Parser`
+    pub fn from_tokens(tokens: &[Token]) -> Features {
+        let mut features = Features::new();
+        features.add_tokens(tokens);
+        features
+    }
+
+    fn add_tokens(&mut self, tokens: &[Token]) {
+        for token in tokens {
+            match token {
+                Token::Image(..) | Token::StandaloneImage(..) => self.image = true,
+                Token::FootnoteReference(..) | Token::FootnoteDefinition(..) => {
+                    self.footnote = true
+                }
+                Token::BlockQuote(..) => self.blockquote = true,
+                Token::CodeBlock(..) => self.codeblock = true,
+                Token::OrderedList(..) => self.ordered_list = true,
+                Token::Table(..) => self.table = true,
+                Token::Link(..) => self.url = true,
+                Token::Subscript(..) => self.subscript = true,
+                Token::Superscript(..) => self.superscript = true,
+                Token::Strikethrough(..) => self.strikethrough = true,
+                Token::TaskItem(..) => self.taskitem = true,
+                Token::Variable(..) => self.variables = true,
+                _ => {}
+            }
+            if let Some(inner) = token.inner() {
+                self.add_tokens(inner);
+            }
         }
     }
 }
@@ -90,10 +129,20 @@ impl BitOr for Features {
             superscript: self.superscript | rhs.superscript,
             strikethrough: self.strikethrough | rhs.strikethrough,
             taskitem: self.taskitem | rhs.taskitem,
+            variables: self.variables | rhs.variables,
         }
     }
 }
 
+/// The result of parsing a chapter, as stored in the cache
+#[derive(Serialize, Deserialize)]
+struct ParsedChapter {
+    tokens: Vec<Token>,
+    yaml_block: String,
+    features: Features,
+    variable_refs: Vec<(String, usize)>,
+}
+
 /// A parser that reads markdown and convert it to AST (a vector of `Token`s)
 ///
 /// This AST can then be used by various renderers.
@@ -127,6 +176,11 @@ pub struct Parser {
     html_as_text: bool,
     superscript: bool,
     parse_frontmatter: bool,
+    variables: bool,
+    /// Variables found in the document, with the line where they appear
+    variable_refs: Vec<(String, usize)>,
+    /// Where warnings are sent
+    emitter: Emitter,
 }
 
 impl Parser {
@@ -139,6 +193,9 @@ impl Parser {
             html_as_text: true,
             superscript: false,
             parse_frontmatter: false,
+            variables: false,
+            variable_refs: vec![],
+            emitter: Emitter::default(),
         }
     }
 
@@ -147,10 +204,12 @@ impl Parser {
         let mut parser = Parser::new();
         parser.html_as_text = book.options.get_bool("crowbook.html_as_text").unwrap();
         parser.parse_frontmatter = book.options.get_bool("input.yaml_blocks").unwrap();
+        parser.variables = book.options.get_bool("input.variables").unwrap();
         parser.superscript = book
             .options
             .get_bool("crowbook.markdown.superscript")
             .unwrap();
+        parser.emitter = book.emitter();
         parser
     }
 
@@ -159,12 +218,78 @@ impl Parser {
         self.html_as_text = b;
     }
 
+    /// Returns true if both parsers would produce the same AST from the same input
+    #[doc(hidden)]
+    pub fn has_same_settings(&self, other: &Parser) -> bool {
+        self.html_as_text == other.html_as_text
+            && self.superscript == other.superscript
+            && self.parse_frontmatter == other.parse_frontmatter
+            && self.variables == other.variables
+    }
+
     /// Sets a parser's source file
     pub fn set_source_file(&mut self, s: &str) {
         self.source = Source::new(s);
     }
 
+    /// Parse a string and returns an AST or an error, using the result of a
+    /// previous parse of the same content if it is in `cache`
+    pub fn parse_cached(
+        &mut self,
+        cache: &Cache,
+        s: &str,
+        yaml: Option<&mut String>,
+    ) -> Result<Vec<Token>> {
+        if !cache.is_enabled() {
+            return self.parse(s, yaml);
+        }
+        let key = Cache::key(&(
+            &self.source.file,
+            self.html_as_text,
+            self.superscript,
+            self.parse_frontmatter,
+            self.variables,
+            s,
+        ));
+        let cached = cache
+            .get("ast", &key)
+            .and_then(|content| serde_json::from_slice::<ParsedChapter>(&content).ok());
+        let parsed = match cached {
+            Some(parsed) => {
+                self.features = self.features | parsed.features;
+                self.variable_refs.extend(parsed.variable_refs.iter().cloned());
+                parsed
+            }
+            None => {
+                // Only keep what this content adds to the parser's state
+                let features = mem::take(&mut self.features);
+                let refs = self.variable_refs.len();
+                let mut yaml_block = String::new();
+                let tokens = self.parse(s, Some(&mut yaml_block))?;
+                let parsed = ParsedChapter {
+                    tokens,
+                    yaml_block,
+                    features: self.features,
+                    variable_refs: self.variable_refs[refs..].to_vec(),
+                };
+                self.features = self.features | features;
+                if let Ok(content) = serde_json::to_vec(&parsed) {
+                    cache.put("ast", &key, &content);
+                }
+                parsed
+            }
+        };
+        if let Some(yaml) = yaml {
+            yaml.push_str(&parsed.yaml_block);
+        }
+        Ok(parsed.tokens)
+    }
+
     /// Parse a file and returns an AST or  an error
+    ///
+    /// The file is always read from the file system: for a book with another
+    /// [`BookSource`](crate::BookSource), read it with `BookSource::read_to_string`
+    /// and use [`parse`](Parser::parse) instead.
     pub fn parse_file<P: AsRef<Path>>(&mut self, filename: P, yaml_block: Option<&mut String>) -> Result<Vec<Token>> {
         let path: &Path = filename.as_ref();
         let mut f = File::open(path).map_err(|_| {
@@ -207,12 +332,15 @@ impl Parser {
             options.extension.front_matter_delimiter = Some("---".to_owned());
         }
 
-        let root = parse_document(&arena, s, &options);
+        let s = mark_conditional_blocks(s);
+        let root = parse_document(&arena, &s, &options);
 
         let mut res = self.parse_node(root, &mut yaml)?;
 
         collapse(&mut res);
 
+        find_inline_conditions(&mut res);
+
         find_standalone(&mut res);
 
         Ok(res)
@@ -244,6 +372,49 @@ impl Parser {
         self.features
     }
 
+    /// Returns the variables (`{{name}}`) found while parsing, with their line number
+    #[doc(hidden)]
+    pub fn variable_refs(&self) -> &[(String, usize)] {
+        &self.variable_refs
+    }
+
+    /// Split a text between strings and variables
+    fn parse_variables(&mut self, text: &str, line: usize) -> Vec<Token> {
+        let mut res = vec![];
+        let mut current = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find("{{") {
+            let after = &rest[start + 2..];
+            let name = after.find("}}").map(|end| (after[..end].trim(), end));
+            match name {
+                Some((name, end))
+                    if !name.is_empty()
+                        && name
+                            .chars()
+                            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '-')) =>
+                {
+                    current.push_str(&rest[..start]);
+                    if !current.is_empty() {
+                        res.push(Token::Str(mem::take(&mut current)));
+                    }
+                    res.push(Token::Variable(name.to_owned()));
+                    self.variable_refs.push((name.to_owned(), line));
+                    self.features.variables = true;
+                    rest = &after[end + 2..];
+                }
+                _ => {
+                    current.push_str(&rest[..start + 2]);
+                    rest = after;
+                }
+            }
+        }
+        current.push_str(rest);
+        if !current.is_empty() {
+            res.push(Token::Str(current));
+        }
+        res
+    }
+
     fn parse_node<'a>(&mut self, node: &'a AstNode<'a>, yaml_block: &mut Option<&mut String>) -> Result<Vec<Token>> {
         let mut inner = vec![];
 
@@ -251,9 +422,38 @@ impl Parser {
         if let NodeValue::DescriptionTerm = node.data.borrow().value {
             self.ignore_paragraphs = true;
         }
+        // Conditional blocks that are currently open, with the content that preceded them
+        let mut open_conditions: Vec<(Condition, Vec<Token>)> = vec![];
         for c in node.children() {
-            let mut v = self.parse_node(c, yaml_block)?;
-            inner.append(&mut v);
+            let marker = match c.data.borrow().value {
+                NodeValue::HtmlBlock(ref block) => block
+                    .literal
+                    .trim()
+                    .strip_prefix(CONDITION_MARKER)
+                    .and_then(|m| m.strip_suffix("-->"))
+                    .map(Condition::parse),
+                _ => None,
+            };
+            match marker {
+                Some(Some(condition)) => {
+                    open_conditions.push((condition, mem::take(&mut inner)));
+                }
+                Some(None) => {
+                    if let Some((condition, outer)) = open_conditions.pop() {
+                        let content = mem::replace(&mut inner, outer);
+                        inner.push(Token::Conditional(condition, content));
+                    }
+                }
+                None => {
+                    let mut v = self.parse_node(c, yaml_block)?;
+                    inner.append(&mut v);
+                }
+            }
+        }
+        while let Some((condition, outer)) = open_conditions.pop() {
+            self.emitter.warn(t!("parser.unclosed_condition", file = self.source).into_owned());
+            let content = mem::replace(&mut inner, outer);
+            inner.push(Token::Conditional(condition, content));
         }
         // Reset state after special cases shenanigans
         if let NodeValue::DescriptionTerm = node.data.borrow().value {
@@ -295,7 +495,9 @@ impl Parser {
             }
             NodeValue::HtmlBlock(ref block) => {
                 let text = block.literal.clone();
-                if self.html_as_text {
+                if let Some(page) = page_break(&text) {
+                    vec![Token::PageBreak(page)]
+                } else if self.html_as_text {
                     vec![Token::Str(text)]
                 } else {
                     debug!("{}", t!("parser.ignore_html", block = text));
@@ -304,7 +506,9 @@ impl Parser {
             }
             NodeValue::HtmlInline(ref html) => {
                 let text = html.clone();
-                if self.html_as_text {
+                if let Some(page) = page_break(&text) {
+                    vec![Token::PageBreak(page)]
+                } else if self.html_as_text {
                     vec![Token::Str(text)]
                 } else {
                     debug!("{}", t!("parser.ignore_html", block = text));
@@ -325,8 +529,12 @@ impl Parser {
                 vec![Token::FootnoteDefinition(reference.name, inner)]
             }
             NodeValue::Text(ref text) => {
-                let text = text.clone();
-                vec![Token::Str(text)]
+                if self.variables && text.contains("{{") {
+                    let line = node.data.borrow().sourcepos.start.line;
+                    self.parse_variables(text, line)
+                } else {
+                    vec![Token::Str(text.clone())]
+                }
             }
             NodeValue::Code(ref code) => {
                 let text = code.literal.clone();
@@ -396,6 +604,210 @@ impl Default for Parser {
     }
 }
 
+/// Returns the page number of a page break marker (`<!-- pagebreak 12 -->`)
+fn page_break(html: &str) -> Option<String> {
+    let page = html
+        .trim()
+        .strip_prefix("<!--")?
+        .strip_suffix("-->")?
+        .trim()
+        .strip_prefix("pagebreak ")?
+        .trim();
+    if !page.is_empty() && page.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        Some(page.to_owned())
+    } else {
+        None
+    }
+}
+
+/// Prefix of the HTML comments that replace the fences of conditional blocks
+const CONDITION_MARKER: &str = "<!-- crowbook-condition ";
+
+/// Replace the fences of conditional blocks (`::: {only=pdf}` ... `:::`) by HTML comments,
+/// so they are parsed as standalone blocks while keeping line numbers intact.
+///
+/// Fences inside code blocks, and `:::` fences that do not open a condition, are left untouched.
+fn mark_conditional_blocks(s: &str) -> Cow<'_, str> {
+    if !s.contains(":::") {
+        return Cow::Borrowed(s);
+    }
+    let mut res = String::with_capacity(s.len());
+    let mut code_fence: Option<String> = None;
+    // For each open fenced div, whether it is a condition
+    let mut fences: Vec<bool> = vec![];
+    for line in s.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\n', '\r']);
+        let indent = content.len() - content.trim_start_matches(' ').len();
+        let trimmed = content.trim();
+        if indent < 4 {
+            if let Some(ref fence) = code_fence {
+                if trimmed.starts_with(fence.as_str()) && trimmed.trim_start_matches(&fence[..1]).is_empty() {
+                    code_fence = None;
+                }
+            } else if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                let c = trimmed.chars().next().unwrap();
+                let len = trimmed.len() - trimmed.trim_start_matches(c).len();
+                code_fence = Some(trimmed[..len].to_owned());
+            } else if let Some(rest) = trimmed.strip_prefix(":::") {
+                let rest = rest.trim_start_matches(':').trim();
+                if rest.is_empty() {
+                    // Closing fence: only replace it if it closes a condition
+                    if fences.pop() == Some(true) {
+                        res.push_str(&content[..indent]);
+                        res.push_str(CONDITION_MARKER);
+                        res.push_str("-->");
+                        res.push_str(&line[content.len()..]);
+                        continue;
+                    }
+                } else if let Some(condition) = Condition::parse(rest) {
+                    fences.push(true);
+                    res.push_str(&content[..indent]);
+                    res.push_str(CONDITION_MARKER);
+                    res.push_str(if condition.only { "only=" } else { "unless=" });
+                    res.push_str(&condition.keys.join(","));
+                    res.push_str(" -->");
+                    res.push_str(&line[content.len()..]);
+                    continue;
+                } else {
+                    fences.push(false);
+                }
+            }
+        }
+        res.push_str(line);
+    }
+    Cow::Owned(res)
+}
+
+/// Find the end of an inline condition (`]{only=...}`) in a string, starting at `from`.
+///
+/// Returns the positions of the closing bracket and of the closing brace, and the condition.
+fn find_condition_end(s: &str, from: usize) -> Option<(usize, usize, Condition)> {
+    let mut from = from;
+    while let Some(pos) = s[from..].find("]{") {
+        let start = from + pos;
+        if let Some(len) = s[start..].find('}') {
+            let end = start + len;
+            if let Some(condition) = Condition::parse(&s[start + 1..=end]) {
+                return Some((start, end, condition));
+            }
+        }
+        from = start + 2;
+    }
+    None
+}
+
+/// Returns true if the bracket at `pos` in the string `ast[j]` can open a conditional
+/// span, i.e. it starts a run of text instead of following a word (as in `array[0]`)
+fn opens_span(ast: &[Token], j: usize, pos: usize) -> bool {
+    let s = match ast[j] {
+        Token::Str(ref s) => s,
+        _ => return false,
+    };
+    match s[..pos].chars().next_back() {
+        Some(c) => {
+            c.is_whitespace() || matches!(c, '(' | '"' | '\'' | '«' | '“' | '‘' | '—' | '–')
+        }
+        None => j == 0 || matches!(ast[j - 1], Token::SoftBreak | Token::HardBreak),
+    }
+}
+
+/// Replace inline conditional spans (`[some text]{unless=epub}`) by conditional tokens
+///
+/// A condition directly following a link or an image (`[text](url){only=html}`)
+/// applies to it.
+fn find_inline_conditions(ast: &mut Vec<Token>) {
+    for token in ast.iter_mut() {
+        if let Some(inner) = token.inner_mut() {
+            find_inline_conditions(inner);
+        }
+    }
+
+    let mut i = 1;
+    while i < ast.len() {
+        let found = match (&ast[i - 1], &ast[i]) {
+            (Token::Link(..) | Token::Image(..), Token::Str(ref s)) if s.starts_with('{') => s
+                .find('}')
+                .and_then(|end| Condition::parse(&s[..=end]).map(|c| (end, c))),
+            _ => None,
+        };
+        if let Some((end, condition)) = found {
+            let rest = match ast[i] {
+                Token::Str(ref s) => s[end + 1..].to_owned(),
+                _ => unreachable!(),
+            };
+            let link = ast[i - 1].clone();
+            ast[i - 1] = Token::Conditional(condition, vec![link]);
+            ast[i] = Token::Str(rest);
+        }
+        i += 1;
+    }
+
+    let mut i = 0;
+    let mut from = 0;
+    while i < ast.len() {
+        let found = if let Token::Str(ref s) = ast[i] {
+            find_condition_end(s, from)
+        } else {
+            None
+        };
+        let (close, end, condition) = match found {
+            Some(found) => found,
+            None => {
+                i += 1;
+                from = 0;
+                continue;
+            }
+        };
+        let text = if let Token::Str(ref s) = ast[i] {
+            s.clone()
+        } else {
+            unreachable!();
+        };
+
+        // Look for the opening bracket, in this string or in a previous one
+        let opening = if let Some(pos) = text[..close].rfind('[') {
+            Some((i, pos))
+        } else {
+            (0..i).rev().find_map(|j| match ast[j] {
+                Token::Str(ref s) => s.rfind('[').map(|pos| (j, pos)),
+                _ => None,
+            })
+        };
+        let (j, pos) = match opening {
+            Some((j, pos)) if opens_span(ast, j, pos) => (j, pos),
+            _ => {
+                from = end;
+                continue;
+            }
+        };
+
+        let mut replacement = vec![];
+        let mut content = vec![];
+        if j == i {
+            replacement.push(Token::Str(text[..pos].to_owned()));
+            content.push(Token::Str(text[pos + 1..close].to_owned()));
+        } else {
+            let mut tokens: Vec<Token> = ast.drain(j..i).collect();
+            if let Token::Str(ref s) = tokens[0] {
+                replacement.push(Token::Str(s[..pos].to_owned()));
+                tokens[0] = Token::Str(s[pos + 1..].to_owned());
+            }
+            content.append(&mut tokens);
+            content.push(Token::Str(text[..close].to_owned()));
+            i = j;
+        }
+        content.retain(|t| t != &Token::Str(String::new()));
+        replacement.retain(|t| t != &Token::Str(String::new()));
+        replacement.push(Token::Conditional(condition, content));
+        let n = replacement.len();
+        replacement.push(Token::Str(text[end + 1..].to_owned()));
+        ast.splice(i..=i, replacement);
+        i += n;
+        from = 0;
+    }
+    ast.retain(|t| t != &Token::Str(String::new()));
+}
+
 /// Replace consecutives Strs by a Str of both, collapse soft breaks to previous std and so on
 fn collapse(ast: &mut Vec<Token>) {
     let mut i = 0;
@@ -0,0 +1,159 @@
+// Copyright (C) 2016-2023 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Progress of loading and rendering a book, and cancellation
+
+use crate::error::{Error, Result};
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Something that happened while loading or rendering a book
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Event {
+    /// What the book is currently doing, e.g. "Parsing chapters"
+    Status(String),
+    /// A chapter file was loaded. `current` goes from 1 to `total`, the number
+    /// of chapter files listed in the configuration file.
+    ChapterLoaded {
+        file: String,
+        current: usize,
+        total: usize,
+    },
+    /// A format started rendering
+    FormatStarted { format: String },
+    /// A format was rendered, to `path` if it was written to a file
+    FormatFinished {
+        format: String,
+        path: Option<String>,
+    },
+    /// A format could not be rendered
+    FormatFailed { format: String, error: String },
+    /// A warning about the content of the book
+    Warning(String),
+    /// An error that stops loading or rendering the book
+    Error(String),
+    /// All formats have been rendered
+    Finished,
+}
+
+/// Receives the events of a book, e.g. to display progress in a user interface.
+///
+/// Formats are rendered in parallel, so `on_event` can be called from
+/// several threads.
+///
+/// # Example
+///
+/// ```
+/// use crowbook::{Book, Event};
+/// use std::sync::{Arc, Mutex};
+///
+/// let events = Arc::new(Mutex::new(vec![]));
+/// let recorded = events.clone();
+/// let mut book = Book::new();
+/// book.add_progress_listener(move |event: &Event| recorded.lock().unwrap().push(event.clone()));
+/// book.read_markdown_config("# Hello\n\nWorld".as_bytes()).unwrap();
+/// book.render_format_to("html", &mut vec![]).unwrap();
+/// assert!(events.lock().unwrap().contains(&Event::Finished));
+/// ```
+pub trait ProgressListener: Send + Sync {
+    /// Called for each event
+    fn on_event(&self, event: &Event);
+}
+
+impl<F: Fn(&Event) + Send + Sync> ProgressListener for F {
+    fn on_event(&self, event: &Event) {
+        self(event)
+    }
+}
+
+/// Sends events to the progress listeners of a book.
+///
+/// Clones share the same listeners, so parts of Crowbook that don't have access
+/// to the book (e.g. the resource handler or the parser) can report warnings too.
+/// Without listeners, warnings are only logged.
+#[derive(Clone, Default)]
+pub(crate) struct Emitter {
+    listeners: Vec<Arc<dyn ProgressListener>>,
+}
+
+impl Emitter {
+    /// Adds a listener
+    pub fn add(&mut self, listener: Arc<dyn ProgressListener>) {
+        self.listeners.push(listener);
+    }
+
+    /// Sends an event to the listeners
+    pub fn emit(&self, event: Event) {
+        for listener in &self.listeners {
+            listener.on_event(&event);
+        }
+    }
+
+    /// Logs a warning about the content of the book, and sends it to the listeners
+    pub fn warn(&self, msg: String) {
+        warn!("{}", msg);
+        self.emit(Event::Warning(msg));
+    }
+}
+
+impl fmt::Debug for Emitter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Emitter")
+            .field("listeners", &self.listeners.len())
+            .finish()
+    }
+}
+
+/// Allows to stop rendering from another thread.
+///
+/// Clones share the same state, so a clone can be given to a book with
+/// `Book::set_cancellation_token` and cancelled from elsewhere. Formats that
+/// have not started rendering are skipped, and running LaTeX commands are
+/// stopped; they fail with an error for which `Error::is_cancelled` is true.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Creates a new token, that isn't cancelled
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Cancels the operations using this token
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns true if `cancel` has been called
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns an error if `cancel` has been called
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(Error::cancelled())
+        } else {
+            Ok(())
+        }
+    }
+}
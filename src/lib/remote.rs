@@ -0,0 +1,182 @@
+// Copyright (C) 2025 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Download of remote images, so they can be included like local files
+
+use crate::bookoptions::BookOptions;
+use crate::error::{Error, Result, Source};
+use crate::misc::StableHasher;
+
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rust_i18n::t;
+
+/// Maximum size of a downloaded file
+#[cfg(feature = "remote")]
+const MAX_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Downloads remote (`http` or `https`) files into a cache directory.
+///
+/// Files are named after a hash of their URL, so a file that was already
+/// downloaded is reused without accessing the network.
+#[derive(Debug, Clone)]
+pub struct Fetcher {
+    cache: PathBuf,
+    timeout: Duration,
+    offline: bool,
+}
+
+impl Fetcher {
+    /// Creates a new fetcher, writing files in `cache`
+    pub fn new<P: Into<PathBuf>>(cache: P, timeout: Duration, offline: bool) -> Fetcher {
+        Fetcher {
+            cache: cache.into().join("remote"),
+            timeout,
+            offline,
+        }
+    }
+
+    /// Returns a fetcher configured by `resources.fetch_remote.*` options,
+    /// or `None` if `resources.fetch_remote` is not set
+    pub fn from_options(options: &BookOptions) -> Option<Fetcher> {
+        if !options.get_bool("resources.fetch_remote").unwrap() {
+            return None;
+        }
+        let timeout = options.get_i32("resources.fetch_remote.timeout").unwrap();
+        Some(Fetcher::new(
+            options.get_path("crowbook.cache_dir").unwrap(),
+            Duration::from_secs(timeout.max(1) as u64),
+            options.get_bool("resources.fetch_remote.offline").unwrap(),
+        ))
+    }
+
+    /// Returns true if this url can be fetched
+    pub fn is_remote(url: &str) -> bool {
+        let url = url.to_lowercase();
+        url.starts_with("http://") || url.starts_with("https://")
+    }
+
+    /// Returns the local copy of a remote file, downloading it if it isn't
+    /// in the cache yet
+    pub fn fetch(&self, source: &Source, url: &str) -> Result<PathBuf> {
+        let name = hash(url);
+        if let Some(file) = self.cached(&name) {
+            return Ok(file);
+        }
+        if self.offline {
+            return Err(Error::render(source, t!("remote.offline", url = url)));
+        }
+        let (content, content_type) = self.download(source, url)?;
+        let extension = extension(url).or_else(|| {
+            let mime = content_type?;
+            let extensions = mime_guess::get_mime_extensions_str(mime.split(';').next()?.trim())?;
+            extensions.first().map(|e| e.to_string())
+        });
+        let file = match extension {
+            Some(extension) => self.cache.join(format!("{name}.{extension}")),
+            None => self.cache.join(&name),
+        };
+        self.write(source, &file, &content)?;
+        Ok(file)
+    }
+
+    /// Returns the file containing a previous download, if there is one
+    fn cached(&self, name: &str) -> Option<PathBuf> {
+        fs::read_dir(&self.cache)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .find(|path| {
+                path.is_file()
+                    && path.file_stem().map(|s| s == name).unwrap_or(false)
+                    && path.extension().map(|e| e != "part").unwrap_or(true)
+            })
+    }
+
+    /// Writes a downloaded file, going through a temporary file so an
+    /// interrupted build doesn't leave an incomplete file in the cache
+    fn write(&self, source: &Source, file: &Path, content: &[u8]) -> Result<()> {
+        let tmp = file.with_extension("part");
+        fs::create_dir_all(&self.cache)
+            .and_then(|_| fs::write(&tmp, content))
+            .and_then(|_| fs::rename(&tmp, file))
+            .map_err(|err| {
+                Error::render(
+                    source,
+                    t!("remote.write", file = file.display(), error = err),
+                )
+            })
+    }
+
+    /// Downloads a file, returning its content and its content type
+    #[cfg(feature = "remote")]
+    fn download(&self, source: &Source, url: &str) -> Result<(Vec<u8>, Option<String>)> {
+        info!("{}", t!("remote.fetching", url = url));
+        let agent: ureq::Agent = ureq::Agent::config_builder()
+            .timeout_global(Some(self.timeout))
+            .build()
+            .into();
+        let error = |err: ureq::Error| {
+            Error::render(source, t!("remote.fetch", url = url, error = err))
+        };
+        let mut response = agent.get(url).call().map_err(error)?;
+        let content_type = response
+            .headers()
+            .get("content-type")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_owned());
+        let content = response
+            .body_mut()
+            .with_config()
+            .limit(MAX_SIZE)
+            .read_to_vec()
+            .map_err(error)?;
+        Ok((content, content_type))
+    }
+
+    #[cfg(not(feature = "remote"))]
+    fn download(&self, source: &Source, url: &str) -> Result<(Vec<u8>, Option<String>)> {
+        let _ = self.timeout;
+        Err(Error::render(source, t!("remote.no_support", url = url)))
+    }
+}
+
+/// Hash of an url, used as the name of its local copy
+///
+/// It must not change between runs, or offline mode wouldn't find downloaded files.
+fn hash(url: &str) -> String {
+    let mut hasher = StableHasher::default();
+    url.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Returns the extension of the file an url points to, if there is one
+fn extension(url: &str) -> Option<String> {
+    let url = url.split(['?', '#']).next()?;
+    let path = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let (_, path) = path.split_once('/')?;
+    let name = path.rsplit('/').next()?;
+    let (_, extension) = name.rsplit_once('.')?;
+    if !extension.is_empty() && extension.chars().all(|c| c.is_ascii_alphanumeric()) {
+        Some(extension.to_lowercase())
+    } else {
+        None
+    }
+}
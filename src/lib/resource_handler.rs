@@ -1,14 +1,18 @@
 use crate::error::{Error, Result, Source};
+use crate::images::{ImageProcessor, ProcessedImage};
 use crate::token::Token;
 use crate::misc;
+use crate::progress::Emitter;
+use crate::remote::Fetcher;
+use crate::vfs::{BookSource, FileSystem};
 
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fs;
-use std::io::Read;
+use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use walkdir::WalkDir;
 use rust_i18n::t;
 
 /// Resource Handler.
@@ -27,6 +31,17 @@ pub struct ResourceHandler {
     /// make sure all image files will be included in e.g. the Epub document.
     #[doc(hidden)]
     pub images: HashMap<String, String>,
+
+    /// Processes images (resizing, conversion) for the output format, if needed
+    processor: Option<ImageProcessor>,
+    /// Images that were processed, by original file name
+    processed: HashMap<String, ProcessedImage>,
+    /// Downloads remote images, if they must be included like local ones
+    fetcher: Option<Fetcher>,
+    /// Where local images are read from
+    book_source: Arc<dyn BookSource>,
+    /// Where warnings are sent
+    emitter: Emitter,
 }
 
 impl ResourceHandler {
@@ -37,6 +52,11 @@ impl ResourceHandler {
             images: HashMap::new(),
             map_images: false,
             base64: false,
+            processor: None,
+            processed: HashMap::new(),
+            fetcher: None,
+            book_source: Arc::new(FileSystem),
+            emitter: Emitter::default(),
         }
     }
 
@@ -56,27 +76,69 @@ impl ResourceHandler {
         self.base64 = b;
     }
 
+    /// Sets the processor used to resize or convert images when they are mapped
+    pub fn set_image_processor(&mut self, mut processor: Option<ImageProcessor>) {
+        if let Some(ref mut processor) = processor {
+            processor.emitter = self.emitter.clone();
+        }
+        self.processor = processor;
+    }
+
+    /// Sets the fetcher used to download remote images when they are mapped
+    pub fn set_fetcher(&mut self, fetcher: Option<Fetcher>) {
+        self.fetcher = fetcher;
+    }
+
+    /// Sets where local images are read from
+    pub fn set_book_source(&mut self, book_source: Arc<dyn BookSource>) {
+        self.book_source = book_source;
+    }
+
+    /// Sets where warnings about images and links are sent
+    pub(crate) fn set_emitter(&mut self, emitter: Emitter) {
+        if let Some(ref mut processor) = self.processor {
+            processor.emitter = emitter.clone();
+        }
+        self.emitter = emitter;
+    }
+
+    /// Reads the content of an image returned by `image_files`
+    ///
+    /// The original images are read from the book source, while processed or
+    /// downloaded ones are read from the file system.
+    pub fn read_image(&self, original: &str, file: &Path) -> io::Result<Vec<u8>> {
+        if Path::new(original) == file {
+            self.book_source.read(file)
+        } else {
+            fs::read(file)
+        }
+    }
+
+    /// Returns true if this remote image will be downloaded and included
+    /// like a local one
+    pub fn fetches(&self, url: &str) -> bool {
+        self.fetcher.is_some() && Fetcher::is_remote(url)
+    }
+
     /// Add a local image file and get the resulting transformed
     /// file name
+    ///
+    /// Remote images are also handled if a fetcher has been set.
     pub fn map_image<'a, S: Into<Cow<'a, str>>>(
         &'a mut self,
         source: &Source,
         file: S,
     ) -> Result<Cow<'a, str>> {
-        // If image is not local, do nothing much
         let file = file.into();
-        if !Self::is_local(file.as_ref()) {
-            warn!(
-                "{}",
-                t!("resources.non_local",
-                   file = file
-                )
-            );
+        let remote = self.fetches(file.as_ref());
+        // If image is not local, do nothing much
+        if !remote && !Self::is_local(file.as_ref()) {
+            self.emitter.warn(t!("resources.non_local", file = file).into_owned());
             return Ok(file);
         }
 
         // Check exisence of the file
-        if fs::metadata(file.as_ref()).is_err() {
+        if !remote && !self.book_source.is_file(Path::new(file.as_ref())) {
             return Err(Error::file_not_found(
                 source,
                 t!("format.image"),
@@ -94,45 +156,65 @@ impl ResourceHandler {
             return Ok(Cow::Borrowed(&self.images[file.as_ref()]));
         }
 
+        // Remote images are downloaded, and then handled as local files
+        let local = match self.fetcher {
+            Some(ref fetcher) if remote => fetcher.fetch(source, file.as_ref())?,
+            _ => PathBuf::from(file.as_ref()),
+        };
+        let mut processed = match self.processor {
+            Some(ref processor) if remote => processor.process(source, &local)?,
+            Some(ref processor) => processor.process_in(&*self.book_source, source, &local)?,
+            None => None,
+        };
+        if remote && processed.is_none() {
+            processed = Some(ProcessedImage {
+                extension: local
+                    .extension()
+                    .map(|e| e.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                file: local,
+                width: None,
+                variants: vec![],
+            });
+        }
+        let path = match processed {
+            Some(ref processed) => processed.file.clone(),
+            None => PathBuf::from(file.as_ref()),
+        };
+
         // Else, create a new file name that has same extension
         // (or a base64 version of the file)
         let dest_file = if !(self.base64) {
-            if let Some(extension) = Path::new(file.as_ref()).extension() {
+            if let Some(extension) = path.extension() {
                 format!(
                     "images/image_{}.{}",
                     self.images.len(),
                     extension.to_string_lossy()
                 )
             } else {
-                warn!(
-                    "{}",
-                    t!("resources.no_ext",
-                        file = file
-                    )
-                );
+                self.emitter.warn(t!("resources.no_ext", file = file).into_owned());
                 format!("images/image_{}", self.images.len())
             }
         } else {
-            let mut f = match fs::canonicalize(file.as_ref()).and_then(fs::File::open) {
-                Ok(f) => f,
-                Err(_) => {
+            let content = match self.read_image(file.as_ref(), &path) {
+                Ok(content) => content,
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
                     return Err(Error::file_not_found(
                         source,
                         t!("format.image"),
                         format!("{file}"),
                     ));
                 }
+                Err(_) => {
+                    error!(
+                        "{}",
+                        t!("resources.read_error", file = file)
+                    );
+                    return Ok(file);
+                }
             };
-            let mut content: Vec<u8> = vec![];
-            if f.read_to_end(&mut content).is_err() {
-                error!(
-                    "{}",
-                    t!("resources.read_error", file = file)
-                );
-                return Ok(file);
-            }
             let base64 = misc::u8_to_base64(&content);
-            match mime_guess::from_path(file.as_ref()).first() {
+            match mime_guess::from_path(&path).first() {
                 None => {
                     error!(
                         "{}",
@@ -146,6 +228,9 @@ impl ResourceHandler {
             }
         };
 
+        if let Some(processed) = processed {
+            self.processed.insert(file.to_string(), processed);
+        }
         self.images.insert(file.into_owned(), dest_file.clone());
         Ok(Cow::Owned(dest_file))
     }
@@ -156,6 +241,47 @@ impl ResourceHandler {
         &self.images
     }
 
+    /// Returns the files that must be written for mapped images, as
+    /// `(original file, file to read, destination)`
+    ///
+    /// Unlike `images_mapping`, this takes processed images and their
+    /// smaller versions into account.
+    #[doc(hidden)]
+    pub fn image_files(&self) -> Vec<(&str, PathBuf, String)> {
+        let mut files = vec![];
+        for (original, dest) in &self.images {
+            match self.processed.get(original) {
+                Some(processed) => {
+                    files.push((original.as_str(), processed.file.clone(), dest.clone()));
+                    for (width, file) in &processed.variants {
+                        files.push((original.as_str(), file.clone(), variant_name(dest, *width)));
+                    }
+                }
+                None => files.push((original.as_str(), PathBuf::from(original), dest.clone())),
+            }
+        }
+        files
+    }
+
+    /// Returns the value of the `srcset` attribute for a mapped image, if
+    /// smaller versions of it were generated
+    pub fn srcset(&self, file: &str) -> Option<String> {
+        let processed = self.processed.get(file)?;
+        let dest = self.images.get(file)?;
+        if processed.variants.is_empty() || self.base64 {
+            return None;
+        }
+        let mut candidates: Vec<String> = processed
+            .variants
+            .iter()
+            .map(|(width, _)| format!("{} {width}w", variant_name(dest, *width)))
+            .collect();
+        if let Some(width) = processed.width {
+            candidates.push(format!("{dest} {width}w"));
+        }
+        Some(candidates.join(", "))
+    }
+
     /// Add a match between an original file and a dest file
     pub fn add_link<S1: Into<String>, S2: Into<String>>(&mut self, from: S1, to: S2) {
         self.links.insert(from.into(), to.into());
@@ -171,12 +297,8 @@ impl ResourceHandler {
             if let Some(link) = self.links.get(&new_from) {
                 link
             } else {
-                warn!(
-                    "{}",
-                    t!("resources.no_match",
-                        file = from,
-                        new_from = new_from
-                    )
+                self.emitter.warn(
+                    t!("resources.no_match", file = from, new_from = new_from).into_owned(),
                 );
                 from
             }
@@ -231,6 +353,20 @@ impl ResourceHandler {
     }
 }
 
+/// Returns the destination of a smaller version of an image, e.g.
+/// `images/image_0-480w.jpg` for `images/image_0.jpg`
+fn variant_name(dest: &str, width: u32) -> String {
+    let path = Path::new(dest);
+    match path.extension() {
+        Some(extension) => format!(
+            "{}-{width}w.{}",
+            path.with_extension("").to_string_lossy(),
+            extension.to_string_lossy()
+        ),
+        None => format!("{dest}-{width}w"),
+    }
+}
+
 impl Default for ResourceHandler {
     fn default() -> Self {
         Self::new()
@@ -242,47 +378,38 @@ impl Default for ResourceHandler {
 /// # Arguments
 /// - list: a list of files
 /// - base: the path where to get them
+/// - files: where to read them from
 ///
 /// # Returns
 /// A list of files (relative to `base`), or an error.
-pub fn get_files(list: &[String], base: &str) -> Result<Vec<String>> {
+pub fn get_files(list: &[String], base: &str, files: &dyn BookSource) -> Result<Vec<String>> {
     let mut out: Vec<String> = vec![];
     let base = Path::new(base);
     for path in list {
         let abs_path = base.join(path);
-        let res = fs::metadata(&abs_path);
-        match res {
-            Err(err) => {
-                return Err(Error::render(
+        if files.is_file(&abs_path) {
+            out.push(path.clone());
+        } else if files.is_dir(&abs_path) {
+            let list = files.files_in(&abs_path).map_err(|err| {
+                Error::render(
                     Source::empty(),
                     t!("resources.read_file",
                         file = abs_path.display(),
                         error = err
                     ),
-                ))
-            }
-            Ok(metadata) => {
-                if metadata.is_file() {
-                    out.push(path.clone());
-                } else if metadata.is_dir() {
-                    let files = WalkDir::new(&abs_path)
-                        .follow_links(true)
-                        .into_iter()
-                        .filter_map(|e| e.ok())
-                        .filter(|e| e.file_type().is_file())
-                        .map(|e| PathBuf::from(e.path().strip_prefix(base).unwrap()));
-                    for file in files {
-                        out.push(file.to_string_lossy().into_owned());
-                    }
-                } else {
-                    return Err(Error::render(
-                        Source::empty(),
-                        t!("resources.no_path",
-                            path = &path
-                        ),
-                    ));
-                }
+                )
+            })?;
+            for file in list {
+                let file = file.strip_prefix(base).unwrap_or(&file);
+                out.push(file.to_string_lossy().into_owned());
             }
+        } else {
+            return Err(Error::render(
+                Source::empty(),
+                t!("resources.no_path",
+                    path = &path
+                ),
+            ));
         }
     }
     Ok(out)
@@ -0,0 +1,168 @@
+// Copyright (C) 2025 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Full-text search index for the multi-files HTML renderer
+
+use serde::Serialize;
+
+use std::collections::BTreeMap;
+
+/// Words shorter than this (in characters) are not indexed
+const MIN_LENGTH: usize = 2;
+
+/// Stems are never shorter than this (in characters)
+const MIN_STEM: usize = 3;
+
+/// Returns the suffixes stripped to get the stem of a word, longest first
+///
+/// This is a (very) light stemmer, but the same suffixes are used by the
+/// JavaScript code to stem the search query, so they only need to be
+/// consistent.
+fn suffixes(lang: &str) -> &'static [&'static str] {
+    match lang.get(0..2).unwrap_or(lang) {
+        "en" => &[
+            "ational", "ization", "fulness", "ousness", "iveness", "tional", "ments", "ement",
+            "ness", "ings", "ment", "edly", "ing", "ies", "ers", "est", "ed", "er", "es", "ly",
+            "s",
+        ],
+        "fr" => &[
+            "issements", "issement", "atrices", "ations", "ements", "ation", "ement", "ances",
+            "euses", "ences", "ance", "euse", "ence", "ités", "ives", "eaux", "ité", "ive", "ifs",
+            "aux", "eux", "ant", "ent", "ées", "ée", "és", "es", "er", "ez", "if", "e", "s", "x",
+        ],
+        "de" => &[
+            "ungen", "heit", "keit", "ung", "ern", "em", "en", "er", "es", "e", "n", "s",
+        ],
+        "es" => &[
+            "aciones", "amente", "ación", "mente", "idad", "es", "os", "as", "s", "o", "a",
+        ],
+        "it" => &[
+            "amente", "zione", "zioni", "mente", "ità", "i", "e", "o", "a",
+        ],
+        "ca" => &["ament", "ció", "ions", "es", "os", "s", "a"],
+        _ => &[],
+    }
+}
+
+/// Returns the stem of a (lowercase) word
+fn stem<'w>(word: &'w str, suffixes: &[&str]) -> &'w str {
+    for suffix in suffixes {
+        if let Some(stem) = word.strip_suffix(suffix) {
+            if stem.chars().count() >= MIN_STEM {
+                return stem;
+            }
+        }
+    }
+    word
+}
+
+/// Splits a text in lowercase words
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() >= MIN_LENGTH)
+        .map(|w| w.to_lowercase())
+}
+
+/// Search index of a book, mapping the stems of the words to the chapters
+/// they appear in.
+///
+/// The index is written as a JavaScript file (see `to_js`) rather than as
+/// JSON, so it can be loaded with a `script` tag and the search page works
+/// when the book is opened directly from the filesystem.
+#[derive(Debug, Clone)]
+pub struct SearchIndex {
+    suffixes: &'static [&'static str],
+    /// URL and title of each chapter
+    docs: Vec<(String, String)>,
+    /// For each stem, the chapters it appears in and the number of occurrences
+    terms: BTreeMap<String, Vec<(usize, usize)>>,
+}
+
+impl SearchIndex {
+    /// Creates a new, empty index, using stemming rules for `lang`
+    pub fn new(lang: &str) -> SearchIndex {
+        SearchIndex {
+            suffixes: suffixes(&lang.to_lowercase()),
+            docs: vec![],
+            terms: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the (plain) text of a chapter to the index
+    pub fn add(&mut self, url: &str, title: &str, text: &str) {
+        let doc = self.docs.len();
+        self.docs.push((url.to_owned(), title.to_owned()));
+        for word in words(title).chain(words(text)) {
+            let entries = self
+                .terms
+                .entry(stem(&word, self.suffixes).to_owned())
+                .or_default();
+            match entries.last_mut() {
+                Some((d, n)) if *d == doc => *n += 1,
+                _ => entries.push((doc, 1)),
+            }
+        }
+    }
+
+    /// Returns the chapters containing a word, with the number of occurrences
+    pub fn lookup(&self, word: &str) -> &[(usize, usize)] {
+        let word = word.to_lowercase();
+        self.terms
+            .get(stem(&word, self.suffixes))
+            .map(|v| v.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Returns true if nothing was indexed
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    /// Returns the index as a JavaScript file, setting a `crowbookSearchIndex` variable
+    pub fn to_js(&self) -> String {
+        #[derive(Serialize)]
+        struct Index<'a> {
+            suffixes: &'a [&'a str],
+            min_stem: usize,
+            min_length: usize,
+            docs: &'a [(String, String)],
+            terms: BTreeMap<&'a str, Vec<usize>>,
+        }
+
+        let index = Index {
+            suffixes: self.suffixes,
+            min_stem: MIN_STEM,
+            min_length: MIN_LENGTH,
+            docs: &self.docs,
+            // Flat list of chapter number, occurrences, to keep the index compact
+            terms: self
+                .terms
+                .iter()
+                .map(|(term, entries)| {
+                    (
+                        term.as_str(),
+                        entries.iter().flat_map(|&(d, n)| [d, n]).collect(),
+                    )
+                })
+                .collect(),
+        };
+        format!(
+            "var crowbookSearchIndex = {};\n",
+            serde_json::to_string(&index).unwrap()
+        )
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright (C) 2016-2023 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Destinations renderers can write named files into
+
+use crate::error::{Error, Result, Source};
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+#[cfg(feature = "archive")]
+use std::io::{Seek, Write};
+
+use rust_i18n::t;
+
+/// A destination for the files generated by a renderer.
+///
+/// Paths are relative, use `/` as a separator, and can't go outside of the
+/// sink (e.g. `images/image_0.png`, but not `../foo` or `/foo`).
+pub trait OutputSink {
+    /// Writes a file
+    fn write_file(&mut self, path: &str, content: &[u8]) -> Result<()>;
+}
+
+impl<S: OutputSink + ?Sized> OutputSink for &mut S {
+    fn write_file(&mut self, path: &str, content: &[u8]) -> Result<()> {
+        (**self).write_file(path, content)
+    }
+}
+
+/// Returns an error if a path could point outside of a sink
+fn check_path(path: &str) -> Result<()> {
+    let valid = !path.is_empty()
+        && Path::new(path)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)));
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::render(
+            Source::empty(),
+            t!("sink.outside", file = path),
+        ))
+    }
+}
+
+/// Writes files in a directory, creating subdirectories as needed.
+///
+/// Files that already have the same content are left untouched, so their
+/// modification time doesn't change.
+#[derive(Debug, Clone)]
+pub struct DirSink {
+    dir: PathBuf,
+}
+
+impl DirSink {
+    /// Creates a sink writing in `dir`
+    pub fn new<P: Into<PathBuf>>(dir: P) -> DirSink {
+        DirSink { dir: dir.into() }
+    }
+
+    /// Returns the directory files are written in
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl OutputSink for DirSink {
+    fn write_file(&mut self, path: &str, content: &[u8]) -> Result<()> {
+        check_path(path)?;
+        let dest_file = self.dir.join(path);
+        if let Ok(current) = fs::read(&dest_file) {
+            if current == content {
+                return Ok(());
+            }
+        }
+        let dest_dir = dest_file.parent().unwrap();
+        fs::create_dir_all(dest_dir).map_err(|e| {
+            Error::render(
+                Source::empty(),
+                t!("sink.create_dir", path = dest_dir.display(), error = e),
+            )
+        })?;
+        fs::write(&dest_file, content).map_err(|e| {
+            Error::render(
+                Source::empty(),
+                t!("sink.write", file = dest_file.display(), error = e),
+            )
+        })
+    }
+}
+
+/// Keeps files in memory
+///
+/// # Example
+///
+/// ```
+/// use crowbook::{Book, MemorySink};
+///
+/// let mut book = Book::new();
+/// book.read_markdown_config("# Hello\n\nWorld".as_bytes()).unwrap();
+/// let mut files = MemorySink::new();
+/// book.render_format_to_sink("html.dir", &mut files).unwrap();
+/// assert!(files.get("index.html").is_some());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MemorySink {
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+impl MemorySink {
+    /// Creates a new, empty sink
+    pub fn new() -> MemorySink {
+        MemorySink::default()
+    }
+
+    /// Returns the content of a file, if it was written
+    pub fn get(&self, path: &str) -> Option<&[u8]> {
+        self.files.get(path).map(|c| c.as_slice())
+    }
+
+    /// Returns all the files that were written, by path
+    pub fn files(&self) -> &BTreeMap<String, Vec<u8>> {
+        &self.files
+    }
+
+    /// Returns all the files that were written, by path
+    pub fn into_files(self) -> BTreeMap<String, Vec<u8>> {
+        self.files
+    }
+}
+
+impl OutputSink for MemorySink {
+    fn write_file(&mut self, path: &str, content: &[u8]) -> Result<()> {
+        check_path(path)?;
+        self.files.insert(path.to_owned(), content.to_vec());
+        Ok(())
+    }
+}
+
+/// Writes files in a zip archive
+#[cfg(feature = "archive")]
+pub struct ZipSink<W: Write + Seek> {
+    zip: zip::ZipWriter<W>,
+}
+
+#[cfg(feature = "archive")]
+impl<W: Write + Seek> ZipSink<W> {
+    /// Creates a sink writing a zip archive to `writer`
+    pub fn new(writer: W) -> ZipSink<W> {
+        ZipSink {
+            zip: zip::ZipWriter::new(writer),
+        }
+    }
+
+    /// Finishes writing the archive, and returns the underlying writer
+    pub fn finish(self) -> Result<W> {
+        self.zip
+            .finish()
+            .map_err(|e| Error::render(Source::empty(), t!("sink.zip", error = e)))
+    }
+}
+
+#[cfg(feature = "archive")]
+impl<W: Write + Seek> OutputSink for ZipSink<W> {
+    fn write_file(&mut self, path: &str, content: &[u8]) -> Result<()> {
+        check_path(path)?;
+        let options = zip::write::SimpleFileOptions::default();
+        self.zip
+            .start_file(path, options)
+            .map_err(|e| Error::render(Source::empty(), t!("sink.zip", error = e)))?;
+        self.zip
+            .write_all(content)
+            .map_err(|e| Error::render(Source::empty(), t!("sink.zip", error = e)))
+    }
+}
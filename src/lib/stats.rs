@@ -16,8 +16,10 @@
 // along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::book::Book;
+use crate::progress::Emitter;
 use crate::style;
 use crate::text_view::view_as_text;
+use crate::token::Token;
 
 #[cfg(feature = "nightly")]
 use hyphenation;
@@ -47,9 +49,9 @@ struct ChapterStats {
 
 impl ChapterStats {
     #[cfg(feature = "nightly")]
-    pub fn fill_advanced(&mut self, lang: &str, text: &str) {
+    pub fn fill_advanced(&mut self, lang: &str, text: &str, emitter: &Emitter) {
         let words: Vec<_> = text.split_whitespace().collect();
-        let lang = Stats::language_from_str(lang);
+        let lang = Stats::language_from_str(lang, emitter);
         let corp = hyphenation::Standard::from_embedded(lang).unwrap();
         // Count the number of syllables for each word.
         let syl = words
@@ -75,7 +77,7 @@ impl ChapterStats {
     }
 
     #[cfg(not(feature = "nightly"))]
-    pub fn fill_advanced(&mut self, _: &str, _: &str) {}
+    pub fn fill_advanced(&mut self, _: &str, _: &str, _: &Emitter) {}
 }
 
 pub struct Stats {
@@ -84,14 +86,21 @@ pub struct Stats {
 }
 
 impl Stats {
+    /// Computes the statistics of the book as rendered in HTML
     pub fn new(book: &Book, advanced: bool) -> Stats {
+        Stats::for_format(book, "html", advanced)
+    }
+
+    /// Computes the statistics of the book as rendered in `format`, skipping chapters
+    /// and content whose condition excludes them
+    pub fn for_format(book: &Book, format: &str, advanced: bool) -> Stats {
         let lang = book.options.get_str("lang").unwrap();
 
         let mut stats;
 
         if cfg!(not(feature = "nightly")) {
             if advanced {
-                warn!("{}", t!("stats.no_advanced"));
+                book.warn(t!("stats.no_advanced").into_owned());
             }
             stats = Stats {
                 chapters: vec![],
@@ -110,9 +119,10 @@ impl Stats {
             }
         }
 
-        for c in &book.chapters {
+        let emitter = book.emitter();
+        for c in book.chapters_for(format) {
             let name = c.filename.clone();
-            let text = view_as_text(&c.content);
+            let text = text_for(book, format, &c.content);
             let wc = text.split_whitespace().count();
             // Note: Don't count the bytes with `len()` count the actual (multibyte-)characters
             let cc = text.chars().count();
@@ -124,7 +134,7 @@ impl Stats {
                 advanced: None,
             };
             if advanced {
-                chapter_stats.fill_advanced(lang, &text);
+                chapter_stats.fill_advanced(lang, &text, &emitter);
             }
             stats.chapters.push(chapter_stats);
         }
@@ -133,7 +143,7 @@ impl Stats {
 
     // Returns the Language (defined by Hyphenation crate) according to the str code
     #[cfg(feature = "nightly")]
-    fn language_from_str(lang: &str) -> Language {
+    fn language_from_str(lang: &str, emitter: &Emitter) -> Language {
         // FIXME: handle case where lang is e.g. fr_FR or en_GB
         match lang {
             "cz" => Language::Czech,
@@ -154,11 +164,10 @@ impl Stats {
             "sv" => Language::Swedish,
             "tk" => Language::Turkish,
             _ => {
-                warn!(
-                    //FIXME: display localized warning (or use Result?)
-                    "Unknown language: '{}' for text statistics, using 'en' default.",
-                    lang
-                );
+                //FIXME: display localized warning (or use Result?)
+                emitter.warn(format!(
+                    "Unknown language: '{lang}' for text statistics, using 'en' default."
+                ));
                 Language::EnglishGB
             }
         }
@@ -276,6 +285,25 @@ impl Stats {
     }
 }
 
+/// Returns the text of some tokens, skipping content whose condition isn't met for `format`
+fn text_for(book: &Book, format: &str, tokens: &[Token]) -> String {
+    let mut text = String::new();
+    for token in tokens {
+        match *token {
+            Token::Conditional(ref condition, ref inner) => {
+                if book.is_condition_met(condition, format) {
+                    text.push_str(&text_for(book, format, inner));
+                }
+            }
+            _ => match token.inner() {
+                Some(inner) => text.push_str(&text_for(book, format, inner)),
+                None => text.push_str(&view_as_text(std::slice::from_ref(token))),
+            },
+        }
+    }
+    text
+}
+
 impl fmt::Display for Stats {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let max_chapter_length = self
@@ -15,30 +15,169 @@
 // You should have received a copy of the GNU Lesser General Public License
 // along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
 
+use crate::bookoptions::BookOptions;
+use crate::cache::Cache;
+use crate::chapter::Chapter;
 use crate::error::Result;
+use crate::progress::Emitter;
+use crate::token::Token;
+
+#[cfg(feature = "syntect")]
+use std::fs;
+#[cfg(feature = "syntect")]
+use std::path::PathBuf;
+#[cfg(feature = "syntect")]
+use std::collections::HashMap;
+#[cfg(feature = "syntect")]
+use std::sync::Mutex;
 
 use crowbook_text_processing::escape;
 use rust_i18n::t;
+#[cfg(feature = "syntect")]
+use rayon::prelude::*;
+#[cfg(feature = "syntect")]
+use walkdir::WalkDir;
 
+/// Prefix of the CSS classes used when `rendering.highlight.classes` is set
 #[cfg(feature = "syntect")]
+const CLASS_STYLE: syntect::html::ClassStyle =
+    syntect::html::ClassStyle::SpacedPrefixed { prefix: "sy-" };
 
 /// Wrapper around syntect, so it can be more easily optionally compiled.
 #[cfg(feature = "syntect")]
 pub struct Syntax {
     syntax_set: syntect::parsing::SyntaxSet,
+    /// Syntaxes loaded from `rendering.highlight.syntaxes`, which are looked up first
+    custom_syntaxes: Option<syntect::parsing::SyntaxSet>,
     theme: syntect::highlighting::Theme,
+    /// Theme used for the dark variant of the stylesheet, if any
+    dark_theme: Option<syntect::highlighting::Theme>,
+    /// Whether HTML output uses CSS classes instead of inline styles
+    classes: bool,
+    /// Where highlighted code is stored, so it isn't highlighted again on next builds
+    cache: Cache,
+    /// Identifies the theme and syntaxes in cache keys
+    fingerprint: String,
+    /// Code highlighted in advance by `prepare`, by cache key
+    highlighted: Mutex<HashMap<String, String>>,
+    /// Where warnings about invalid attributes are sent
+    emitter: Emitter,
 }
 
 #[cfg(not(feature = "syntect"))]
-pub struct Syntax {}
+pub struct Syntax {
+    emitter: Emitter,
+}
 
 #[cfg(feature = "syntect")]
 impl Syntax {
     /// Creates a new Syntax wrapper
-    pub fn new(theme_name: &str) -> Syntax {
+    ///
+    /// `theme_name` is the name of a default syntect theme, which is only used
+    /// if `rendering.highlight.theme_file` is not set. Syntaxes in the directory
+    /// set by `rendering.highlight.syntaxes` are added to the default ones.
+    pub fn new(options: &BookOptions, theme_name: &str) -> Syntax {
         let mut theme_set = syntect::highlighting::ThemeSet::load_defaults();
-        let theme = match theme_set.themes.remove(theme_name) {
-            Some(theme) => theme,
+        let theme = Self::load_theme(
+            &mut theme_set,
+            theme_name,
+            options.get_path("rendering.highlight.theme_file").ok(),
+        );
+        let dark_theme = match (
+            options.get_str("rendering.highlight.dark_theme"),
+            options.get_path("rendering.highlight.dark_theme_file"),
+        ) {
+            (Err(_), Err(_)) => None,
+            (name, file) => Some(Self::load_theme(
+                &mut theme_set,
+                name.unwrap_or(theme_name),
+                file.ok(),
+            )),
+        };
+        let classes = options.get_bool("rendering.highlight.classes").unwrap();
+        let fingerprint = Self::fingerprint(options, theme_name, classes);
+        // Custom syntaxes are kept in their own set, as rebuilding the default
+        // one with them would be slow
+        let custom_syntaxes = options.get_path("rendering.highlight.syntaxes").ok().and_then(|dir| {
+            let mut builder = syntect::parsing::SyntaxSetBuilder::new();
+            match builder.add_from_folder(&dir, false) {
+                Ok(()) => Some(builder.build()),
+                Err(err) => {
+                    error!(
+                        "{}",
+                        t!("syntax.syntaxes", path = dir, error = err)
+                    );
+                    None
+                }
+            }
+        });
+        Syntax {
+            syntax_set: syntect::parsing::SyntaxSet::load_defaults_nonewlines(),
+            custom_syntaxes,
+            theme,
+            dark_theme,
+            classes,
+            cache: Cache::disabled(),
+            fingerprint,
+            highlighted: Mutex::new(HashMap::new()),
+            emitter: Emitter::default(),
+        }
+    }
+
+    /// Sets the cache where highlighted code is stored
+    pub fn set_cache(&mut self, cache: Cache) {
+        self.cache = cache;
+    }
+
+    /// Sets where warnings are sent, so they reach the book's progress listeners
+    pub(crate) fn set_emitter(&mut self, emitter: Emitter) {
+        self.emitter = emitter;
+    }
+
+    /// Returns a key depending on everything that affects highlighting,
+    /// including the content of the theme and syntax files
+    fn fingerprint(options: &BookOptions, theme_name: &str, classes: bool) -> String {
+        let mut files = vec![];
+        if let Ok(file) = options.get_path("rendering.highlight.theme_file") {
+            files.push(PathBuf::from(file));
+        }
+        if let Ok(dir) = options.get_path("rendering.highlight.syntaxes") {
+            let mut syntaxes: Vec<PathBuf> = WalkDir::new(dir)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file())
+                .map(|e| e.into_path())
+                .collect();
+            syntaxes.sort();
+            files.extend(syntaxes);
+        }
+        let files: Vec<_> = files
+            .into_iter()
+            .map(|file| {
+                let content = fs::read(&file).unwrap_or_default();
+                (file, content)
+            })
+            .collect();
+        Cache::key(&(theme_name, classes, files))
+    }
+
+    /// Loads a theme from a `.tmTheme` file or, if there is none, from the default ones
+    fn load_theme(
+        theme_set: &mut syntect::highlighting::ThemeSet,
+        theme_name: &str,
+        file: Option<String>,
+    ) -> syntect::highlighting::Theme {
+        if let Some(file) = file {
+            match syntect::highlighting::ThemeSet::get_theme(&file) {
+                Ok(theme) => return theme,
+                Err(err) => error!(
+                    "{}",
+                    t!("syntax.theme_file", file = file, error = err)
+                ),
+            }
+        }
+        match theme_set.themes.get(theme_name) {
+            Some(theme) => theme.clone(),
             None => {
                 error!(
                     "{}",
@@ -48,7 +187,7 @@ impl Syntax {
                 );
                 info!(
                     "{}",
-                    t!("syntax valid_themes",
+                    t!("syntax.valid_themes",
                         themes = theme_set
                             .themes
                             .keys()
@@ -57,53 +196,173 @@ impl Syntax {
                             .join(", ")
                     )
                 );
-                theme_set.themes.remove("InspiredGitHub").unwrap()
+                theme_set.themes["InspiredGitHub"].clone()
             }
-        };
-        Syntax {
-            syntax_set: syntect::parsing::SyntaxSet::load_defaults_nonewlines(),
-            theme,
         }
     }
 
-    /// Convert a string containing code to HTML
-    pub fn to_html(&self, code: &str, language: &str) -> Result<String> {
-        let language = strip_language(language);
+    /// Finds the syntax for a language, and the set it belongs to
+    fn find_syntax(
+        &self,
+        language: &str,
+    ) -> (&syntect::parsing::SyntaxReference, &syntect::parsing::SyntaxSet) {
+        if let Some(ref custom) = self.custom_syntaxes {
+            if let Some(syntax) = custom.find_syntax_by_token(language) {
+                return (syntax, custom);
+            }
+        }
         let syntax = self
             .syntax_set
             .find_syntax_by_token(language)
             .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
+        (syntax, &self.syntax_set)
+    }
+
+    /// Returns the stylesheet used by HTML output, if it uses CSS classes
+    ///
+    /// If a dark theme is set, its rules apply when the reader prefers a dark
+    /// color scheme.
+    pub fn css(&self) -> Result<Option<String>> {
+        if !self.classes {
+            return Ok(None);
+        }
+        let mut css = syntect::html::css_for_theme_with_class_style(&self.theme, CLASS_STYLE)?;
+        if let Some(ref dark_theme) = self.dark_theme {
+            let dark = syntect::html::css_for_theme_with_class_style(dark_theme, CLASS_STYLE)?;
+            css.push_str(&format!(
+                "\n@media (prefers-color-scheme: dark) {{\n{dark}}}\n"
+            ));
+        }
+        Ok(Some(css))
+    }
+
+    /// Highlights lines of code with CSS classes
+    ///
+    /// Spans are closed at the end of each line, and opened again on the next
+    /// one, so lines can be decorated separately.
+    fn classed_lines(
+        &self,
+        code: &str,
+        syntax: &syntect::parsing::SyntaxReference,
+        syntax_set: &syntect::parsing::SyntaxSet,
+    ) -> Result<Vec<String>> {
+        let mut state = syntect::parsing::ParseState::new(syntax);
+        let mut stack = syntect::parsing::ScopeStack::new();
+        let mut lines = vec![];
+        for line in code.split('\n') {
+            let mut res = String::new();
+            for scope in stack.as_slice() {
+                let classes: Vec<_> = scope
+                    .build_string()
+                    .split('.')
+                    .map(|atom| format!("sy-{atom}"))
+                    .collect();
+                res.push_str(&format!("<span class=\"{}\">", classes.join(" ")));
+            }
+            let ops = state
+                .parse_line(line, syntax_set)
+                .map_err(syntect::Error::from)?;
+            let (spans, _) =
+                syntect::html::line_tokens_to_classed_spans(line, &ops, CLASS_STYLE, &mut stack)?;
+            res.push_str(&spans);
+            res.push_str(&"</span>".repeat(stack.len()));
+            if line.is_empty() {
+                // Nothing to style, and keeps the final empty line empty
+                res.clear();
+            }
+            lines.push(res);
+        }
+        Ok(lines)
+    }
+
+    /// Convert a string containing code to HTML
+    ///
+    /// `info` is the info string of the code block, e.g. `rust,linenos`. If `xhtml11`
+    /// is set, the markup is valid XHTML 1.1 (for EPUB 2), see `CodeInfo::html_listing`.
+    pub fn to_html(&self, code: &str, info: &str, xhtml11: bool) -> Result<String> {
+        self.highlight(if xhtml11 { "xhtml11" } else { "html" }, code, info)
+    }
+
+    /// Highlights all the code blocks of some chapters in parallel, so
+    /// `to_html` (if `format` is `"html"`, or `"xhtml11"`) or `to_tex` (if it
+    /// is `"tex"`) only have to look up the result afterwards.
+    ///
+    /// Errors are ignored here: they will be returned when rendering the
+    /// code block.
+    pub fn prepare(&self, format: &str, chapters: &[Chapter]) {
+        let mut blocks = vec![];
+        for chapter in chapters {
+            code_blocks(&chapter.content, &mut blocks);
+        }
+        let highlighted: Vec<_> = blocks
+            .par_iter()
+            .filter_map(|&(info, code)| {
+                let key = Cache::key(&(&self.fingerprint, format, code, info));
+                self.highlight(format, code, info).ok().map(|s| (key, s))
+            })
+            .collect();
+        self.highlighted.lock().unwrap().extend(highlighted);
+    }
+
+    /// Returns highlighted code, from what was computed by `prepare`, from the
+    /// cache, or by highlighting it
+    fn highlight(&self, format: &str, code: &str, info: &str) -> Result<String> {
+        let key = Cache::key(&(&self.fingerprint, format, code, info));
+        if let Some(content) = self.highlighted.lock().unwrap().get(&key) {
+            return Ok(content.clone());
+        }
+        self.cache.get_or_insert_with("code", &key, || match format {
+            "tex" => self.highlight_tex(code, info),
+            "xhtml11" => self.highlight_html(code, info, true),
+            _ => self.highlight_html(code, info, false),
+        })
+    }
+
+    fn highlight_html(&self, code: &str, info: &str, xhtml11: bool) -> Result<String> {
+        let info = CodeInfo::parse_with(info, &self.emitter);
+        let (syntax, syntax_set) = self.find_syntax(&info.language);
+        if self.classes {
+            let lines = self.classed_lines(code, syntax, syntax_set)?;
+            return Ok(info.html_listing(
+                &format!(
+                    "<pre class = \"sy-code\">{}</pre>\n",
+                    info.html_lines(&lines, xhtml11)
+                ),
+                xhtml11,
+            ));
+        }
         let mut h = syntect::easy::HighlightLines::new(syntax, &self.theme);
-        let mut formatted_code = String::new();
+        let mut lines = vec![];
         for line in code.split('\n') {
-            let regions = h.highlight_line(line, &self.syntax_set)?;
+            let regions = h.highlight_line(line, syntax_set)?;
             let bg = syntect::html::IncludeBackground::No;
-            let res: String = syntect::html::styled_line_to_highlighted_html(&regions[..], bg)?;
-            formatted_code.push_str(&res);
-            formatted_code.push_str("\n");
+            lines.push(syntect::html::styled_line_to_highlighted_html(&regions[..], bg)?);
         }
-        Ok(format!("<pre>{formatted_code}</pre>"))
+        Ok(info.html_listing(
+            &format!("<pre>{}</pre>\n", info.html_lines(&lines, xhtml11)),
+            xhtml11,
+        ))
+    }
+
+    /// Convert a string containing code to LaTeX
+    ///
+    /// `info` is the info string of the code block, e.g. `rust,linenos`.
+    pub fn to_tex(&self, code: &str, info: &str) -> Result<String> {
+        self.highlight("tex", code, info)
     }
 
-    pub fn to_tex(&self, code: &str, language: &str) -> Result<String> {
-        let language = strip_language(language);
-        use crate::latex::insert_breaks;
+    fn highlight_tex(&self, code: &str, info: &str) -> Result<String> {
+        let info = CodeInfo::parse_with(info, &self.emitter);
         use syntect::highlighting::{Color, FontStyle};
-        let syntax = self
-            .syntax_set
-            .find_syntax_by_token(language)
-            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
+        let (syntax, syntax_set) = self.find_syntax(&info.language);
         let mut h = syntect::easy::HighlightLines::new(syntax, &self.theme);
 
-        let mut formatted_code = String::new();
+        let mut lines = vec![];
         for line in code.split('\n') {
-            let regions = h.highlight_line(line, &self.syntax_set)?;
+            let regions = h.highlight_line(line, syntax_set)?;
+            let mut formatted_line = String::new();
             for (style, text) in regions {
-                let mut content = escape::tex(text).into_owned();
-                content = insert_breaks(&content);
-                content = content
-                    .replace(' ', "\\hphantom{ }\\allowbreak{}");
-                content = format!("\\texttt{{{content}}}");
+                let mut content = format!("\\texttt{{{}}}", tex_code(text));
                 if style.foreground != Color::BLACK {
                     let r = style.foreground.r as f32 / 255.0;
                     let g = style.foreground.g as f32 / 255.0;
@@ -119,40 +378,339 @@ impl Syntax {
                 if style.font_style.contains(FontStyle::UNDERLINE) {
                     content = format!("\\underline{{{content}}}");
                 }
-                formatted_code.push_str(&content);
+                formatted_line.push_str(&content);
             }
-	    formatted_code.push_str("\\\\{}\n");
+            lines.push(formatted_line);
         }
-        Ok(format!("{{\\sloppy {formatted_code}}}"))
+        Ok(info.tex_listing(&format!("{{\\sloppy {}}}", info.tex_lines(&lines))))
     }
 }
 
-/// Strip language name of possible other infos, e.g. "rust,ignore" -> "rust"
-/// Currently only ',' is done
-fn strip_language(language: &str) -> &str {
-    let splits: Vec<_> = language.split(|c: char| matches!(c, ',')).collect();
-    splits[0].trim()
-}
-
 #[cfg(not(feature = "syntect"))]
 impl Syntax {
-    pub fn new(_: &str) -> Syntax {
+    pub fn new(_: &BookOptions, _: &str) -> Syntax {
         error!("{}", t!("syntax.no_support"));
-        Syntax {}
+        Syntax {
+            emitter: Emitter::default(),
+        }
     }
 
-    pub fn to_html(&self, code: &str, language: &str) -> Result<String> {
-        Ok(format!(
-            "<pre><code class = \"language-{lang}\">{code}</code></pre>",
-            code = escape::html(code),
-            lang = language
-        ))
+    pub fn set_cache(&mut self, _: Cache) {}
+
+    pub(crate) fn set_emitter(&mut self, emitter: Emitter) {
+        self.emitter = emitter;
     }
 
-    pub fn to_tex(&self, code: &str, _: &str) -> Result<String> {
-        Ok(format!(
-            "\\begin{{spverbatim}}{}\\end{{spverbatim}}\n",
-            code
-        ))
+    pub fn prepare(&self, _: &str, _: &[Chapter]) {}
+
+    pub fn css(&self) -> Result<Option<String>> {
+        Ok(None)
     }
+
+    pub fn to_html(&self, code: &str, info: &str, xhtml11: bool) -> Result<String> {
+        Ok(CodeInfo::parse_with(info, &self.emitter).html_fallback(code, xhtml11))
+    }
+
+    pub fn to_tex(&self, code: &str, info: &str) -> Result<String> {
+        Ok(CodeInfo::parse_with(info, &self.emitter).tex_fallback(code))
+    }
+}
+
+/// Attributes of a code block, given after the language in its info string,
+/// e.g. `rust,linenos,hl=3-5,title="src/main.rs",start=10`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeInfo {
+    /// Language of the code
+    pub language: String,
+    /// Whether lines must be numbered (`linenos`)
+    pub line_numbers: bool,
+    /// Number of the first line (`start`)
+    pub start: usize,
+    /// Ranges of highlighted lines, counted from the first line of the block (`hl`)
+    pub highlighted: Vec<(usize, usize)>,
+    /// Caption of the listing, e.g. a file name (`title`)
+    pub title: Option<String>,
+    /// Identifier of the listing, so it can be linked to (`id`, or `#id`)
+    pub id: Option<String>,
+}
+
+impl CodeInfo {
+    /// Parses the info string of a code block
+    ///
+    /// Attributes are separated by commas or spaces, and values containing one
+    /// of them can be quoted (e.g. `hl="1,4-6"`). Unknown attributes, such as
+    /// `ignore` in `rust,ignore`, are ignored.
+    pub fn parse(info: &str) -> CodeInfo {
+        CodeInfo::parse_with(info, &Emitter::default())
+    }
+
+    /// Parses the info string of a code block, sending warnings about invalid
+    /// attributes to `emitter`
+    pub(crate) fn parse_with(info: &str, emitter: &Emitter) -> CodeInfo {
+        let mut code_info = CodeInfo {
+            language: String::new(),
+            line_numbers: false,
+            start: 1,
+            highlighted: vec![],
+            title: None,
+            id: None,
+        };
+        for (i, attribute) in split_attributes(info).into_iter().enumerate() {
+            let (key, value) = match attribute.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim().trim_matches('"')),
+                None if attribute.starts_with('#') => ("id", &attribute[1..]),
+                None if i == 0 => {
+                    code_info.language = attribute;
+                    continue;
+                }
+                None => (attribute.as_str(), ""),
+            };
+            match key {
+                "linenos" | "linenums" => code_info.line_numbers = true,
+                "start" => match value.parse::<usize>() {
+                    Ok(n) => {
+                        code_info.start = n;
+                        code_info.line_numbers = true;
+                    }
+                    Err(_) => emitter.warn(t!("syntax.invalid_attribute", attribute = attribute).into_owned()),
+                },
+                "hl" | "highlight" => {
+                    for range in value.split(',').map(|r| r.trim()).filter(|r| !r.is_empty()) {
+                        let (first, last) = range.split_once('-').unwrap_or((range, range));
+                        match (first.trim().parse::<usize>(), last.trim().parse::<usize>()) {
+                            (Ok(first), Ok(last)) if first > 0 && first <= last => {
+                                code_info.highlighted.push((first, last))
+                            }
+                            _ => emitter.warn(
+                                t!("syntax.invalid_attribute", attribute = attribute).into_owned(),
+                            ),
+                        }
+                    }
+                }
+                "title" | "caption" => code_info.title = Some(value.to_owned()),
+                "id" => {
+                    if !value.is_empty()
+                        && value
+                            .chars()
+                            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | ':' | '.'))
+                    {
+                        code_info.id = Some(value.to_owned());
+                    } else {
+                        emitter.warn(t!("syntax.invalid_attribute", attribute = attribute).into_owned());
+                    }
+                }
+                _ => (),
+            }
+        }
+        code_info
+    }
+
+    /// Returns true if lines are decorated (numbered or highlighted)
+    pub fn has_line_decorations(&self) -> bool {
+        self.line_numbers || !self.highlighted.is_empty()
+    }
+
+    /// Returns true if a line (starting at 1 for the first line of the block) is highlighted
+    pub fn is_highlighted(&self, line: usize) -> bool {
+        self.highlighted
+            .iter()
+            .any(|(first, last)| line >= *first && line <= *last)
+    }
+
+    /// Formats already escaped lines of code in HTML, adding line numbers
+    /// and highlighting if needed
+    ///
+    /// If `xhtml11` is set, line numbers have no ARIA attribute.
+    pub fn html_lines<S: AsRef<str>>(&self, lines: &[S], xhtml11: bool) -> String {
+        let mut res = String::new();
+        for (i, line) in code_lines(lines).iter().enumerate() {
+            let line = line.as_ref();
+            if !self.has_line_decorations() {
+                res.push_str(line);
+            } else {
+                let class = if self.is_highlighted(i + 1) {
+                    "line hl"
+                } else {
+                    "line"
+                };
+                let number = if self.line_numbers {
+                    format!(
+                        "<span class = \"lineno\"{}>{}</span>",
+                        if xhtml11 { "" } else { " aria-hidden = \"true\"" },
+                        self.start + i
+                    )
+                } else {
+                    String::new()
+                };
+                res.push_str(&format!("<span class = \"{class}\">{number}{line}</span>"));
+            }
+            res.push('\n');
+        }
+        res
+    }
+
+    /// Adds a caption and an anchor to a listing rendered in HTML, if needed
+    ///
+    /// If `xhtml11` is set, which has no figures, the listing is a `div` and its
+    /// caption a `p` instead.
+    pub fn html_listing(&self, pre: &str, xhtml11: bool) -> String {
+        if self.title.is_none() && self.id.is_none() {
+            return pre.to_owned();
+        }
+        let id = match self.id {
+            Some(ref id) => format!(" id = \"{id}\""),
+            None => String::new(),
+        };
+        let title = self.title.as_deref().map(escape::html);
+        if xhtml11 {
+            let caption = match title {
+                Some(title) => format!("<p class = \"caption\">{title}</p>\n"),
+                None => String::new(),
+            };
+            format!("<div class = \"listing\"{id}>\n{caption}{pre}</div>\n")
+        } else {
+            let caption = match title {
+                Some(title) => format!("<figcaption>{title}</figcaption>\n"),
+                None => String::new(),
+            };
+            format!("<figure class = \"listing\"{id}>\n{caption}{pre}</figure>\n")
+        }
+    }
+
+    /// Renders code in HTML without syntax highlighting
+    pub fn html_fallback(&self, code: &str, xhtml11: bool) -> String {
+        let lines: Vec<_> = code.split('\n').map(escape::html).collect();
+        let class = if self.language.is_empty() {
+            String::new()
+        } else {
+            format!(" class = \"language-{}\"", escape::html(self.language.as_str()))
+        };
+        self.html_listing(
+            &format!(
+                "<pre><code{class}>{}</code></pre>\n",
+                self.html_lines(&lines, xhtml11)
+            ),
+            xhtml11,
+        )
+    }
+
+    /// Formats lines of code already converted to LaTeX, adding line numbers
+    /// and highlighting if needed
+    pub fn tex_lines<S: AsRef<str>>(&self, lines: &[S]) -> String {
+        let mut res = String::new();
+        for (i, line) in code_lines(lines).iter().enumerate() {
+            let mut line = line.as_ref().to_owned();
+            if self.line_numbers {
+                line = format!("\\mdcodelineno{{{}}}{line}", self.start + i);
+            }
+            if self.is_highlighted(i + 1) {
+                line = format!("\\mdcodehl{{{line}}}");
+            }
+            res.push_str(&line);
+            res.push_str("\\\\{}\n");
+        }
+        res
+    }
+
+    /// Adds a caption and a label to a listing rendered in LaTeX, if needed
+    pub fn tex_listing(&self, content: &str) -> String {
+        let mut res = String::new();
+        if let Some(ref id) = self.id {
+            res.push_str(&format!("\\label{{{id}}}"));
+        }
+        if let Some(ref title) = self.title {
+            res.push_str(&format!("\\mdcodetitle{{{}}}\n", escape::tex(title.as_str())));
+        }
+        res.push_str(content);
+        res
+    }
+
+    /// Renders code in LaTeX without syntax highlighting
+    pub fn tex_fallback(&self, code: &str) -> String {
+        if self.has_line_decorations() {
+            let lines: Vec<_> = code
+                .split('\n')
+                .map(|line| format!("\\texttt{{{}}}", tex_code(line)))
+                .collect();
+            self.tex_listing(&format!("{{\\sloppy {}}}", self.tex_lines(&lines)))
+        } else {
+            self.tex_listing(&format!("\\begin{{spverbatim}}\n{code}\n\\end{{spverbatim}}"))
+        }
+    }
+}
+
+/// Returns the identifiers of the listings in some tokens, so they can be
+/// registered as link targets
+pub fn listing_ids(tokens: &[Token]) -> Vec<String> {
+    let mut ids = vec![];
+    for token in tokens {
+        match *token {
+            Token::CodeBlock(ref info, _) => {
+                if let Some(id) = CodeInfo::parse(info).id {
+                    ids.push(id);
+                }
+            }
+            _ => {
+                if let Some(inner) = token.inner() {
+                    ids.extend(listing_ids(inner));
+                }
+            }
+        }
+    }
+    ids
+}
+
+/// Collects the info strings and contents of the code blocks in some tokens
+#[cfg(feature = "syntect")]
+fn code_blocks<'t>(tokens: &'t [Token], blocks: &mut Vec<(&'t str, &'t str)>) {
+    for token in tokens {
+        match *token {
+            Token::CodeBlock(ref info, ref code) => blocks.push((info.as_str(), code.as_str())),
+            _ => {
+                if let Some(inner) = token.inner() {
+                    code_blocks(inner, blocks);
+                }
+            }
+        }
+    }
+}
+
+/// Splits an info string in attributes, keeping quoted values together
+fn split_attributes(info: &str) -> Vec<String> {
+    let mut attributes = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in info.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            ',' | ' ' | '\t' if !quoted => {
+                if !current.is_empty() {
+                    attributes.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        attributes.push(current);
+    }
+    attributes
+}
+
+/// Returns the lines of a code block, without the empty line that follows
+/// its final newline
+fn code_lines<S: AsRef<str>>(lines: &[S]) -> &[S] {
+    match lines.split_last() {
+        Some((last, rest)) if last.as_ref().is_empty() => rest,
+        _ => lines,
+    }
+}
+
+/// Escapes a piece of code for LaTeX, allowing it to break across lines
+fn tex_code(code: &str) -> String {
+    crate::latex::insert_breaks(&escape::tex(code))
+        .replace(' ', "\\hphantom{ }\\allowbreak{}")
 }
@@ -43,6 +43,16 @@ pub mod html_single {
 
 pub mod html_dir {
     pub static TEMPLATE: &str = include_str!("../../templates/html_dir/template.html");
+    pub static SEARCH_JS: &str = include_str!("../../templates/html_dir/search.js");
+}
+
+pub mod themes {
+    /// Built-in themes, with their manifest
+    pub static BUILTIN: &[(&str, &str)] = &[
+        ("classic", include_str!("../../templates/themes/classic.yml")),
+        ("dark", include_str!("../../templates/themes/dark.yml")),
+        ("sepia", include_str!("../../templates/themes/sepia.yml")),
+    ];
 }
 
 pub mod latex {
@@ -0,0 +1,126 @@
+use crate::book::Book;
+use crate::epub::EpubRenderer;
+use crate::html_single::HtmlSingleRenderer;
+use crate::number::Number;
+
+fn book(content: &str) -> Book<'static> {
+    let mut book = Book::new();
+    book.read_config("title: Test\n".as_bytes()).unwrap();
+    book.add_chapter_from_source(Number::Default, content.as_bytes(), true)
+        .unwrap();
+    book
+}
+
+#[test]
+fn accessibility_html() {
+    let book = book("# Title\n\nText[^1]<!-- pagebreak 7 -->\n\n[^1]: Note\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n# Other\n");
+    let html = HtmlSingleRenderer::new(&book)
+        .unwrap()
+        .render_book()
+        .unwrap();
+    assert!(html.contains("<html lang=\"en\">"));
+    assert!(html.contains("<nav id = \"nav\" aria-label = \"Table of contents\">"));
+    assert!(html.contains("<a href=\"#link-1\" aria-label=\"1. Title\">"));
+    assert!(html.contains("role = \"doc-noteref\""));
+    assert!(html.contains("role = \"doc-footnote\""));
+    assert!(html.contains("<th scope = \"col\">a</th>"));
+    assert!(html.contains(
+        "<span role = \"doc-pagebreak\" id = \"page_7\" aria-label = \"7\"></span>"
+    ));
+}
+
+#[test]
+fn accessibility_metadata() {
+    let mut book = book("# Title\n\n![](image.png)\n");
+    let epub = EpubRenderer::new(&book).unwrap();
+    let metadata = epub.accessibility_metadata();
+    let values = |name| {
+        metadata
+            .iter()
+            .filter(|(n, _)| *n == name)
+            .map(|(_, v)| v.as_str())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(values("schema:accessMode"), ["textual", "visual"]);
+    assert_eq!(values("schema:accessModeSufficient"), ["textual"]);
+    assert_eq!(values("schema:accessibilityHazard"), ["none"]);
+    assert!(values("dcterms:conformsTo").is_empty());
+
+    book.options
+        .set("epub.accessibility.summary", "Fully accessible")
+        .unwrap();
+    book.options
+        .set_yaml(
+            yaml_rust::Yaml::String("epub.accessibility.hazards".into()),
+            yaml_rust::Yaml::Array(vec![yaml_rust::Yaml::String("flashing".into())]),
+        )
+        .unwrap();
+    let epub = EpubRenderer::new(&book).unwrap();
+    let metadata = epub.accessibility_metadata();
+    assert!(metadata.contains(&("schema:accessibilitySummary", String::from("Fully accessible"))));
+    assert!(metadata.contains(&("schema:accessibilityHazard", String::from("flashing"))));
+}
+
+#[cfg(feature = "archive")]
+#[test]
+fn accessibility_epub3() {
+    use crate::vfs::{BookSource, MemorySource};
+    use std::io::Cursor;
+    use std::path::Path;
+
+    let mut book = book("# Title\n\nText\n");
+    book.options.set("input.yaml_blocks", "true").unwrap();
+    book.add_chapter_from_source(
+        Number::Default,
+        "---\nchapter.lang: fr\n---\n\n# Titre\n\n<!-- pagebreak 12 -->\n\nTexte\n".as_bytes(),
+        true,
+    )
+    .unwrap();
+    book.options.set("epub.version", "3").unwrap();
+    book.options
+        .set("epub.accessibility.page_source", "urn:isbn:9780000000000")
+        .unwrap();
+    let mut out = vec![];
+    book.render_format_to("epub", &mut out).unwrap();
+    let files = MemorySource::from_zip(Cursor::new(out)).unwrap();
+    let file = |name: &str| {
+        let path = Path::new("OEBPS").join(name);
+        String::from_utf8(files.read(&path).unwrap()).unwrap()
+    };
+
+    let opf = file("content.opf");
+    assert!(opf.contains("<meta property=\"schema:accessMode\">textual</meta>"));
+    assert!(opf.contains("<meta property=\"schema:accessibilityHazard\">none</meta>"));
+    assert!(opf.contains("<meta property=\"schema:accessibilityFeature\">pageBreakMarkers</meta>"));
+    assert!(opf.contains("<meta property=\"a11y:pageBreakSource\">urn:isbn:9780000000000</meta>"));
+
+    let nav = file("nav.xhtml");
+    assert!(nav.contains("<nav epub:type = \"landmarks\">"));
+    assert!(nav.contains("epub:type=\"bodymatter\""));
+    assert!(nav.contains("<nav epub:type=\"page-list\""));
+    assert!(nav.contains("<a href=\"chapter_001.xhtml#page_12\">12</a>"));
+
+    assert!(!file("chapter_000.xhtml").contains("doc-pagebreak"));
+    let chapter = file("chapter_001.xhtml");
+    assert!(chapter.contains("id=\"page_12\" aria-label=\"12\""));
+    assert!(chapter.contains("<div lang = \"fr\" xml:lang = \"fr\">"));
+    assert!(chapter.contains("xml:lang=\"fr\" lang=\"fr\""));
+}
+
+#[cfg(feature = "archive")]
+#[test]
+fn accessibility_epub3_without_pages() {
+    use crate::vfs::{BookSource, MemorySource};
+    use std::io::Cursor;
+    use std::path::Path;
+
+    let mut book = book("# Title\n\nText\n");
+    book.options.set("epub.version", "3").unwrap();
+    let mut out = vec![];
+    book.render_format_to("epub", &mut out).unwrap();
+    let files = MemorySource::from_zip(Cursor::new(out)).unwrap();
+    let nav = String::from_utf8(files.read(Path::new("OEBPS/nav.xhtml")).unwrap()).unwrap();
+    assert!(!nav.contains("page-list"));
+    let opf = String::from_utf8(files.read(Path::new("OEBPS/content.opf")).unwrap()).unwrap();
+    assert!(!opf.contains("pageBreakMarkers"));
+}
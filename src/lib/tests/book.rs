@@ -1,5 +1,8 @@
 use super::test_eq;
 use crate::book::Book;
+use crate::number::Number;
+use crate::token::Token;
+use crate::vfs::MemorySource;
 
 #[test]
 fn load_config() {
@@ -21,3 +24,167 @@ epub.version: 3";
     );
     assert_eq!(book.options.get_i32("epub.version").unwrap(), 3);
 }
+
+#[test]
+fn chapter_options() {
+    let mut book = Book::new();
+    book.options.set("input.yaml_blocks", "true").unwrap();
+    book.add_chapter_from_source(
+        Number::Default,
+        "---\nchapter.title: Other\nchapter.toc: false\nchapter.lang: fr\n---\n\n# Title\n"
+            .as_bytes(),
+        true,
+    )
+    .unwrap();
+    let chapter = &book.chapters[0];
+    assert!(!chapter.toc);
+    assert_eq!(
+        chapter.content[0],
+        Token::Header(1, vec![Token::Str(String::from("Other"))])
+    );
+    let options = book.chapter_options(chapter).unwrap();
+    test_eq(options.get_str("lang").unwrap(), "fr");
+    test_eq(book.options.get_str("lang").unwrap(), "en");
+}
+
+#[test]
+fn chapters_loaded_in_order() {
+    let mut files = MemorySource::new();
+    let mut config = String::from("title: Test\ninput.yaml_blocks: true\n\n");
+    for i in 0..20 {
+        let file = format!("{i:02}.md");
+        config.push_str(&format!("+ {file}\n"));
+        let content = if i == 0 {
+            // Changes how the next chapters are parsed
+            String::from("---\ncrowbook.markdown.superscript: true\n---\n\n# Chapter 0\n")
+        } else {
+            format!("# Chapter {i}\n\nSome ^text^.\n")
+        };
+        files.add_file(&file, content.as_str());
+        if i == 10 {
+            config.push_str("-- sub.md\n");
+            files.add_file("sub.md", "# Section\n");
+        }
+    }
+    files.add_file("book.book", config.as_str());
+    let mut book = Book::new();
+    book.set_book_source(files);
+    book.load_file("book.book").unwrap();
+
+    assert_eq!(book.chapters.len(), 21);
+    for (i, chapter) in book.chapters.iter().take(11).enumerate() {
+        assert_eq!(chapter.filename, format!("{i:02}.md"));
+    }
+    let sub = &book.chapters[11];
+    assert_eq!(sub.filename, "sub.md");
+    assert_eq!(sub.number, book.chapters[10].number);
+    assert_eq!(sub.content[0], Token::Header(2, vec![Token::Str(String::from("Section"))]));
+    assert_eq!(book.chapters[20].filename, "19.md");
+    assert!(book.chapters[20]
+        .content
+        .iter()
+        .any(|t| matches!(t, Token::Paragraph(inner) if inner.iter().any(|t| matches!(t, Token::Superscript(_))))));
+}
+
+fn load_chapters(list: &str, files: &[(&str, &str)]) -> crate::error::Result<Vec<String>> {
+    let mut source = MemorySource::new();
+    source.add_file("book.book", format!("title: Test\n\n{list}").as_str());
+    for (file, content) in files {
+        source.add_file(file, *content);
+    }
+    let mut book = Book::new();
+    book.set_book_source(source);
+    book.load_file("book.book")?;
+    Ok(book.chapters.iter().map(|c| c.filename.clone()).collect())
+}
+
+#[test]
+fn chapter_patterns() {
+    let files = [
+        ("chapters/10.md", "# Ten\n"),
+        ("chapters/2.md", "# Two\n"),
+        ("chapters/notes.txt", "Not a chapter"),
+    ];
+    let expected = vec!["chapters/2.md", "chapters/10.md"];
+    assert_eq!(load_chapters("+ chapters/*.md\n", &files).unwrap(), expected);
+    assert_eq!(load_chapters("+ chapters/\n", &files).unwrap(), expected);
+    assert_eq!(load_chapters("+ chapters\n", &files).unwrap(), expected);
+}
+
+#[test]
+fn chapter_pattern_order() {
+    let files = [
+        ("chapters/a.md", "---\norder: 2\n---\n# A\n"),
+        ("chapters/b.md", "---\norder: 1.5\n---\n# B\n"),
+        ("chapters/c.md", "# C\n"),
+    ];
+    assert_eq!(
+        load_chapters("+ chapters/\n", &files).unwrap(),
+        vec!["chapters/b.md", "chapters/a.md", "chapters/c.md"]
+    );
+}
+
+#[test]
+fn chapter_pattern_no_match() {
+    let files = [("chapters/intro.md", "# Intro\n")];
+    assert!(load_chapters("+ chapters/*.txt\n", &files).is_err());
+    assert!(load_chapters("+ other/\n", &files).is_err());
+}
+
+#[test]
+fn chapter_literal_brackets() {
+    // `[1]` would be a character class in a pattern, but the file exists
+    let files = [("notes[1].md", "# Notes\n"), ("notes1.md", "# Other\n")];
+    assert_eq!(load_chapters("+ notes[1].md\n", &files).unwrap(), vec!["notes[1].md"]);
+}
+
+#[test]
+fn parallel_and_sequential_loading() {
+    let mut files = MemorySource::new();
+    let options = "title: Test\nlang: fr\ninput.yaml_blocks: true\n";
+    let mut config = format!("{options}\n");
+    for i in 0..12 {
+        let file = format!("{i:02}.md");
+        config.push_str(&format!("+ {file}\n"));
+        let content = if i == 5 {
+            // Cleaned with another cleaner than the rest of the book
+            String::from("---\nchapter.lang: en\n---\n\n# Chapter 5\n\nIs it \"English\" ?\n")
+        } else {
+            format!("# Chapter {i}\n\nEst-ce \"français\" ? Note[^1].\n\n[^1]: Note {i}.\n")
+        };
+        files.add_file(&file, content.as_str());
+    }
+    config.push_str("-- sub.md\n");
+    files.add_file("sub.md", "# Section\n\nSous-section : fin.\n");
+    files.add_file("parallel.book", config.as_str());
+    files.add_file("sequential.book", options);
+
+    let mut parallel = Book::new();
+    parallel.set_book_source(files.clone());
+    parallel.load_file("parallel.book").unwrap();
+
+    let mut sequential = Book::new();
+    sequential.set_book_source(files);
+    sequential.load_file("sequential.book").unwrap();
+    for i in 0..12 {
+        sequential
+            .add_chapter(Number::Default, &format!("{i:02}.md"), true)
+            .unwrap();
+    }
+    sequential.add_subchapter(1, "sub.md").unwrap();
+
+    for format in ["html", "tex", "json"] {
+        let mut expected = vec![];
+        sequential.render_format_to(format, &mut expected).unwrap();
+        let mut actual = vec![];
+        parallel.render_format_to(format, &mut actual).unwrap();
+        assert_eq!(String::from_utf8(actual).unwrap(), String::from_utf8(expected).unwrap());
+    }
+
+    // Each chapter was cleaned according to its own options
+    let mut html = vec![];
+    parallel.render_format_to("html", &mut html).unwrap();
+    let html = String::from_utf8(html).unwrap();
+    assert!(html.contains("Is it “English” ?"));
+    assert!(html.contains("Sous-section\u{a0}: fin."));
+}
@@ -0,0 +1,58 @@
+use super::temp_dir;
+use crate::book::Book;
+use crate::cache::Cache;
+use crate::parser::Parser;
+
+use std::fs;
+
+#[test]
+fn cache_get_or_insert() {
+    let dir = temp_dir("cache");
+    let cache = Cache::new(&*dir);
+    let key = Cache::key("some input");
+    assert_ne!(key, Cache::key("other input"));
+    assert!(cache.get("code", &key).is_none());
+
+    let value = cache.get_or_insert_with("code", &key, || Ok("computed".to_owned()));
+    assert_eq!(value.unwrap(), "computed");
+    let value = cache.get_or_insert_with("code", &key, || panic!("should be in the cache"));
+    assert_eq!(value.unwrap(), "computed");
+
+    let disabled = Cache::disabled();
+    disabled.put("code", &key, b"content");
+    assert!(disabled.get("code", &key).is_none());
+}
+
+#[test]
+fn cache_parsed_chapter() {
+    let dir = temp_dir("cache-ast");
+    let cache = Cache::new(&*dir);
+    let content = "---\nauthor: Foo\n---\n\n# Title\n\n```rust\nfn main() {}\n```\n";
+
+    let mut yaml = String::new();
+    let mut parser = Parser::new();
+    let tokens = parser.parse_cached(&cache, content, Some(&mut yaml)).unwrap();
+    assert_eq!(fs::read_dir(dir.join("ast")).unwrap().count(), 1);
+
+    let mut cached_yaml = String::new();
+    let mut cached_parser = Parser::new();
+    let cached = cached_parser
+        .parse_cached(&cache, content, Some(&mut cached_yaml))
+        .unwrap();
+    assert_eq!(cached, tokens);
+    assert_eq!(cached_yaml, yaml);
+    assert!(cached_parser.features().codeblock);
+
+    // Parser options are part of the key
+    let mut parser = Parser::new();
+    parser.html_as_text(false);
+    parser.parse_cached(&cache, content, None).unwrap();
+    assert_eq!(fs::read_dir(dir.join("ast")).unwrap().count(), 2);
+}
+
+#[test]
+fn cache_disabled_without_file() {
+    let mut book = Book::new();
+    book.read_config("title: Test\n".as_bytes()).unwrap();
+    assert!(!Cache::for_book(&book).is_enabled());
+}
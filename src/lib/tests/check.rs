@@ -0,0 +1,51 @@
+use crate::book::Book;
+use crate::check::{Check, Severity};
+use crate::number::Number;
+
+#[test]
+fn check_problems() {
+    let mut book = Book::new();
+    assert!(book.options.set("autor", "Author").is_err());
+    book.add_chapter_from_source(
+        Number::Default,
+        "# Title\n\n### Subsection\n\n![](http://foo.bar/image.png)\n".as_bytes(),
+        true,
+    )
+    .unwrap();
+    let check = Check::new(&book);
+    let kinds: Vec<_> = check.problems().iter().map(|p| p.kind).collect();
+    assert_eq!(kinds, vec!["unknown_option", "heading_jump", "missing_alt"]);
+    assert!(check.has_errors());
+    assert_eq!(check.count(Severity::Warning), 2);
+}
+
+#[test]
+fn check_json_lines() {
+    let mut book = Book::new();
+    book.read_config("title: Test\nautor: \"Some \\\"one\\\"\"\n".as_bytes())
+        .unwrap();
+    let check = Check::new(&book);
+    assert_eq!(check.problems()[0].source.line, Some(2));
+
+    let json: serde_json::Value = serde_json::from_str(&check.to_json()).unwrap();
+    assert_eq!(json["errors"], 1);
+    let problem = &json["problems"][0];
+    assert_eq!(problem["severity"], "error");
+    assert_eq!(problem["kind"], "unknown_option");
+    assert_eq!(problem["line"], 2);
+}
+
+#[test]
+fn check_multi_paragraph_quote() {
+    let mut book = Book::new();
+    book.add_chapter_from_source(
+        Number::Default,
+        "# Title\n\n“First paragraph,\n\n“second paragraph.”\n\n“Not closed.\n".as_bytes(),
+        true,
+    )
+    .unwrap();
+    let check = Check::new(&book);
+    let kinds: Vec<_> = check.problems().iter().map(|p| p.kind).collect();
+    assert_eq!(kinds, vec!["unbalanced_quotes"]);
+    assert!(check.problems()[0].message.contains("Not closed"));
+}
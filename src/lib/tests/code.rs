@@ -0,0 +1,129 @@
+use crate::book::Book;
+use crate::html_single::HtmlSingleRenderer;
+use crate::number::Number;
+use crate::syntax::CodeInfo;
+
+#[test]
+fn code_info_parse() {
+    let info = CodeInfo::parse("rust,linenos,hl=3-5,title=\"src/main.rs\",start=10");
+    assert_eq!(info.language, "rust");
+    assert!(info.line_numbers);
+    assert_eq!(info.start, 10);
+    assert_eq!(info.highlighted, vec![(3, 5)]);
+    assert_eq!(info.title.as_deref(), Some("src/main.rs"));
+    assert!(info.is_highlighted(4));
+    assert!(!info.is_highlighted(6));
+
+    let info = CodeInfo::parse("python hl=\"1,4-5\" #lst-main title=\"A, B\"");
+    assert_eq!(info.language, "python");
+    assert!(!info.line_numbers);
+    assert_eq!(info.highlighted, vec![(1, 1), (4, 5)]);
+    assert_eq!(info.id.as_deref(), Some("lst-main"));
+    assert_eq!(info.title.as_deref(), Some("A, B"));
+
+    let info = CodeInfo::parse("rust,ignore");
+    assert_eq!(info.language, "rust");
+    assert!(!info.has_line_decorations());
+}
+
+#[test]
+fn code_info_render() {
+    let info = CodeInfo::parse("c,linenos,hl=2,start=7,title=main.c");
+    let html = info.html_fallback("int a;\nint b;\n", false);
+    assert_eq!(
+        html,
+        "<figure class = \"listing\">\n<figcaption>main.c</figcaption>\n\
+         <pre><code class = \"language-c\">\
+         <span class = \"line\"><span class = \"lineno\" aria-hidden = \"true\">7</span>int a;</span>\n\
+         <span class = \"line hl\"><span class = \"lineno\" aria-hidden = \"true\">8</span>int b;</span>\n\
+         </code></pre>\n</figure>\n"
+    );
+    assert_eq!(
+        info.html_fallback("int a;\n", true),
+        "<div class = \"listing\">\n<p class = \"caption\">main.c</p>\n\
+         <pre><code class = \"language-c\">\
+         <span class = \"line\"><span class = \"lineno\">7</span>int a;</span>\n\
+         </code></pre>\n</div>\n"
+    );
+    let tex = info.tex_lines(&["a", "b", ""]);
+    assert_eq!(tex, "\\mdcodelineno{7}a\\\\{}\n\\mdcodehl{\\mdcodelineno{8}b}\\\\{}\n");
+
+    // Without attributes, output is unchanged
+    let info = CodeInfo::parse("");
+    assert_eq!(info.html_fallback("a < b\n", false), "<pre><code>a &lt; b\n</code></pre>\n");
+}
+
+#[test]
+fn code_listing_reference() {
+    let mut book = Book::new();
+    book.read_config("title: Test\nrendering.highlight: none\n".as_bytes())
+        .unwrap();
+    book.add_chapter_from_source(
+        Number::Default,
+        "# One\n\nSee [the listing](#lst-hello).\n\n```rust,id=lst-hello,title=hello.rs\nfn main() {}\n```\n"
+            .as_bytes(),
+        true,
+    )
+    .unwrap();
+    let html = HtmlSingleRenderer::new(&book)
+        .unwrap()
+        .render_book()
+        .unwrap();
+    assert!(html.contains("<a href = \"#lst-hello\">the listing</a>"));
+    assert!(html.contains("<figure class = \"listing\" id = \"lst-hello\">\n<figcaption>hello.rs</figcaption>"));
+}
+
+#[cfg(feature = "syntect")]
+#[test]
+fn code_custom_syntax_and_theme() {
+    use super::temp_dir;
+    use crate::syntax::Syntax;
+    use std::fs;
+
+    let dir = temp_dir("syntax");
+    fs::write(
+        dir.join("mydsl.sublime-syntax"),
+        "%YAML 1.2\n---\nname: MyDsl\nfile_extensions: [mydsl]\nscope: source.mydsl\n\
+         contexts:\n  main:\n    - match: '\\bfrobnicate\\b'\n      scope: keyword.control.mydsl\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("brand.tmTheme"),
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><dict>
+<key>name</key><string>Brand</string>
+<key>settings</key><array>
+<dict><key>settings</key><dict><key>foreground</key><string>#112233</string></dict></dict>
+<dict><key>scope</key><string>keyword</string><key>settings</key><dict><key>foreground</key><string>#AA0000</string></dict></dict>
+</array></dict></plist>"#,
+    )
+    .unwrap();
+
+    let mut book = Book::new();
+    book.options
+        .set("rendering.highlight.syntaxes", dir.to_str().unwrap())
+        .unwrap();
+    book.options
+        .set("rendering.highlight.theme_file", dir.join("brand.tmTheme").to_str().unwrap())
+        .unwrap();
+    let syntax = Syntax::new(&book.options, "InspiredGitHub");
+    assert!(syntax.css().unwrap().is_none());
+    let html = syntax.to_html("frobnicate x\n", "mydsl", false).unwrap();
+    assert!(html.contains("color:#aa0000;\">frobnicate</span>"));
+
+    book.options.set("rendering.highlight.classes", "true").unwrap();
+    book.options
+        .set("rendering.highlight.dark_theme", "base16-ocean.dark")
+        .unwrap();
+    let syntax = Syntax::new(&book.options, "InspiredGitHub");
+    let html = syntax.to_html("frobnicate x\n", "mydsl", false).unwrap();
+    assert_eq!(
+        html,
+        "<pre class = \"sy-code\"><span class=\"sy-source sy-mydsl\">\
+         <span class=\"sy-keyword sy-control sy-mydsl\">frobnicate</span> x</span>\n</pre>\n"
+    );
+    let css = syntax.css().unwrap().unwrap();
+    assert!(css.contains("Brand"));
+    assert!(css.contains(".sy-keyword {\n color: #aa0000;"));
+    assert!(css.contains("@media (prefers-color-scheme: dark)"));
+}
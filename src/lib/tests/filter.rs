@@ -0,0 +1,102 @@
+use crate::book::Book;
+use crate::chapter::Chapter;
+use crate::filter::{join_command, split_command};
+use crate::number::Number;
+use crate::token::{Condition, Data, Token};
+
+use yaml_rust::Yaml;
+
+fn book_with_filters(filters: &str) -> Book<'static> {
+    let mut book = Book::new();
+    book.read_config(format!("title: Test\nfilters: {filters}\n").as_bytes())
+        .unwrap();
+    book
+}
+
+#[test]
+fn filter_serialize_roundtrip() {
+    let mut chapter = Chapter::new(
+        Number::Specified(2),
+        "foo.md",
+        vec![
+            Token::Header(1, vec![Token::Str("Title".into())]),
+            Token::Paragraph(vec![
+                Token::Annotation(Data::Repetition("word".into()), vec![Token::Str("word".into())]),
+                Token::SoftBreak,
+                Token::Link("url".into(), String::new(), vec![]),
+            ]),
+        ],
+    );
+    chapter.condition = Condition::parse("only=pdf");
+    let json = serde_json::to_string(&chapter).unwrap();
+    assert!(json.contains(r#"{"t":"Header","c":[1,[{"t":"Str","c":"Title"}]]}"#));
+    assert!(json.contains(r#"{"t":"SoftBreak"}"#));
+    let result: Chapter = serde_json::from_str(&json).unwrap();
+    assert_eq!(result.number, chapter.number);
+    assert_eq!(result.content, chapter.content);
+    assert_eq!(result.condition, chapter.condition);
+}
+
+#[cfg(unix)]
+#[test]
+fn filter_transforms_chapter() {
+    let mut book = book_with_filters("[\"sed s/Hello/Goodbye/g\", cat]");
+    book.add_chapter_from_source(Number::Default, "# Hello\n\nHello world\n".as_bytes(), true)
+        .unwrap();
+    assert_eq!(
+        book.chapters[0].content,
+        vec![
+            Token::Header(1, vec![Token::Str("Goodbye".into())]),
+            Token::Paragraph(vec![Token::Str("Goodbye world".into())]),
+        ]
+    );
+}
+
+#[test]
+fn filter_split_command() {
+    assert_eq!(split_command("sed  s/a/b/g"), vec!["sed", "s/a/b/g"]);
+    assert_eq!(
+        split_command(r#"python3 "my filters/a.py" 'it''s' a\ b"#),
+        vec!["python3", "my filters/a.py", "its", "a b"]
+    );
+    assert_eq!(split_command(r"C:\filters\a.exe ''"), vec![r"C:\filters\a.exe", ""]);
+    let args: Vec<String> = vec!["a b".into(), "it's".into(), r"c:\d".into(), String::new()];
+    assert_eq!(split_command(&join_command(&args)), args);
+}
+
+#[cfg(unix)]
+#[test]
+fn filter_argv() {
+    let mut book = book_with_filters("[[sed, \"s/Hello/Good bye/g\"]]");
+    book.add_chapter_from_source(Number::Default, "# Hello\n\nHello world\n".as_bytes(), true)
+        .unwrap();
+    assert_eq!(
+        book.chapters[0].content,
+        vec![
+            Token::Header(1, vec![Token::Str("Good bye".into())]),
+            Token::Paragraph(vec![Token::Str("Good bye world".into())]),
+        ]
+    );
+
+    let res = book.options.set_yaml(
+        Yaml::String("filters".into()),
+        Yaml::Array(vec![Yaml::Array(vec![Yaml::Array(vec![])])]),
+    );
+    assert!(res.is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn filter_errors() {
+    let mut book = book_with_filters("[\"false\"]");
+    let res = book.add_chapter_from_source(Number::Default, "# Hello\n".as_bytes(), true);
+    assert!(matches!(res, Err(ref e) if e.is_filter()));
+
+    let mut book = book_with_filters("[\"echo not json\"]");
+    let res = book.add_chapter_from_source(Number::Default, "# Hello\n".as_bytes(), true);
+    assert!(matches!(res, Err(ref e) if e.is_filter()));
+
+    let mut book = book_with_filters("[\"crowbook-no-such-filter\"]");
+    let res = book.add_chapter_from_source(Number::Default, "# Hello\n".as_bytes(), true);
+    assert!(matches!(res, Err(ref e) if e.is_filter()));
+}
@@ -0,0 +1,129 @@
+use super::temp_dir;
+use crate::book::Book;
+use crate::html_dir::HtmlDirRenderer;
+use crate::number::Number;
+use crate::sink::{MemorySink, OutputSink};
+use crate::vfs::MemorySource;
+
+use std::fs;
+use std::path::Path;
+
+fn render(dest: &Path, chapters: &[&str], force: bool) -> crate::error::Result<()> {
+    let mut book = Book::new();
+    book.read_config("title: Test\n".as_bytes()).unwrap();
+    book.options
+        .set("html.dir.force", if force { "true" } else { "false" })
+        .unwrap();
+    for chapter in chapters {
+        book.add_chapter_from_source(Number::Default, chapter.as_bytes(), true)
+            .unwrap();
+    }
+    HtmlDirRenderer::new(&book)?.render_book(dest)
+}
+
+#[test]
+fn html_dir_incremental() {
+    let dir = temp_dir("html-dir");
+    let dest = dir.join("html");
+    render(&dest, &["# One\n", "# Two\n"], false).unwrap();
+    assert!(dest.join("chapter_001.html").exists());
+    let manifest = fs::read_to_string(dest.join(".crowbook-manifest")).unwrap();
+    assert!(manifest.lines().any(|l| l == "chapter_001.html"));
+
+    // Files that are not generated are kept, unchanged files are not rewritten
+    fs::write(dest.join("CNAME"), "example.com").unwrap();
+    let modified = fs::metadata(dest.join("stylesheet.css")).unwrap().modified().unwrap();
+    render(&dest, &["# One\n"], false).unwrap();
+    assert!(!dest.join("chapter_001.html").exists());
+    assert!(dest.join("chapter_000.html").exists());
+    assert_eq!(fs::read_to_string(dest.join("CNAME")).unwrap(), "example.com");
+    assert_eq!(
+        fs::metadata(dest.join("stylesheet.css")).unwrap().modified().unwrap(),
+        modified
+    );
+}
+
+#[test]
+fn html_dir_not_generated() {
+    let dir = temp_dir("html-dir-foreign");
+    fs::write(dir.join("file.txt"), "").unwrap();
+    assert!(render(&dir, &["# One\n"], false).is_err());
+    assert!(dir.join("file.txt").exists());
+
+    render(&dir, &["# One\n"], true).unwrap();
+    assert!(dir.join("file.txt").exists());
+    assert!(dir.join("index.html").exists());
+}
+
+#[test]
+fn html_dir_without_manifest() {
+    let dir = temp_dir("html-dir-old");
+    for file in ["index.html", "chapter_000.html", "chapter_001.html", "CNAME"] {
+        fs::write(dir.join(file), "").unwrap();
+    }
+    render(&dir, &["# One\n"], false).unwrap();
+    assert!(!dir.join("chapter_001.html").exists());
+    assert!(dir.join("CNAME").exists());
+    assert!(dir.join(".crowbook-manifest").exists());
+}
+
+#[test]
+fn html_dir_to_memory() {
+    let mut book = Book::new();
+    book.read_config("title: Test\n".as_bytes()).unwrap();
+    book.add_chapter_from_source(Number::Default, "# One\n".as_bytes(), true)
+        .unwrap();
+    let mut files = MemorySink::new();
+    book.render_format_to_sink("html.dir", &mut files).unwrap();
+    assert!(files.get("index.html").is_some());
+    assert!(files.get("chapter_000.html").is_some());
+    assert!(files.get(".crowbook-manifest").is_none());
+
+    // Single file formats use their automatic file name
+    let mut files = MemorySink::new();
+    book.render_format_to_sink("html", &mut files).unwrap();
+    assert_eq!(files.files().keys().collect::<Vec<_>>(), ["book.html"]);
+
+    assert!(files.write_file("../foo", b"").is_err());
+}
+
+#[test]
+fn tex_to_sink() {
+    let mut source = MemorySource::new();
+    source
+        .add_file("book.book", "title: Test\n\n+ chapter.md\n")
+        .add_file("chapter.md", "# One\n\n![An image](image.png)\n")
+        .add_file(
+            "image.png",
+            &include_bytes!("../../../img/crowbook-small.png")[..],
+        );
+    let mut book = Book::new();
+    book.set_book_source(source);
+    book.load_file("book.book").unwrap();
+
+    // The LaTeX file comes with the images it uses
+    let mut files = MemorySink::new();
+    book.render_format_to_sink("tex", &mut files).unwrap();
+    assert_eq!(files.files().len(), 2);
+    let tex = String::from_utf8(files.get("book.tex").unwrap().to_vec()).unwrap();
+    let (image, _) = files
+        .files()
+        .iter()
+        .find(|(name, _)| name.starts_with("images/"))
+        .unwrap();
+    assert!(tex.contains(image.as_str()));
+}
+
+#[cfg(feature = "archive")]
+#[test]
+fn html_dir_to_zip() {
+    let mut book = Book::new();
+    book.read_config("title: Test\n".as_bytes()).unwrap();
+    book.add_chapter_from_source(Number::Default, "# One\n".as_bytes(), true)
+        .unwrap();
+    let mut content = vec![];
+    book.render_format_to("html.dir", &mut content).unwrap();
+    let mut zip = zip::ZipArchive::new(std::io::Cursor::new(content)).unwrap();
+    assert!(zip.by_name("index.html").is_ok());
+    assert!(zip.by_name("stylesheet.css").is_ok());
+}
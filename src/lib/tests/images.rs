@@ -0,0 +1,67 @@
+use super::temp_dir;
+use crate::error::Source;
+use crate::images::{ImageProcessor, ImageSettings};
+
+use std::fs;
+
+fn settings() -> ImageSettings {
+    ImageSettings {
+        max_width: None,
+        max_height: None,
+        quality: 85,
+        unsupported: vec![],
+        srcset: vec![],
+    }
+}
+
+#[test]
+fn images_resize() {
+    let dir = temp_dir("resize");
+    let file = dir.join("image.png");
+    image::RgbImage::new(200, 100).save(&file).unwrap();
+
+    let processor = ImageProcessor::new(settings(), dir.join("cache"));
+    assert!(processor.process(&Source::empty(), &file).unwrap().is_none());
+
+    let mut s = settings();
+    s.max_width = Some(100);
+    let processor = ImageProcessor::new(s, dir.join("cache"));
+    let processed = processor.process(&Source::empty(), &file).unwrap().unwrap();
+    assert_eq!(processed.extension, "png");
+    assert_eq!(processed.width, Some(100));
+    assert!(processed.file.starts_with(dir.join("cache/images")));
+    assert_eq!(image::image_dimensions(&processed.file).unwrap(), (100, 50));
+
+    // Second time, the cached file is used
+    let cached = processor.process(&Source::empty(), &file).unwrap().unwrap();
+    assert_eq!(cached.file, processed.file);
+}
+
+#[test]
+fn images_srcset_and_conversion() {
+    let dir = temp_dir("srcset");
+    let file = dir.join("image.jpg");
+    image::RgbImage::new(300, 300).save(&file).unwrap();
+
+    let mut s = settings();
+    s.srcset = vec![100, 400];
+    let processor = ImageProcessor::new(s, dir.join("cache"));
+    let processed = processor.process(&Source::empty(), &file).unwrap().unwrap();
+    // Original is small enough, only a smaller version is generated
+    assert_eq!(processed.file, file);
+    assert_eq!(processed.variants.len(), 1);
+    assert_eq!(processed.variants[0].0, 100);
+
+    let svg = dir.join("image.svg");
+    fs::write(
+        &svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"20\" height=\"10\"><rect width=\"20\" height=\"10\"/></svg>",
+    )
+    .unwrap();
+    let mut s = settings();
+    s.unsupported = vec!["svg"];
+    let processor = ImageProcessor::new(s, dir.join("cache"));
+    let processed = processor.process(&Source::empty(), &svg).unwrap().unwrap();
+    assert_eq!(processed.extension, "png");
+    assert_eq!(image::image_dimensions(&processed.file).unwrap(), (40, 20));
+}
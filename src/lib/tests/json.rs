@@ -0,0 +1,54 @@
+use crate::book::Book;
+use crate::number::Number;
+use crate::token::Token;
+
+fn book() -> Book<'static> {
+    let mut book = Book::new();
+    book.read_config("title: Test\nauthor: Joan\nrendering.num_depth: 2\ninput.yaml_blocks: true\noutput: [html, json]\n".as_bytes())
+        .unwrap();
+    book.add_chapter_from_source(
+        Number::Specified(3),
+        "---\nchapter.toc: false\n---\n# Title\n\nSome \"quoted\" text with a [link](http://example.com).\n"
+            .as_bytes(),
+        true,
+    )
+    .unwrap();
+    book
+}
+
+#[test]
+fn json_roundtrip() {
+    let mut book = book();
+    let mut json = vec![];
+    book.render_format_to("json", &mut json).unwrap();
+    let json = String::from_utf8(json).unwrap();
+    assert!(json.contains("\"crowbook_version\""));
+    assert!(json.contains("“quoted”"));
+    assert!(!json.contains("output.json"));
+
+    let mut copy = Book::new();
+    copy.from_json(json.as_bytes()).unwrap();
+    assert_eq!(copy.options.get_str("author").unwrap(), "Joan");
+    assert_eq!(copy.options.get_i32("rendering.num_depth").unwrap(), 2);
+    assert!(!copy.options.get_bool("input.clean").unwrap());
+    assert_eq!(copy.options.get_str_vec("output").unwrap(), &["html"]);
+    assert!(copy.options.get_path("output.json").is_err());
+    assert_eq!(copy.chapters.len(), 1);
+    assert_eq!(copy.chapters[0].number, Number::Specified(3));
+    assert!(!copy.chapters[0].toc);
+    assert!(copy.features.url);
+    assert!(matches!(copy.chapters[0].content[0], Token::Header(1, _)));
+
+    let mut html = vec![];
+    book.render_format_to("html", &mut html).unwrap();
+    let mut copy_html = vec![];
+    copy.render_format_to("html", &mut copy_html).unwrap();
+    assert_eq!(String::from_utf8(html).unwrap(), String::from_utf8(copy_html).unwrap());
+}
+
+#[test]
+fn json_invalid() {
+    let mut book = Book::new();
+    let res = book.from_json("{\"chapters\": 42}".as_bytes());
+    assert!(matches!(res, Err(ref e) if e.is_config_parser()));
+}
@@ -0,0 +1,36 @@
+use super::test_eq;
+use crate::migrate::Migration;
+
+#[test]
+fn migrate_config() {
+    let config = "\
+author: Me
+numbering: 2 # comment
+description: |
+  autoclean: not an option
+tex.short: true
+profiles:
+  ebook:
+    use_initials: true
+
++ chapter.md
+";
+    let migration = Migration::new(config);
+    test_eq(
+        &migration.content,
+        "\
+author: Me
+rendering.num_depth: 2 # comment
+description: |
+  autoclean: not an option
+# 'tex.short' has been removed from Crowbook and is ignored
+# tex.short: true
+profiles:
+  ebook:
+    rendering.initials: true
+
++ chapter.md
+",
+    );
+    assert_eq!(migration.changes.len(), 3);
+}
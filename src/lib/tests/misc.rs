@@ -0,0 +1,29 @@
+use crate::misc::natural_cmp;
+
+#[test]
+fn natural_order() {
+    let mut files = vec!["chapter_10.md", "chapter_2.md", "appendix.md", "chapter_1.md", "chapter_02b.md"];
+    files.sort_by(|a, b| natural_cmp(a, b));
+    assert_eq!(
+        files,
+        vec!["appendix.md", "chapter_1.md", "chapter_2.md", "chapter_02b.md", "chapter_10.md"]
+    );
+}
+
+#[test]
+fn stable_hash() {
+    use crate::misc::StableHasher;
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = StableHasher::default();
+    hasher.write(b"a");
+    assert_eq!(hasher.finish(), 0xaf63dc4c8601ec8c);
+
+    let hash = |s: &str| {
+        let mut hasher = StableHasher::default();
+        s.hash(&mut hasher);
+        hasher.finish()
+    };
+    assert_eq!(hash("http://example.com/image.png"), hash("http://example.com/image.png"));
+    assert_ne!(hash("ab"), hash("ba"));
+}
@@ -1,3 +1,7 @@
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
 /// Equivalent to assert_eq! but with prettier output
 pub fn test_eq(actual: &str, expected: &str) {
     if actual != expected {
@@ -8,5 +12,50 @@ pub fn test_eq(actual: &str, expected: &str) {
     }
 }
 
+/// A temporary directory, removed (with its content) when it goes out of scope
+pub struct TempDir(PathBuf);
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Creates an empty temporary directory, unique to this test run
+pub fn temp_dir(name: &str) -> TempDir {
+    let dir = std::env::temp_dir().join(format!("crowbook-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    TempDir(dir)
+}
+
+mod accessibility;
 mod book;
+mod cache;
+mod check;
+mod code;
+mod filter;
+mod html_dir;
+#[cfg(feature = "images")]
+mod images;
+mod json;
+mod migrate;
+mod misc;
+mod notes;
 mod parser;
+mod profile;
+mod progress;
+#[cfg(feature = "remote")]
+mod remote;
+mod search;
+mod theme;
+mod vfs;
+mod visitor;
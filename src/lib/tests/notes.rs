@@ -0,0 +1,104 @@
+use crate::book::Book;
+use crate::number::Number;
+
+fn book(notes: &str, numbering: &str) -> Book<'static> {
+    let mut book = Book::new();
+    book.read_config("title: Test\nlang: en\n".as_bytes()).unwrap();
+    book.options.set("rendering.notes", notes).unwrap();
+    book.options.set("rendering.notes.numbering", numbering).unwrap();
+    book.add_chapter_from_source(
+        Number::Default,
+        "# One\n\nA[^a] b[^b] again[^a].\n\n[^a]: Note a\n\n[^b]: Note b\n".as_bytes(),
+        true,
+    )
+    .unwrap();
+    book.add_chapter_from_source(
+        Number::Default,
+        "# Two\n\nC[^c].\n\n[^c]: Note c\n".as_bytes(),
+        true,
+    )
+    .unwrap();
+    book
+}
+
+fn render(book: &mut Book, format: &str) -> String {
+    let mut out = vec![];
+    book.render_format_to(format, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn html_notes_numbering() {
+    let html = render(&mut book("chapter_end", "chapter"), "html");
+    assert_eq!(html.matches("<sup>[1]</sup>").count(), 3);
+    assert_eq!(html.matches("<sup>[2]</sup>").count(), 1);
+    assert!(!html.contains("<sup>[3]</sup>"));
+
+    let html = render(&mut book("chapter_end", "book"), "html");
+    assert_eq!(html.matches("<sup>[1]</sup>").count(), 2);
+    assert!(html.contains("<sup>[3]</sup>"));
+}
+
+#[cfg(feature = "archive")]
+#[test]
+fn epub_notes_numbering() {
+    use crate::vfs::{BookSource, MemorySource};
+    use std::io::Cursor;
+    use std::path::Path;
+
+    let mut book = book("footnotes", "book");
+    book.options.set("epub.version", "3").unwrap();
+    let mut out = vec![];
+    book.render_format_to("epub", &mut out).unwrap();
+    let files = MemorySource::from_zip(Cursor::new(out)).unwrap();
+    let chapter = |name: &str| {
+        let path = Path::new("OEBPS").join(name);
+        String::from_utf8(files.read(&path).unwrap()).unwrap()
+    };
+    let first = chapter("chapter_000.xhtml");
+    assert_eq!(first.matches(">[1]</sup>").count(), 2);
+    assert!(first.contains(">[2]</sup>"));
+    assert!(first.contains("epub:type = \"noteref\""));
+    assert!(first.contains("epub:type = \"footnote\""));
+    assert!(chapter("chapter_001.xhtml").contains(">[3]</sup>"));
+}
+
+#[test]
+fn latex_endnotes() {
+    let tex = render(&mut book("chapter_end", "chapter"), "tex");
+    assert!(tex.contains("\\usepackage{endnotes}"));
+    // A note referenced twice keeps its number, and its text is written once
+    assert_eq!(tex.matches("\\protect\\endnotemark[1]").count(), 3);
+    assert_eq!(tex.matches("\\endnotetext[1]{").count(), 2);
+    assert!(tex.contains("\\endnotetext[1]{Note a"));
+    assert!(tex.contains("\\endnotetext[2]{Note b"));
+    assert_eq!(tex.matches("\\theendnotes").count(), 2);
+    // The text of a note comes after the paragraph where it is referenced
+    let paragraph = tex.find("again\\protect\\endnotemark[1]").unwrap();
+    assert!(tex.find("\\endnotetext[2]").unwrap() > paragraph);
+}
+
+#[test]
+fn latex_sidenotes() {
+    let tex = render(&mut book("side", "book"), "tex");
+    assert!(tex.contains("\\usepackage{marginnote}"));
+    assert!(tex.contains("\\protect\\mdsidenotemark[2]"));
+    assert!(tex.contains("\\mdsidenotetext[3]{Note c"));
+    assert!(!tex.contains("\\footnotemark"));
+}
+
+#[test]
+fn latex_footnotes_in_tables() {
+    let mut book = Book::new();
+    book.read_config("title: Test\n".as_bytes()).unwrap();
+    book.add_chapter_from_source(
+        Number::Default,
+        "# One\n\n| a | b |\n|---|---|\n| x[^a] | y |\n\n[^a]: In a table\n".as_bytes(),
+        true,
+    )
+    .unwrap();
+    let tex = render(&mut book, "tex");
+    let end = tex.find("\\end{mdtable}").unwrap();
+    assert!(tex.find("\\protect\\footnotemark[1]").unwrap() < end);
+    assert!(tex.find("\\footnotetext[1]{In a table").unwrap() > end);
+}
@@ -177,3 +177,55 @@ fn table_simple() {
     let result = format!("{:?}", parse_from_str(doc));
     test_eq(&result, expected);
 }
+
+#[test]
+fn conditional_block() {
+    let doc = "
+Before
+::: {only=pdf,tex}
+Print *only*
+:::
+After
+";
+    let expected = r#"[Paragraph([Str("Before")]), Conditional(Condition { only: true, keys: ["pdf", "tex"] }, [Paragraph([Str("Print "), Emphasis([Str("only")])])]), Paragraph([Str("After")])]"#;
+    let result = format!("{:?}", parse_from_str(doc));
+    test_eq(&result, expected);
+}
+
+#[test]
+fn conditional_inline() {
+    let doc = "See [the *map*]{unless=epub}.";
+    let expected = r#"[Paragraph([Str("See "), Conditional(Condition { only: false, keys: ["epub"] }, [Str("the "), Emphasis([Str("map")])]), Str(".")])]"#;
+    let result = format!("{:?}", parse_from_str(doc));
+    test_eq(&result, expected);
+}
+
+#[test]
+fn conditional_inline_needs_opening_bracket() {
+    let doc = "Use array[0]{only=pdf} here.";
+    let expected = r#"[Paragraph([Str("Use array[0]{only=pdf} here.")])]"#;
+    let result = format!("{:?}", parse_from_str(doc));
+    test_eq(&result, expected);
+}
+
+#[test]
+fn conditional_link() {
+    let doc = "A [link](http://example.com){unless=epub} and ![image](foo.png){only=html}.";
+    let expected = r#"[Paragraph([Str("A "), Conditional(Condition { only: false, keys: ["epub"] }, [Link("http://example.com", "", [Str("link")])]), Str(" and "), Conditional(Condition { only: true, keys: ["html"] }, [Image("foo.png", "", [Str("image")])]), Str(".")])]"#;
+    let result = format!("{:?}", parse_from_str(doc));
+    test_eq(&result, expected);
+}
+
+#[test]
+fn variables() {
+    let doc = "Version {{ version }} of {{title}}, not {{a variable}}";
+    let expected = r#"[Paragraph([Str("Version {{ version }} of {{title}}, not {{a variable}}")])]"#;
+    let result = format!("{:?}", parse_from_str(doc));
+    test_eq(&result, expected);
+
+    let mut book = Book::new();
+    book.options.set("input.variables", "true").unwrap();
+    let expected = r#"[Paragraph([Str("Version "), Variable("version"), Str(" of "), Variable("title"), Str(", not {{a variable}}")])]"#;
+    let result = format!("{:?}", Parser::from(&book).parse(doc, None).unwrap());
+    test_eq(&result, expected);
+}
@@ -0,0 +1,52 @@
+use crate::book::Book;
+use crate::vfs::MemorySource;
+
+fn source() -> MemorySource {
+    let mut files = MemorySource::new();
+    files
+        .add_file(
+            "book.book",
+            "title: Test\noutput.html: book.html\nprofiles:\n  print:\n    crowbook.markdown.superscript: true\n    tex.paper.size: a5paper\n\n+ chapter.md\n",
+        )
+        .add_file("chapter.md", "# Chapter\n\nx^2^\n");
+    files
+}
+
+fn render_html(book: &mut Book) -> String {
+    let mut out = vec![];
+    book.render_format_to("html", &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn profile_before_loading() {
+    let mut book = Book::new();
+    book.set_book_source(source());
+    book.with_profile("print").load_file("book.book").unwrap();
+    assert_eq!(book.options.get_str("tex.paper.size").unwrap(), "a5paper");
+    assert_eq!(book.options.get_str("rendering.edition").unwrap(), "print");
+    assert_eq!(book.options.get_relative_path("output.html").unwrap(), "book-print.html");
+    assert!(render_html(&mut book).contains("x<sup>2</sup>"));
+}
+
+#[test]
+fn profile_changes_parsing() {
+    let mut book = Book::new();
+    book.set_book_source(source());
+    book.load_file("book.book").unwrap();
+    assert!(render_html(&mut book).contains("x^2^"));
+
+    // Applying the profile afterwards doesn't parse chapters again
+    book.set_profile("print").unwrap();
+    assert!(render_html(&mut book).contains("x^2^"));
+
+    book.reload_with_profile("print").unwrap();
+    assert!(render_html(&mut book).contains("x<sup>2</sup>"));
+}
+
+#[test]
+fn unknown_profile() {
+    let mut book = Book::new();
+    book.set_book_source(source());
+    assert!(book.with_profile("web").load_file("book.book").is_err());
+}
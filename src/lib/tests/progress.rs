@@ -0,0 +1,120 @@
+use crate::book::Book;
+use crate::epub::EpubRenderer;
+use crate::html_single::HtmlSingleRenderer;
+use crate::latex::LatexRenderer;
+use crate::progress::{CancellationToken, Event};
+use crate::vfs::MemorySource;
+
+use std::sync::{Arc, Mutex};
+
+fn book(events: &Arc<Mutex<Vec<Event>>>) -> Book<'static> {
+    let mut files = MemorySource::new();
+    files
+        .add_file("book.book", "title: Test\n\n+ 01.md\n+ 02.md\n")
+        .add_file("01.md", "# First\n\n![](image.png)\n")
+        .add_file("02.md", "# Second\n")
+        .add_file("image.png", &include_bytes!("../../../img/crowbook-small.png")[..]);
+    let mut book = Book::new();
+    book.set_book_source(files);
+    let events = events.clone();
+    book.add_progress_listener(move |event: &Event| events.lock().unwrap().push(event.clone()));
+    book
+}
+
+#[test]
+fn progress_events() {
+    let events = Arc::new(Mutex::new(vec![]));
+    let mut book = book(&events);
+    book.load_file("book.book").unwrap();
+    book.render_format_to("html", &mut vec![]).unwrap();
+
+    let events = events.lock().unwrap();
+    let loaded: Vec<_> = events
+        .iter()
+        .filter_map(|e| match e {
+            Event::ChapterLoaded { file, current, total } => Some((file.as_str(), *current, *total)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(loaded, vec![("01.md", 1, 2), ("02.md", 2, 2)]);
+    assert!(events.contains(&Event::FormatStarted {
+        format: String::from("html")
+    }));
+    assert!(events.contains(&Event::FormatFinished {
+        format: String::from("html"),
+        path: None
+    }));
+    assert!(events.iter().any(|e| matches!(e, Event::Warning(_))));
+    assert_eq!(events.last(), Some(&Event::Finished));
+}
+
+#[test]
+fn cancelled_rendering() {
+    let events = Arc::new(Mutex::new(vec![]));
+    let mut book = book(&events);
+    book.load_file("book.book").unwrap();
+    let token = CancellationToken::new();
+    book.set_cancellation_token(token.clone());
+    token.cancel();
+
+    let res = book.render_format_to("html", &mut vec![]);
+    assert!(matches!(res, Err(ref e) if e.is_cancelled()));
+    let events = events.lock().unwrap();
+    assert!(events
+        .iter()
+        .any(|e| matches!(e, Event::FormatFailed { format, .. } if format == "html")));
+    assert!(!events.contains(&Event::Finished));
+}
+
+#[test]
+fn cancelled_loading_and_renderers() {
+    let events = Arc::new(Mutex::new(vec![]));
+    let mut loading = book(&events);
+    let token = CancellationToken::new();
+    loading.set_cancellation_token(token.clone());
+    token.cancel();
+    let res = loading.load_file("book.book");
+    assert!(matches!(res, Err(ref e) if e.is_cancelled()));
+
+    // Renderers stop in their chapter loop, even when they are not called by `Book`
+    let mut book = book(&events);
+    book.load_file("book.book").unwrap();
+    let token = CancellationToken::new();
+    book.set_cancellation_token(token.clone());
+    token.cancel();
+    let res = HtmlSingleRenderer::new(&book).unwrap().render_book();
+    assert!(matches!(res, Err(ref e) if e.is_cancelled()));
+    let res = LatexRenderer::new(&book).render_book();
+    assert!(matches!(res, Err(ref e) if e.is_cancelled()));
+    let res = EpubRenderer::new(&book).unwrap().render_book(&mut vec![]);
+    assert!(matches!(res, Err(ref e) if e.is_cancelled()));
+}
+
+#[test]
+fn content_warnings() {
+    let mut files = MemorySource::new();
+    files
+        .add_file("book.book", "title: Test\n\n+ 01.md\n")
+        .add_file(
+            "01.md",
+            "# First\n\n![](https://example.com/remote.png)\n\n```rust,start=x\nfn main() {}\n```\n",
+        );
+    let mut book = Book::new();
+    book.set_book_source(files);
+    let events = Arc::new(Mutex::new(vec![]));
+    let cloned = events.clone();
+    book.add_progress_listener(move |event: &Event| cloned.lock().unwrap().push(event.clone()));
+    book.load_file("book.book").unwrap();
+    book.render_format_to("html", &mut vec![]).unwrap();
+
+    let events = events.lock().unwrap();
+    let warnings: Vec<_> = events
+        .iter()
+        .filter_map(|e| match e {
+            Event::Warning(msg) => Some(msg.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert!(warnings.iter().any(|w| w.contains("remote.png")), "{warnings:?}");
+    assert!(warnings.iter().any(|w| w.contains("start=x")), "{warnings:?}");
+}
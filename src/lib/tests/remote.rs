@@ -0,0 +1,91 @@
+use super::temp_dir;
+use crate::book::Book;
+use crate::error::Source;
+use crate::html_dir::HtmlDirRenderer;
+use crate::number::Number;
+use crate::remote::Fetcher;
+
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Starts a HTTP server answering every request with `content`, and
+/// returns its address and the number of requests it got
+fn serve(content: &'static [u8]) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = format!("http://{}", listener.local_addr().unwrap());
+    let count = Arc::new(AtomicUsize::new(0));
+    let requests = count.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut buffer = [0; 4096];
+            let _ = stream.read(&mut buffer);
+            requests.fetch_add(1, Ordering::SeqCst);
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                content.len()
+            );
+            let _ = stream.write_all(content);
+        }
+    });
+    (address, count)
+}
+
+#[test]
+fn remote_fetch_and_cache() {
+    let dir = temp_dir("remote");
+    let (address, count) = serve(b"not really a png");
+    let url = format!("{address}/image");
+    let timeout = Duration::from_secs(5);
+
+    // Offline mode, with nothing in the cache
+    let fetcher = Fetcher::new(&*dir, timeout, true);
+    assert!(fetcher.fetch(&Source::empty(), &url).is_err());
+    assert_eq!(count.load(Ordering::SeqCst), 0);
+
+    let fetcher = Fetcher::new(&*dir, timeout, false);
+    let file = fetcher.fetch(&Source::empty(), &url).unwrap();
+    assert!(file.starts_with(dir.join("remote")));
+    assert_eq!(file.extension().unwrap(), "png");
+    assert_eq!(fs::read(&file).unwrap(), b"not really a png");
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+
+    // The cached file is reused, also in offline mode
+    assert_eq!(fetcher.fetch(&Source::empty(), &url).unwrap(), file);
+    let fetcher = Fetcher::new(&*dir, timeout, true);
+    assert_eq!(fetcher.fetch(&Source::empty(), &url).unwrap(), file);
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn remote_images_in_book() {
+    let dir = temp_dir("remote-book");
+    let (address, count) = serve(b"image");
+    let mut book = Book::new();
+    book.read_config("title: Test\nresources.fetch_remote: true\n".as_bytes())
+        .unwrap();
+    book.options
+        .set("crowbook.cache_dir", dir.join("cache").to_str().unwrap())
+        .unwrap();
+    // A fresh directory, so the renderer doesn't refuse to write into it
+    book.options
+        .set("output.html.dir", dir.join("html").to_str().unwrap())
+        .unwrap();
+    let content = format!("# Title\n\n![Image]({address}/image.png)\n");
+    book.add_chapter_from_source(Number::Default, content.as_bytes(), true)
+        .unwrap();
+    HtmlDirRenderer::new(&book)
+        .unwrap()
+        .render_book(&dir.join("html"))
+        .unwrap();
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    assert_eq!(fs::read(dir.join("html/images/image_0.png")).unwrap(), b"image");
+    let chapter = fs::read_to_string(dir.join("html/chapter_000.html")).unwrap();
+    assert!(chapter.contains("src = \"images/image_0.png\""));
+}
@@ -0,0 +1,15 @@
+use crate::search::SearchIndex;
+
+#[test]
+fn search_index() {
+    let mut index = SearchIndex::new("en");
+    index.add("chapter_000.html", "Beginning", "The runners are running.");
+    index.add("chapter_001.html", "End", "Nothing to run here. Or running?");
+    assert_eq!(index.lookup("Running"), &[(0, 2), (1, 1)]);
+    assert_eq!(index.lookup("runners"), index.lookup("running"));
+    assert_eq!(index.lookup("beginning"), &[(0, 1)]);
+    assert!(index.lookup("missing").is_empty());
+    assert!(index
+        .to_js()
+        .starts_with("var crowbookSearchIndex = {\"suffixes\":[\"ational\""));
+}
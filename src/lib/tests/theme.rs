@@ -0,0 +1,56 @@
+use crate::bookoptions::BookOptions;
+use crate::error::Source;
+use crate::theme::Theme;
+
+#[test]
+fn builtin_theme() {
+    let mut options = BookOptions::new();
+    options.set("tex.font.size", "12").unwrap();
+    options.set("theme", "classic").unwrap();
+    // Options already set by the book are kept
+    assert_eq!(options.get_i32("tex.font.size").unwrap(), 12);
+    assert!(options.get_bool("rendering.initials").unwrap());
+    // ... and options set after the theme override it
+    options.set("rendering.initials", "false").unwrap();
+    assert!(!options.get_bool("rendering.initials").unwrap());
+    assert!(options.set("theme", "no_such_theme").is_err());
+}
+
+#[test]
+fn theme_manifest() {
+    let theme = Theme::from_manifest(
+        "name: test\nfiles: [fonts/]\nhtml.css.add: \"p {}\"\n",
+        None,
+        Source::empty(),
+    )
+    .unwrap();
+    assert_eq!(theme.name, "test");
+    assert_eq!(theme.files, vec!["fonts/"]);
+    assert_eq!(theme.options.get_str("html.css.add").unwrap(), "p {}");
+    assert!(Theme::from_manifest("html.nope: 1\n", None, Source::empty()).is_err());
+}
+
+#[test]
+fn theme_from_book_source() {
+    use crate::book::Book;
+    use crate::sink::MemorySink;
+    use crate::vfs::MemorySource;
+
+    let mut files = MemorySource::new();
+    files
+        .add_file("book.book", "theme: my_theme\ntitle: Test\n\n+ intro.md\n")
+        .add_file("intro.md", "# Introduction\n")
+        .add_file(
+            "my_theme/theme.yml",
+            "name: my_theme\nfiles: [fonts/]\nhtml.css.add: \"p {}\"\n",
+        )
+        .add_file("my_theme/fonts/font.woff", "font");
+    let mut book = Book::new();
+    book.set_book_source(files);
+    book.load_file("book.book").unwrap();
+    assert_eq!(book.options.get_str("html.css.add").unwrap(), "p {}");
+
+    let mut output = MemorySink::new();
+    book.render_format_to_sink("html.dir", &mut output).unwrap();
+    assert_eq!(output.get("fonts/font.woff").unwrap(), b"font");
+}
@@ -0,0 +1,76 @@
+use crate::book::Book;
+use crate::vfs::{BookSource, MemorySource};
+
+use std::path::Path;
+
+fn source() -> MemorySource {
+    let mut files = MemorySource::new();
+    files
+        .add_file("book/book.book", "import: common.book\ntitle: Test\n\n+ chapters/\n")
+        .add_file("book/common.book", "author: Someone\n")
+        .add_file("book/chapters/01.md", "# First\n\n![An image](img/image.png)\n")
+        .add_file("book/chapters/02.md", "# Second\n\nSome text.\n")
+        .add_file(
+            "book/img/image.png",
+            &include_bytes!("../../../img/crowbook-small.png")[..],
+        );
+    files
+}
+
+#[test]
+fn memory_source_paths() {
+    let files = source();
+    assert!(files.is_file(Path::new("./book/chapters/../common.book")));
+    assert!(files.is_dir(Path::new("book/chapters")));
+    assert!(!files.is_file(Path::new("book/chapters")));
+    assert_eq!(files.files_in(Path::new("book/chapters")).unwrap().len(), 2);
+    assert_eq!(files.files_up_to(Path::new("./book"), 1).unwrap().len(), 2);
+    assert_eq!(files.files_up_to(Path::new("book"), 2).unwrap().len(), 5);
+    assert!(files.read(Path::new("book/missing.md")).is_err());
+}
+
+#[test]
+fn book_from_memory() {
+    let mut book = Book::new();
+    book.set_book_source(source());
+    book.load_file("book/book.book").unwrap();
+    assert_eq!(book.options.get_str("author").unwrap(), "Someone");
+    assert_eq!(book.chapters.len(), 2);
+    assert_eq!(book.chapters[0].filename, "chapters/01.md");
+
+    let mut html = vec![];
+    book.render_format_to("html", &mut html).unwrap();
+    let html = String::from_utf8(html).unwrap();
+    assert!(html.contains("Some text."));
+    assert!(html.contains("data:image/png;base64,"));
+}
+
+#[test]
+fn missing_chapter() {
+    let mut files = MemorySource::new();
+    files.add_file("book.book", "title: Test\n\n+ missing.md\n");
+    let mut book = Book::new();
+    book.set_book_source(files);
+    let res = book.load_file("book.book");
+    assert!(matches!(res, Err(ref e) if e.is_file_not_found()));
+}
+
+#[cfg(feature = "archive")]
+#[test]
+fn book_from_zip() {
+    use std::io::{Cursor, Write};
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+    let options = zip::write::SimpleFileOptions::default();
+    for path in source().paths() {
+        zip.start_file(path.to_string_lossy(), options).unwrap();
+        zip.write_all(&source().read(path).unwrap()).unwrap();
+    }
+    let archive = zip.finish().unwrap();
+
+    let files = MemorySource::from_zip(Cursor::new(archive.into_inner())).unwrap();
+    let mut book = Book::new();
+    book.set_book_source(files);
+    book.load_file("book/book.book").unwrap();
+    assert_eq!(book.chapters.len(), 2);
+}
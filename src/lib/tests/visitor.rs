@@ -0,0 +1,89 @@
+use crate::book::Book;
+use crate::number::Number;
+use crate::token::{Data, Token};
+use crate::visitor::{Fold, Visitor, VisitorMut};
+
+fn book(content: &str) -> Book<'static> {
+    let mut book = Book::new();
+    book.read_config("title: Test\n".as_bytes()).unwrap();
+    book.add_chapter_from_source(Number::Default, content.as_bytes(), false)
+        .unwrap();
+    book
+}
+
+struct Text(String);
+
+impl Visitor for Text {
+    fn visit_token(&mut self, token: &Token) {
+        // Skip headers
+        if !token.is_header() {
+            self.walk_token(token);
+        }
+    }
+
+    fn visit_str(&mut self, text: &str) {
+        self.0.push_str(text);
+    }
+}
+
+struct Upper;
+
+impl VisitorMut for Upper {
+    fn visit_str_mut(&mut self, text: &mut String) {
+        *text = text.to_uppercase();
+    }
+}
+
+/// Annotates every occurrence of a word
+struct Annotate(&'static str);
+
+impl Fold for Annotate {
+    fn fold_str(&mut self, text: String) -> Vec<Token> {
+        let mut result = vec![];
+        for (i, part) in text.split(self.0).enumerate() {
+            if i > 0 {
+                result.push(Token::Annotation(
+                    Data::Repetition(self.0.into()),
+                    vec![Token::Str(self.0.into())],
+                ));
+            }
+            if !part.is_empty() {
+                result.push(Token::Str(part.into()));
+            }
+        }
+        result
+    }
+}
+
+#[test]
+fn visitor_visit_chapters() {
+    let mut book = book("# Title\n\nSome *nested **text***\n");
+    let mut text = Text(String::new());
+    book.visit_chapters(&mut text);
+    assert_eq!(text.0, "Some nested text");
+
+    book.visit_chapters_mut(&mut Upper);
+    let mut text = Text(String::new());
+    book.visit_chapters(&mut text);
+    assert_eq!(text.0, "SOME NESTED TEXT");
+}
+
+#[test]
+fn visitor_fold_chapters() {
+    let mut book = book("Foo bar foo\n\n> foo\n");
+    book.fold_chapters(&mut Annotate("foo"));
+    let annotation = Token::Annotation(
+        Data::Repetition("foo".into()),
+        vec![Token::Str("foo".into())],
+    );
+    assert_eq!(
+        book.chapters[0].content,
+        vec![
+            Token::Paragraph(vec![
+                Token::Str("Foo bar ".into()),
+                annotation.clone(),
+            ]),
+            Token::BlockQuote(vec![Token::Paragraph(vec![annotation])]),
+        ]
+    );
+}
@@ -30,12 +30,15 @@ where
 
         Token::SoftBreak => f(" "),
 
+        Token::Variable(ref name) => f(&format!("{{{{{name}}}}}")),
+
         Token::Rule | Token::HardBreak => f("\n"),
 
         Token::Image(..)
         | Token::StandaloneImage(..)
         | Token::FootnoteDefinition(..)
         | Token::FootnoteReference(..)
+        | Token::PageBreak(..)
         | Token::Table(..)
         | Token::TableHead(..)
         | Token::TableRow(..)
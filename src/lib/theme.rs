@@ -0,0 +1,230 @@
+// Copyright (C) 2025 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::book::Book;
+use crate::bookoptions::BookOptions;
+use crate::error::{Error, Result, Source};
+use crate::resource_handler;
+use crate::templates::themes;
+use crate::vfs::BookSource;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rust_i18n::t;
+use yaml_rust::{Yaml, YamlLoader};
+
+/// Name of the manifest file of a theme directory
+pub const MANIFEST: &str = "theme.yml";
+
+/// A theme, i.e. a set of options (templates, CSS, ...) that are applied
+/// together with the `theme` option.
+///
+/// A theme is either one of the built-in themes, or a directory containing a
+/// `theme.yml` manifest. Besides `name`, `description` and `files`, keys of
+/// the manifest are book options, and paths are relative to the theme
+/// directory:
+///
+/// ```yaml
+/// name: my_theme
+/// description: Some colors
+/// files: [fonts/]
+/// html.css.colors: colors.css
+/// epub.css: epub.css
+/// ```
+///
+/// Files listed in `files` (which can be directories) are copied alongside
+/// the output for multiple files HTML and EPUB, so the CSS can refer to them.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    /// Name of the theme
+    pub name: String,
+    /// Description of the theme
+    pub description: String,
+    /// Directory of the theme, `None` for built-in themes
+    pub dir: Option<PathBuf>,
+    /// Options set by the theme
+    pub options: BookOptions,
+    /// Files to copy with the output, relative to the theme directory
+    pub files: Vec<String>,
+}
+
+impl Theme {
+    /// Returns the names of the built-in themes
+    pub fn builtin() -> Vec<&'static str> {
+        themes::BUILTIN.iter().map(|(name, _)| *name).collect()
+    }
+
+    /// Loads a theme, either by its name if it is a built-in one, or from a
+    /// directory (or manifest file) relative to `root`, read from `files`
+    pub fn load(name: &str, root: &Path, files: &dyn BookSource) -> Result<Theme> {
+        let path = root.join(name);
+        let is_dir = files.is_dir(&path);
+        if !is_dir && !files.is_file(&path) {
+            if let Some((_, manifest)) = themes::BUILTIN.iter().find(|(n, _)| *n == name) {
+                return Theme::from_manifest(manifest, None, Source::empty());
+            }
+            return Err(Error::book_option(
+                Source::empty(),
+                t!(
+                    "theme.not_found",
+                    theme = name,
+                    builtin = Theme::builtin().join(", ")
+                ),
+            ));
+        }
+        let (dir, file) = if is_dir {
+            (path.clone(), path.join(MANIFEST))
+        } else {
+            (path.parent().unwrap_or(root).to_owned(), path.clone())
+        };
+        let manifest = files.read_to_string(&file).map_err(|_| {
+            Error::file_not_found(
+                Source::empty(),
+                t!("theme.manifest"),
+                format!("{}", file.display()),
+            )
+        })?;
+        Theme::from_manifest(
+            &manifest,
+            Some(&dir),
+            Source::new(format!("{}", file.display())),
+        )
+    }
+
+    /// Creates a theme from the content of its manifest
+    ///
+    /// If `dir` is `None`, paths are relative to the book rather than to the theme.
+    pub fn from_manifest(manifest: &str, dir: Option<&Path>, source: Source) -> Result<Theme> {
+        let docs = YamlLoader::load_from_str(manifest).map_err(|err| {
+            Error::book_option(&source, t!("theme.yaml", error = err))
+        })?;
+        let mut theme = Theme {
+            name: String::new(),
+            description: String::new(),
+            dir: dir.map(|d| d.to_owned()),
+            options: BookOptions::new(),
+            files: vec![],
+        };
+        theme.options.source = source.clone();
+        if let Some(dir) = dir {
+            theme.options.root = dir.to_owned();
+        }
+        let hash = match docs.into_iter().next() {
+            Some(Yaml::Hash(hash)) => hash,
+            Some(Yaml::Null) | None => return Ok(theme),
+            Some(_) => return Err(Error::book_option(&source, t!("theme.not_hash"))),
+        };
+        for (key, value) in hash {
+            match (key.as_str(), value) {
+                (Some("name"), Yaml::String(name)) => theme.name = name,
+                (Some("description"), Yaml::String(description)) => {
+                    theme.description = description
+                }
+                (Some("files"), Yaml::Array(files)) => {
+                    for file in files {
+                        match file {
+                            Yaml::String(file) => theme.files.push(file),
+                            _ => {
+                                return Err(Error::book_option(
+                                    &source,
+                                    t!("theme.files", value = format!("{file:?}")),
+                                ))
+                            }
+                        }
+                    }
+                }
+                (Some("files"), value) => {
+                    return Err(Error::book_option(
+                        &source,
+                        t!("theme.files", value = format!("{value:?}")),
+                    ))
+                }
+                (_, value) => {
+                    theme.options.set_yaml(key, value)?;
+                }
+            }
+        }
+        Ok(theme)
+    }
+
+    /// Returns the files to copy with the output, with the path they must be
+    /// copied to (relative to the output) and their actual path in `source`
+    pub fn get_files(&self, source: &dyn BookSource) -> Result<Vec<(String, PathBuf)>> {
+        let dir = match self.dir {
+            Some(ref dir) => dir,
+            None => return Ok(vec![]),
+        };
+        let files = resource_handler::get_files(&self.files, &dir.to_string_lossy(), source)?;
+        Ok(files
+            .into_iter()
+            .map(|file| {
+                let path = dir.join(&file);
+                (file.replace('\\', "/"), path)
+            })
+            .collect())
+    }
+
+    /// Writes the templates currently used by a book to a directory, with a
+    /// manifest, so it can be used as a starting point for a new theme
+    ///
+    /// Returns the list of files that were written.
+    pub fn export<P: AsRef<Path>>(book: &Book, dir: P) -> Result<Vec<PathBuf>> {
+        let dir = dir.as_ref();
+        let manifest = dir.join(MANIFEST);
+        if fs::metadata(&manifest).is_ok() {
+            return Err(Error::default(
+                Source::empty(),
+                t!("theme.exists", file = manifest.display()),
+            ));
+        }
+        let write = |path: &Path, content: &str| {
+            fs::write(path, content).map_err(|err| {
+                Error::default(
+                    Source::empty(),
+                    t!("theme.write", file = path.display(), error = err),
+                )
+            })
+        };
+        fs::create_dir_all(dir).map_err(|err| {
+            Error::default(
+                Source::empty(),
+                t!("theme.write", file = dir.display(), error = err),
+            )
+        })?;
+
+        let name = dir
+            .canonicalize()
+            .ok()
+            .and_then(|d| d.file_name().map(|n| n.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| String::from("theme"));
+        let mut content = format!("name: {name}\ndescription: \"\"\n# files: [fonts/, images/]\n\n");
+        let mut written = vec![];
+        for template in BookOptions::templates() {
+            // Not all template options have a default content
+            if let Ok(tpl) = book.get_template(template) {
+                let path = dir.join(template);
+                write(&path, &tpl)?;
+                written.push(path);
+                content.push_str(&format!("{template}: {template}\n"));
+            }
+        }
+        write(&manifest, &content)?;
+        written.push(manifest);
+        Ok(written)
+    }
+}
@@ -15,7 +15,10 @@
 // You should have received a copy of the GNU Lesser General Public License
 // along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
 
-#[derive(Debug, Clone, PartialEq)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "t", content = "c")]
 /// The inner type for an annotation.
 ///
 /// This Enum might grow additional variants, so library users should
@@ -26,13 +29,83 @@ pub enum Data {
     Repetition(String),
 }
 
+/// A condition on the output format or on the edition, used for
+/// conditional content and chapters.
+///
+/// It is written `only=pdf,tex` (content is only rendered for these formats
+/// or editions) or `unless=epub` (content is rendered for all formats or
+/// editions except these ones).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Condition {
+    /// `true` for `only=...`, `false` for `unless=...`
+    pub only: bool,
+    /// The list of format keys (e.g. `pdf`, `html.dir`) and edition names
+    pub keys: Vec<String>,
+}
+
+impl Condition {
+    /// Parses a condition, with or without surrounding braces.
+    ///
+    /// Returns `None` if the string is not a valid condition.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crowbook::Condition;
+    /// let condition = Condition::parse("{only=pdf, tex}").unwrap();
+    /// assert!(condition.only);
+    /// assert_eq!(condition.keys, vec!["pdf", "tex"]);
+    /// assert!(Condition::parse("{.class}").is_none());
+    /// ```
+    pub fn parse(s: &str) -> Option<Condition> {
+        let s = s.trim();
+        let s = s
+            .strip_prefix('{')
+            .and_then(|s| s.strip_suffix('}'))
+            .unwrap_or(s);
+        let (key, value) = s.split_once('=')?;
+        let only = match key.trim() {
+            "only" => true,
+            "unless" => false,
+            _ => return None,
+        };
+        let keys: Vec<String> = value
+            .trim()
+            .trim_matches('"')
+            .split(',')
+            .map(|k| k.trim())
+            .filter(|k| !k.is_empty())
+            .map(String::from)
+            .collect();
+        if keys.is_empty() || keys.iter().any(|k| k.contains(char::is_whitespace)) {
+            return None;
+        }
+        Some(Condition { only, keys })
+    }
+
+    /// Returns `true` if content with this condition must be rendered for
+    /// this format and edition.
+    pub fn is_met(&self, format: &str, edition: Option<&str>) -> bool {
+        let matches = self
+            .keys
+            .iter()
+            .any(|k| k == format || Some(k.as_str()) == edition);
+        matches == self.only
+    }
+}
+
 /// A single token representing a Markdown element.
 ///
 /// A Markdown document is, thus, a Vec of `Token`s.
 ///
 /// This Enum might grow additional variants, so library users should
 /// **not** rely on exhaustive matching.
-#[derive(Debug, PartialEq, Clone)]
+///
+/// Tokens are serialized (e.g. for external filters) as `{"t": "Variant", "c": content}`,
+/// where `c` is omitted for variants without content and is an array
+/// for variants with more than one field.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(tag = "t", content = "c")]
 #[non_exhaustive]
 pub enum Token {
     /// The most simple element, containing a String
@@ -108,6 +181,18 @@ pub enum Token {
 
     /// An annotation inserted by crowbook for e.g. grammar checking
     Annotation(Data, Vec<Token>),
+
+    /// Content that is only rendered if its condition is met, either
+    /// a fenced block (`::: {only=pdf}`) or an inline span (`[text]{unless=epub}`)
+    Conditional(Condition, Vec<Token>),
+
+    /// A variable (`{{version}}`), replaced by the value of the corresponding metadata
+    /// when the book is rendered
+    Variable(String),
+
+    /// A page break of the print edition (`<!-- pagebreak 12 -->`), with the number of
+    /// the page that starts
+    PageBreak(String),
 }
 
 use Token::*;
@@ -122,6 +207,8 @@ impl Token {
             | Str(_)
             | CodeBlock(_, _)
             | Code(_)
+            | Variable(_)
+            | PageBreak(_)
             | FootnoteReference(_) => None,
 
             Paragraph(ref v)
@@ -148,6 +235,7 @@ impl Token {
             | StandaloneImage(_, _, ref v)
             | Strikethrough(ref v)
             | TaskItem(_, ref v)
+            | Conditional(_, ref v)
             | Annotation(_, ref v) => Some(v),
         }
     }
@@ -161,10 +249,13 @@ impl Token {
             | Str(_)
             | CodeBlock(_, _)
             | Code(_)
+            | Variable(_)
+            | PageBreak(_)
             | FootnoteReference(_) => None,
 
             Paragraph(ref mut v)
             | Annotation(_, ref mut v)
+            | Conditional(_, ref mut v)
             | Header(_, ref mut v)
             | Emphasis(ref mut v)
             | Strong(ref mut v)
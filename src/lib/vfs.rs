@@ -0,0 +1,264 @@
+// Copyright (C) 2016-2023 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Sources the files of a book (chapters, templates, images, ...) can be read from
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+#[cfg(feature = "archive")]
+use crate::error::{Error, Result, Source};
+#[cfg(feature = "archive")]
+use rust_i18n::t;
+#[cfg(feature = "archive")]
+use std::io::{Read, Seek};
+use walkdir::WalkDir;
+
+/// Where the files of a book are read from.
+///
+/// This is used for the book configuration file, chapters, imported files, templates,
+/// images, additional resources and themes. Files that Crowbook generates itself (e.g. in
+/// `crowbook.cache_dir`) are always read from the file system.
+pub trait BookSource: fmt::Debug + Send + Sync {
+    /// Reads the whole content of a file
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Returns true if `path` is a file
+    fn is_file(&self, path: &Path) -> bool;
+
+    /// Returns true if `path` is a directory
+    fn is_dir(&self, path: &Path) -> bool;
+
+    /// Returns the files in a directory and its subdirectories
+    fn files_in(&self, dir: &Path) -> io::Result<Vec<PathBuf>>;
+
+    /// Returns the files in a directory, at most `depth` levels below it
+    /// (`1` only returns the files directly in `dir`)
+    fn files_up_to(&self, dir: &Path, depth: usize) -> io::Result<Vec<PathBuf>> {
+        let files = self.files_in(dir)?;
+        Ok(files
+            .into_iter()
+            .filter(|p| {
+                p.strip_prefix(dir)
+                    .map(|rel| rel.components().count() <= depth)
+                    .unwrap_or(false)
+            })
+            .collect())
+    }
+
+    /// Reads the whole content of a file as UTF-8
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Reads files from the file system (this is the default source)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileSystem;
+
+impl BookSource for FileSystem {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        let path = if path.as_os_str().is_empty() { Path::new(".") } else { path };
+        path.is_dir()
+    }
+
+    fn files_in(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        self.files_up_to(dir, usize::MAX)
+    }
+
+    fn files_up_to(&self, dir: &Path, depth: usize) -> io::Result<Vec<PathBuf>> {
+        let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+        fs::metadata(dir)?;
+        Ok(WalkDir::new(dir)
+            .follow_links(true)
+            .max_depth(depth)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| e.into_path())
+            .collect())
+    }
+}
+
+/// Files stored in memory, so a book can be built from strings and byte
+/// buffers, or from an archive without extracting it.
+///
+/// Paths are normalized, so `./chapters/../intro.md` and `intro.md` are the same file.
+///
+/// # Example
+///
+/// ```
+/// use crowbook::{Book, MemorySource};
+///
+/// let mut files = MemorySource::new();
+/// files
+///     .add_file("book.book", "title: Foo\n\n+ intro.md\n")
+///     .add_file("intro.md", "# Introduction\n\nSome text.");
+///
+/// let mut book = Book::new();
+/// book.set_book_source(files);
+/// book.load_file("book.book").unwrap();
+/// let mut html = vec![];
+/// book.render_format_to("html", &mut html).unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MemorySource {
+    files: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl MemorySource {
+    /// Creates a new, empty source
+    pub fn new() -> MemorySource {
+        MemorySource::default()
+    }
+
+    /// Adds a file, replacing it if it was already present
+    pub fn add_file<P: AsRef<Path>, C: Into<Vec<u8>>>(&mut self, path: P, content: C) -> &mut Self {
+        self.files.insert(normalize(path.as_ref()), content.into());
+        self
+    }
+
+    /// Returns the paths of all files
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.files.keys().map(|p| p.as_path())
+    }
+
+    /// Reads all the files of a zip archive
+    #[cfg(feature = "archive")]
+    pub fn from_zip<R: Read + Seek>(reader: R) -> Result<MemorySource> {
+        let error = |e: &dyn fmt::Display| {
+            Error::default(Source::empty(), t!("vfs.archive", error = e))
+        };
+        let mut archive = zip::ZipArchive::new(reader).map_err(|e| error(&e))?;
+        let mut source = MemorySource::new();
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i).map_err(|e| error(&e))?;
+            if !file.is_file() {
+                continue;
+            }
+            let path = file.enclosed_name().ok_or_else(|| error(&file.name()))?;
+            let mut content = vec![];
+            file.read_to_end(&mut content).map_err(|e| error(&e))?;
+            source.add_file(path, content);
+        }
+        Ok(source)
+    }
+
+    /// Reads all the files of a tar archive
+    #[cfg(feature = "archive")]
+    pub fn from_tar<R: Read>(reader: R) -> Result<MemorySource> {
+        let error = |e: &dyn fmt::Display| {
+            Error::default(Source::empty(), t!("vfs.archive", error = e))
+        };
+        let mut archive = tar::Archive::new(reader);
+        let mut source = MemorySource::new();
+        for entry in archive.entries().map_err(|e| error(&e))? {
+            let mut entry = entry.map_err(|e| error(&e))?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let path = entry.path().map_err(|e| error(&e))?.into_owned();
+            if path.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+                return Err(error(&path.display()));
+            }
+            let mut content = vec![];
+            entry.read_to_end(&mut content).map_err(|e| error(&e))?;
+            source.add_file(path, content);
+        }
+        Ok(source)
+    }
+
+    /// Reads all the files of a gzip-compressed tar archive (`.tar.gz` or `.tgz`)
+    #[cfg(feature = "archive")]
+    pub fn from_tar_gz<R: Read>(reader: R) -> Result<MemorySource> {
+        MemorySource::from_tar(flate2::read::GzDecoder::new(reader))
+    }
+}
+
+impl BookSource for MemorySource {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.files
+            .get(&normalize(path))
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, path.display().to_string()))
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.files.contains_key(&normalize(path))
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        let dir = normalize(path);
+        self.files.keys().any(|p| p != &dir && p.starts_with(&dir))
+    }
+
+    fn files_in(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let dir = normalize(dir);
+        let files: Vec<PathBuf> = self
+            .files
+            .keys()
+            .filter(|p| *p != &dir && p.starts_with(&dir))
+            .cloned()
+            .collect();
+        if files.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, dir.display().to_string()));
+        }
+        Ok(files)
+    }
+
+    fn files_up_to(&self, dir: &Path, depth: usize) -> io::Result<Vec<PathBuf>> {
+        let dir = normalize(dir);
+        let files = self.files_in(&dir)?;
+        Ok(files
+            .into_iter()
+            .filter(|p| {
+                p.strip_prefix(&dir)
+                    .map(|rel| rel.components().count() <= depth)
+                    .unwrap_or(false)
+            })
+            .collect())
+    }
+}
+
+/// Removes `.` and resolves `..` components of a path, without accessing the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !result.pop() {
+                    result.push("..");
+                }
+            }
+            c => result.push(c),
+        }
+    }
+    result
+}
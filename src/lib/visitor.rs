@@ -0,0 +1,188 @@
+// Copyright (C) 2016-2023 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Traversal of the AST, so library users don't have to match every `Token` variant
+
+use crate::chapter::Chapter;
+use crate::token::Token;
+
+use std::mem;
+
+/// Read-only traversal of the AST.
+///
+/// Every method has a default implementation that visits the children of the
+/// element, so an implementation only needs to override the ones it is
+/// interested in. An overriding method can call the corresponding `walk_*`
+/// method to keep visiting the children.
+///
+/// # Example
+///
+/// ```
+/// use crowbook::{Token, Visitor};
+///
+/// /// Counts the words of a text
+/// struct Words(usize);
+///
+/// impl Visitor for Words {
+///     fn visit_str(&mut self, text: &str) {
+///         self.0 += text.split_whitespace().count();
+///     }
+/// }
+///
+/// let tokens = vec![Token::Paragraph(vec![
+///     Token::Str("Some ".into()),
+///     Token::Emphasis(vec![Token::Str("short text".into())]),
+/// ])];
+/// let mut words = Words(0);
+/// words.visit_tokens(&tokens);
+/// assert_eq!(words.0, 3);
+/// ```
+pub trait Visitor {
+    /// Visits a chapter; by default, visits its content
+    fn visit_chapter(&mut self, chapter: &Chapter) {
+        self.visit_tokens(&chapter.content);
+    }
+
+    /// Visits a list of tokens; by default, visits each of them
+    fn visit_tokens(&mut self, tokens: &[Token]) {
+        for token in tokens {
+            self.visit_token(token);
+        }
+    }
+
+    /// Visits a token; by default, calls `walk_token`
+    fn visit_token(&mut self, token: &Token) {
+        self.walk_token(token);
+    }
+
+    /// Visits the text of a `Str` token; does nothing by default
+    fn visit_str(&mut self, _text: &str) {}
+
+    /// Visits the content of a token: the text of a `Str` token, or the inner
+    /// tokens of a container
+    fn walk_token(&mut self, token: &Token) {
+        if let Token::Str(ref text) = *token {
+            self.visit_str(text);
+        } else if let Some(inner) = token.inner() {
+            self.visit_tokens(inner);
+        }
+    }
+}
+
+/// In-place modification of the AST.
+///
+/// Like `Visitor`, except that tokens can be modified. To replace a token by
+/// several ones or remove it, use `Fold` instead.
+pub trait VisitorMut {
+    /// Visits a chapter; by default, visits its content
+    fn visit_chapter_mut(&mut self, chapter: &mut Chapter) {
+        self.visit_tokens_mut(&mut chapter.content);
+    }
+
+    /// Visits a list of tokens; by default, visits each of them
+    fn visit_tokens_mut(&mut self, tokens: &mut Vec<Token>) {
+        for token in tokens {
+            self.visit_token_mut(token);
+        }
+    }
+
+    /// Visits a token; by default, calls `walk_token_mut`
+    fn visit_token_mut(&mut self, token: &mut Token) {
+        self.walk_token_mut(token);
+    }
+
+    /// Visits the text of a `Str` token; does nothing by default
+    fn visit_str_mut(&mut self, _text: &mut String) {}
+
+    /// Visits the content of a token: the text of a `Str` token, or the inner
+    /// tokens of a container
+    fn walk_token_mut(&mut self, token: &mut Token) {
+        if let Token::Str(ref mut text) = *token {
+            self.visit_str_mut(text);
+        } else if let Some(inner) = token.inner_mut() {
+            self.visit_tokens_mut(inner);
+        }
+    }
+}
+
+/// Transformation of the AST, where each token is replaced by a (possibly empty)
+/// list of tokens.
+///
+/// # Example
+///
+/// ```
+/// use crowbook::{Fold, Token};
+///
+/// /// Removes horizontal rules and makes strong text emphasized instead
+/// struct Simplify;
+///
+/// impl Fold for Simplify {
+///     fn fold_token(&mut self, token: Token) -> Vec<Token> {
+///         match token {
+///             Token::Rule => vec![],
+///             Token::Strong(inner) => self.walk_token(Token::Emphasis(inner)),
+///             token => self.walk_token(token),
+///         }
+///     }
+/// }
+///
+/// let tokens = Simplify.fold_tokens(vec![
+///     Token::Rule,
+///     Token::Paragraph(vec![Token::Strong(vec![Token::Str("Hi".into())])]),
+/// ]);
+/// assert_eq!(tokens, vec![
+///     Token::Paragraph(vec![Token::Emphasis(vec![Token::Str("Hi".into())])]),
+/// ]);
+/// ```
+pub trait Fold {
+    /// Transforms a chapter; by default, transforms its content
+    fn fold_chapter(&mut self, mut chapter: Chapter) -> Chapter {
+        chapter.content = self.fold_tokens(chapter.content);
+        chapter
+    }
+
+    /// Transforms a list of tokens; by default, transforms each of them
+    fn fold_tokens(&mut self, tokens: Vec<Token>) -> Vec<Token> {
+        let mut result = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            result.extend(self.fold_token(token));
+        }
+        result
+    }
+
+    /// Transforms a token; by default, calls `walk_token`
+    fn fold_token(&mut self, token: Token) -> Vec<Token> {
+        self.walk_token(token)
+    }
+
+    /// Transforms the text of a `Str` token; returns it unchanged by default
+    fn fold_str(&mut self, text: String) -> Vec<Token> {
+        vec![Token::Str(text)]
+    }
+
+    /// Transforms the content of a token: the text of a `Str` token, or the
+    /// inner tokens of a container
+    fn walk_token(&mut self, mut token: Token) -> Vec<Token> {
+        if let Token::Str(text) = token {
+            return self.fold_str(text);
+        }
+        if let Some(inner) = token.inner_mut() {
+            *inner = self.fold_tokens(mem::take(inner));
+        }
+        vec![token]
+    }
+}
@@ -16,19 +16,24 @@
 // along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::error::{Error, Result};
+use crate::progress::CancellationToken;
+use crate::sink::OutputSink;
 
 use std::fs::{self, DirBuilder, File};
 use std::io;
 use std::io::Write;
 use std::ops::Drop;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
 use rust_i18n::t;
 
 /// Struct used to create zip (using filesystem and zip command)
 pub struct Zipper {
     args: Vec<String>,
     path: PathBuf,
+    cancellation: CancellationToken,
 }
 
 impl Zipper {
@@ -54,11 +59,18 @@ impl Zipper {
         Ok(Zipper {
             args: vec![],
             path: zipper_path,
+            cancellation: CancellationToken::new(),
         })
     }
 
+    /// Sets the token that stops writing files and running commands when cancelled
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.cancellation = token;
+    }
+
     /// writes a content to a temporary file
     pub fn write<P: AsRef<Path>>(&mut self, path: P, content: &[u8], add_args: bool) -> Result<()> {
+        self.cancellation.check()?;
         let path = path.as_ref();
         let file = format!("{}", path.display());
         if path.starts_with("..") || path.is_absolute() {
@@ -109,6 +121,7 @@ impl Zipper {
         in_file: &str,
         out: &mut dyn Write,
     ) -> Result<String> {
+        self.cancellation.check()?;
         let res_output = command.output().map_err(|e| {
             debug!(
                 "{}",
@@ -168,10 +181,11 @@ impl Zipper {
         // first pass
         let mut command = Command::new(command_name);
         command.current_dir(&self.path).arg(tex_file);
-        let _ = command.output();
+        self.run_pass(&mut command)?;
 
         // second pass
-        let _ = command.output();
+        self.run_pass(&mut command)?;
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
 
         // third pass
         // let mut command = Command::new(command_name);
@@ -179,6 +193,40 @@ impl Zipper {
         // command.arg(tex_file);
         self.run_command(command, command_name, "result.pdf", pdf_file)
     }
+
+    /// Runs a command whose output is not needed, killing it if cancelled
+    ///
+    /// Failing to run the command is not an error here, since it will be reported
+    /// by `run_command`.
+    fn run_pass(&self, command: &mut Command) -> Result<()> {
+        self.cancellation.check()?;
+        let child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(_) => return Ok(()),
+        };
+        loop {
+            match child.try_wait() {
+                Ok(None) if self.cancellation.is_cancelled() => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(Error::cancelled());
+                }
+                Ok(None) => thread::sleep(Duration::from_millis(100)),
+                _ => return Ok(()),
+            }
+        }
+    }
+}
+
+impl OutputSink for Zipper {
+    fn write_file(&mut self, path: &str, content: &[u8]) -> Result<()> {
+        self.write(path, content, true)
+    }
 }
 
 impl Drop for Zipper {
@@ -0,0 +1,72 @@
+// Search in the index generated by Crowbook (search_index.js), using the
+// same splitting and stemming rules.
+function crowbookStem(word, index) {
+    for (var i = 0; i < index.suffixes.length; i++) {
+        var suffix = index.suffixes[i];
+        if (word.length > suffix.length
+            && word.substr(word.length - suffix.length) == suffix
+            && Array.from(word).length - Array.from(suffix).length >= index.min_stem) {
+            return word.substr(0, word.length - suffix.length);
+        }
+    }
+    return word;
+}
+
+function crowbookSearch(query, index) {
+    var words = query.toLowerCase().split(/[^\p{L}\p{N}]+/u);
+    var scores = null;
+    for (var i = 0; i < words.length; i++) {
+        if (Array.from(words[i]).length < index.min_length) {
+            continue;
+        }
+        var entries = index.terms[crowbookStem(words[i], index)] || [];
+        var found = {};
+        for (var j = 0; j < entries.length; j += 2) {
+            var doc = entries[j];
+            // Only keep chapters that contain all the words
+            if (scores == null || doc in scores) {
+                found[doc] = (scores == null ? 0 : scores[doc]) + entries[j + 1];
+            }
+        }
+        scores = found;
+    }
+    var results = [];
+    for (var doc in scores) {
+        results.push([parseInt(doc), scores[doc]]);
+    }
+    results.sort(function(a, b) { return b[1] - a[1] || a[0] - b[0]; });
+    return results;
+}
+
+function crowbookShowResults() {
+    var params = new URLSearchParams(window.location.search);
+    var query = params.get("q") || "";
+    var input = document.getElementById("search-input");
+    if (input) {
+        input.value = query;
+    }
+    var container = document.getElementById("search-results");
+    if (query == "") {
+        return;
+    }
+    var results = crowbookSearch(query, crowbookSearchIndex);
+    if (results.length == 0) {
+        var p = document.createElement("p");
+        p.textContent = container.getAttribute("data-none");
+        container.appendChild(p);
+        return;
+    }
+    var list = document.createElement("ol");
+    for (var i = 0; i < results.length; i++) {
+        var doc = crowbookSearchIndex.docs[results[i][0]];
+        var item = document.createElement("li");
+        var link = document.createElement("a");
+        link.href = doc[0];
+        link.textContent = doc[1];
+        item.appendChild(link);
+        list.appendChild(item);
+    }
+    container.appendChild(list);
+}
+
+window.addEventListener("load", crowbookShowResults);